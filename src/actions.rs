@@ -2,63 +2,246 @@ use url::Url;
 
 use flowclib::compiler::compile;
 use flowclib::compiler::loader;
-use flowclib::compiler::compile_wasm;
 use flowclib::generator::generate;
-use flowclib::model::process::Process::FlowProcess;
+use flowclib::deserializers::deserializer_helper;
+use flowclib::model::flow::Flow;
+use flowclib::model::process::Process;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
 use flowrlib::coordinator::{Submission, Coordinator};
-use flowrstructs::manifest::{DEFAULT_MANIFEST_FILENAME, Manifest};
+use flowrstructs::manifest::Manifest;
 use provider::content::provider::{MetaProvider, Provider};
 
-use crate::{log_error, log_warn};
-use crate::build_ui::UICONTEXT;
+use crate::{compile_errors, compile_options, completion, definition, export, flow_edit, implementations, layout_file, log_error, log_warn,
+            refactor, rename, watch};
+use crate::build_ui::{UICONTEXT, widgets};
+use crate::compile_options::CompileOptions;
+use crate::completion::CompletionKind;
+use crate::flow_edit::{FlowEdit, Fragment};
+use crate::history::{Change, FileChange, MovedPositions};
+use crate::implementations::Cancel;
+use crate::layout_file::Positions;
 use crate::ide_runtime_client::IdeRuntimeClient;
+use crate::rename::Renamed;
+use crate::stdin::STDIN;
+use crate::syntax::{Problem, SourceFormat};
+use crate::templates::Template;
 use crate::ui_context::UiContext;
+use crate::watch::{Changed, Files, Watched};
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use lazy_static::lazy_static;
 use simpath::Simpath;
 
+// Parts of the progress of a compile taken by compiling the flow and by building implementations,
+// the rest is creating the manifest
+const COMPILE_PROGRESS: f64 = 0.1;
+const BUILD_PROGRESS: f64 = 0.8;
+
+// How often the files of the flow are checked for changes when recompiling it on changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    // The compile running, if there is one, so that it can be cancelled
+    static ref COMPILING: Mutex<Option<Cancel>> = Mutex::new(None);
+    // Directories to look for libraries in from the compile options of the flow loaded, as well
+    // as those in FLOW_LIB_PATH
+    static ref LIB_DIRS: Mutex<Vec<String>> = Mutex::new(vec!());
+}
+
+// Changed each time watching the flow's files is turned on or off, so a thread watching them
+// can tell when it should stop
+static WATCH_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
 /// Background action that compiles a flow on a thread and then updates the UI with the resulting
-/// compiled flow manifest
+/// compiled flow manifest. Only one flow is compiled at a time.
 pub fn compile_flow() {
+    if !start_compile() {
+        UiContext::message("The flow is already being compiled");
+    }
+}
+
+fn is_compiling() -> bool {
+    COMPILING.lock().map_or(true, |compiling| compiling.is_some())
+}
+
+// Start compiling the loaded flow on a thread, unless it is already being compiled
+fn start_compile() -> bool {
+    let cancel = Cancel::default();
+    match COMPILING.lock() {
+        Ok(mut compiling) if compiling.is_none() => *compiling = Some(cancel.clone()),
+        _ => return false
+    }
+
     std::thread::spawn(move || {
-        match UICONTEXT.try_lock() {
-            Ok(ref mut context) => {
-                match context.flow {
-                    Some(ref flow) => {
-                        let flow_clone = flow.clone();
-                        let flow_url = flow.source_url.clone();
-                        UiContext::message("Compiling flow");
-                        match compile::compile(&flow_clone) {
-                            Ok(mut tables) => {
-                                UiContext::message("Compiling provided implementations");
-                                match compile_wasm::compile_supplied_implementations(&mut tables, false) {
-                                    Ok(result) => {
-                                        UiContext::message(&result);
-                                        let mut manifest_url = Url::parse(&flow_url).unwrap();
-                                        manifest_url = manifest_url.join(&format!("{}.json", DEFAULT_MANIFEST_FILENAME)).unwrap();
-                                        UiContext::message(&format!("Creating flow manifest at: {}", manifest_url.to_string()));
-                                        match generate::create_manifest(&flow, true, &manifest_url.to_string(), &tables) {
-                                            Ok(manifest) => context.set_manifest(Some(manifest_url.to_string()), Some(manifest)),
-                                            Err(e) => {
-                                                UiContext::ui_error(&e.to_string());
-                                                UiContext::message("Creation of flow manifest failed");
-                                            }
-                                        }
-                                    }
-                                    Err(e) => UiContext::ui_error(&e.to_string())
-                                }
-                            }
-                            Err(e) => UiContext::ui_error(&e.to_string())
-                        }
-                    }
-                    _ => {
-                        UiContext::ui_error("No flow loaded to compile");
-                        UiContext::message("Flow compilation failed");
-                    }
+        compile_loaded_flow(&cancel);
+        if let Ok(mut compiling) = COMPILING.lock() {
+            *compiling = None;
+        }
+        UiContext::compile_progress(None);
+    });
+    true
+}
+
+/// Background action that, while 'on', watches the files the loaded flow is made from and
+/// recompiles it when any of them change, reloading it first if a definition changed. Supplied
+/// implementations that have not changed are not built again.
+pub fn watch_flow(on: bool) {
+    let generation = WATCH_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    if !on {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let mut watched = Watched::default();
+        // A change waiting for the compile running when it was found to finish
+        let mut pending: Option<Changed> = None;
+        while WATCH_GENERATION.load(Ordering::SeqCst) == generation {
+            std::thread::sleep(WATCH_INTERVAL);
+
+            // The files are found on a copy of the flow, so the uicontext is not kept busy while
+            // the directories of the implementations are searched
+            let flow = match UICONTEXT.try_lock() {
+                Ok(context) => context.flow.clone(),
+                // Busy, check again later
+                _ => continue
+            };
+            let flow = match flow {
+                Some(flow) => flow,
+                None => continue
+            };
+            let (root_url, files) = (flow.source_url.clone(), Files::of(&flow));
+
+            let changed = watched.changed(&root_url, &files);
+            if changed > pending {
+                pending = changed;
+            }
+            if pending.is_none() || is_compiling() {
+                continue;
+            }
+
+            if pending == Some(Changed::Definitions) {
+                if let Err(message) = load_flow(&root_url) {
+                    UiContext::message(&format!("Flow changed but could not be reloaded: {}", message));
+                    pending = None;
+                    continue;
                 }
+                pending = Some(Changed::Implementations);
             }
-            _ => log_error("Could not access ui context")
+            if start_compile() {
+                pending = None;
+            }
+        }
+    });
+}
+
+/// Cancel the compile that is running, stopping any implementation it is building. The manifest
+/// is not written if the compile is cancelled.
+pub fn cancel_compile() {
+    if let Ok(compiling) = COMPILING.lock() {
+        if let Some(cancel) = compiling.as_ref() {
+            cancel.cancel();
+            UiContext::message("Cancelling the compile");
+        }
+    }
+}
+
+fn compile_loaded_flow(cancel: &Cancel) {
+    let flow = match UICONTEXT.try_lock() {
+        Ok(context) => context.flow.clone(),
+        _ => {
+            log_error("Could not access ui context");
+            return;
         }
+    };
+    let flow = match flow {
+        Some(flow) => flow,
+        None => {
+            UiContext::ui_error("No flow loaded to compile");
+            UiContext::message("Flow compilation failed");
+            return;
+        }
+    };
+
+    let options = compile_options::read_options(&flow.source_url);
+    UiContext::show_compile_errors(vec!());
+    UiContext::clear_build_output();
+    UiContext::compile_progress(Some((0.0, "Compiling flow".into())));
+    let mut tables = match compile::compile(&flow) {
+        Ok(tables) => tables,
+        Err(e) => return compile_failed(&flow, "Flow compilation failed",
+                                        e.iter().map(|cause| cause.to_string()).collect())
+    };
+
+    let count = implementations::supplied(&tables).len();
+    let built = implementations::build(&mut tables, options.implementations, cancel, &|number, name, directory| {
+        let fraction = COMPILE_PROGRESS + BUILD_PROGRESS * (number - 1) as f64 / count as f64;
+        UiContext::compile_progress(Some((fraction, format!("Building implementation {} of {}: '{}'", number, count, name))));
+        UiContext::build_started(name.to_string(), directory.to_path_buf());
+    }, &|line| UiContext::build_output(line.to_string()));
+    if cancel.is_cancelled() {
+        UiContext::message("Compile cancelled");
+        return;
+    }
+    if let Err(e) = built {
+        return compile_failed(&flow, "Building provided implementations failed", vec!(e));
+    }
+
+    let manifest_url = match options.manifest_url(&flow.source_url) {
+        Ok(manifest_url) => manifest_url.to_string(),
+        Err(e) => return UiContext::ui_error(&e)
+    };
+    UiContext::compile_progress(Some((COMPILE_PROGRESS + BUILD_PROGRESS, "Creating flow manifest".into())));
+    let manifest = match generate::create_manifest(&flow, options.debug_symbols, &manifest_url, &tables) {
+        Ok(manifest) => manifest,
+        Err(e) => return compile_failed(&flow, "Creation of flow manifest failed",
+                                        e.iter().map(|cause| cause.to_string()).collect())
+    };
+    if cancel.is_cancelled() {
+        UiContext::message("Compile cancelled");
+        return;
+    }
+
+    if let Err(e) = write_manifest(&manifest_url, &manifest) {
+        return UiContext::ui_error(&e);
+    }
+    match UICONTEXT.try_lock() {
+        Ok(mut context) => context.set_manifest(Some(manifest_url), Some(manifest)),
+        _ => log_error("Could not access ui context")
+    }
+}
+
+// Write the manifest to a temporary file that is then moved into place, so a manifest is never
+// left partly written
+fn write_manifest(manifest_url: &str, manifest: &Manifest) -> Result<(), String> {
+    let path = Url::parse(manifest_url).ok().and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("Cannot write the manifest to '{}' as it is not a file", manifest_url))?;
+    let contents = serde_json::to_string_pretty(manifest)
+        .map_err(|e| format!("Could not convert the manifest to json: {}", e))?;
+
+    if let Some(output_dir) = path.parent() {
+        fs::create_dir_all(output_dir)
+            .map_err(|e| format!("Could not create the directory '{}' for the manifest: {}", output_dir.display(), e))?;
+    }
+
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, contents).and_then(|_| fs::rename(&temporary, &path)).map_err(|e| {
+        let _ = fs::remove_file(&temporary);
+        format!("Could not write the manifest to '{}': {}", path.display(), e)
+    })
+}
+
+// Show the errors in the 'messages' of an error compiling 'flow' in the compile errors list
+fn compile_failed(flow: &Flow, failure: &str, messages: Vec<String>) {
+    let errors = compile_errors::compile_errors(flow, &messages);
+    UiContext::message(&match errors.len() {
+        1 => format!("{}: 1 error", failure),
+        count => format!("{}: {} errors", failure, count)
     });
+    UiContext::show_compile_errors(errors);
 }
 
 /*
@@ -68,40 +251,623 @@ pub fn compile_flow() {
 
     Using the "FLOW_LIB_PATH" environment variable attempt to locate the library's root folder
     in the file system.
+
+    The library directories in the compile options of the flow loaded are added to it.
  */
 pub fn get_lib_search_path() -> Simpath {
-    // TODO read additional entries from UI
-    let search_path_additions: &[String] = &[];
+    let search_path_additions = LIB_DIRS.lock().map(|lib_dirs| lib_dirs.clone()).unwrap_or_default();
 
-    let lib_search_path = Simpath::new_with_separator("FLOW_LIB_PATH", ',');
+    let mut lib_search_path = Simpath::new_with_separator("FLOW_LIB_PATH", ',');
 
     if env::var("FLOW_LIB_PATH").is_err() && search_path_additions.is_empty() {
         log_warn("'FLOW_LIB_PATH' is not set and no LIB_DIRS supplied, so it is possible libraries referenced will not be found");
     }
 
-    // for addition in search_path_additions {
-    //     lib_search_path.add(addition);
-    //     UIContext::message(&format!("'{}' added to the Library Search Path", addition));
-    // }
+    for addition in &search_path_additions {
+        lib_search_path.add(addition);
+    }
 
     lib_search_path
 }
 
+// Use the library directories in the compile options of the flow defined at 'flow_url' to look
+// for libraries. Relative directories are relative to the flow's directory.
+fn set_lib_dirs(flow_url: &str, options: &CompileOptions) {
+    let flow_dir = compile_options::flow_dir(flow_url);
+    let lib_dirs = options.lib_dirs.iter()
+        .map(|lib_dir| match &flow_dir {
+            Some(flow_dir) if !lib_dir.contains("://") => flow_dir.join(lib_dir).to_string_lossy().to_string(),
+            _ => lib_dir.clone()
+        })
+        .collect();
+    if let Ok(mut dirs) = LIB_DIRS.lock() {
+        *dirs = lib_dirs;
+    }
+}
+
+/// Background action that saves the options for compiling the flow defined at 'flow_url' with
+/// it, and reloads the flow so libraries are found using the library directories in them
+pub fn set_compile_options(flow_url: String, options: CompileOptions) {
+    std::thread::spawn(move || {
+        if let Err(e) = compile_options::write_options(&flow_url, &options) {
+            UiContext::ui_error(&e);
+            return;
+        }
+
+        set_lib_dirs(&flow_url, &options);
+        match load_flow(&flow_url) {
+            Ok(()) => UiContext::message("Compile options saved"),
+            Err(message) => UiContext::message(&format!("Compile options saved, but the flow could not be reloaded: {}",
+                                                        message))
+        }
+    });
+}
+
+/// Background action that writes the definition of a new flow called 'name', created from a
+/// template, in the directory 'dir' and then loads it
+pub fn new_flow(name: String, dir: PathBuf, template: Template) {
+    std::thread::spawn(move || {
+        if name.is_empty() || name.contains(std::path::is_separator) {
+            UiContext::ui_error(&format!("'{}' is not a valid name for a flow", name));
+            return;
+        }
+
+        let path = dir.join(format!("{}.toml", name));
+        if path.exists() {
+            UiContext::ui_error(&format!("A file already exists at '{}'", path.display()));
+            return;
+        }
+
+        if let Err(e) = fs::write(&path, template.definition(&name)) {
+            UiContext::ui_error(&format!("Could not write flow definition to '{}': {}", path.display(), e));
+            return;
+        }
+
+        match Url::from_file_path(&path) {
+            Ok(url) => open_flow(url.to_string()),
+            Err(_) => UiContext::ui_error(&format!("Could not create a Url for '{}'", path.display()))
+        }
+    });
+}
+
 /// Load a flow from 'url' in a background thread and then update the UI with a JSON representation
 /// of it.
 pub fn open_flow(url: String) {
     std::thread::spawn(move || {
-        let provider = &MetaProvider::new(get_lib_search_path()) as &dyn Provider;
+        // Restore the positions of nodes the user moved when the flow was last opened
+        UiContext::set_node_positions(url.clone(), layout_file::read_positions(&url));
 
-        match loader::load(&url, provider) {
-            Ok(FlowProcess(flow)) => {
-                match UICONTEXT.try_lock() {
-                    Ok(mut context) => context.set_flow(Some(flow)),
-                    _ => log_error("Could not get access to uicontext")
+        if let Err(message) = load_flow(&url) {
+            UiContext::ui_error(&message);
+        }
+    });
+}
+
+// Load the flow at 'url' and set it into the ui context
+fn load_flow(url: &str) -> Result<(), String> {
+    set_lib_dirs(url, &compile_options::read_options(url));
+    let provider = &MetaProvider::new(get_lib_search_path()) as &dyn Provider;
+
+    match loader::load(url, provider) {
+        Ok(FlowProcess(flow)) => {
+            match UICONTEXT.try_lock() {
+                Ok(mut context) => {
+                    context.set_flow(Some(flow));
+                    Ok(())
                 }
+                _ => Err(format!("Flow loaded from Url: '{}' could not be shown as the flow is busy, try again once it has finished", url))
             }
-            Ok(_) => UiContext::ui_error(&format!("Process loaded from Url: '{}' was not of type 'Flow'", url)),
-            Err(e) => UiContext::ui_error(&format!("Could not load flow from Url: '{}'. {}", url, e.to_string()))
+        }
+        Ok(_) => Err(format!("Process loaded from Url: '{}' was not of type 'Flow'", url)),
+        Err(e) => Err(format!("Could not load flow from Url: '{}'. {}", url, e.to_string()))
+    }
+}
+
+/// Background action that saves the edited definition of the flow, to the file the flow was loaded
+/// from or to the file at 'url' if one is supplied, and then reloads the flow from there.
+pub fn save_flow(url: Option<String>, contents: String) {
+    std::thread::spawn(move || {
+        let current_url = match UICONTEXT.try_lock() {
+            Ok(context) => context.flow.as_ref().map(|flow| flow.source_url.clone()),
+            _ => {
+                log_error("Could not get access to uicontext");
+                return;
+            }
+        };
+
+        let url = match (url, current_url.clone()) {
+            (Some(url), _) => url,
+            (None, Some(current_url)) => current_url,
+            (None, None) => {
+                UiContext::ui_error("No flow loaded to save");
+                return;
+            }
+        };
+
+        let path = match Url::parse(&url).ok().and_then(|url| url.to_file_path().ok()) {
+            Some(path) => path,
+            None => {
+                UiContext::ui_error(&format!("Cannot save flow to '{}' as it is not a file", url));
+                return;
+            }
+        };
+
+        // The contents are written as-is so the file must be of the same format
+        let extension = |url: &str| Path::new(url).extension().map(|e| e.to_string_lossy().to_lowercase());
+        if let Ok(context) = UICONTEXT.try_lock() {
+            if let Some(ref flow) = context.flow {
+                if extension(&flow.source_url) != extension(&url) {
+                    UiContext::ui_error(&format!("Cannot save the flow to '{}' as it would change the format \
+                                                 of the definition", path.display()));
+                    return;
+                }
+            }
+        }
+
+        // Saving over the definition of the flow being edited can be undone
+        let before = if current_url.as_ref() == Some(&url) { fs::read_to_string(&path).ok() } else { None };
+
+        if let Err(e) = fs::write(&path, &contents) {
+            UiContext::ui_error(&format!("Could not save flow to '{}': {}", path.display(), e));
+            return;
+        }
+
+        if let Some(before) = before {
+            let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            record_change(Change::single(format!("Edit of '{}'", file_name), url.clone(), before, contents));
+        }
+
+        // The problems that stop it loading are underlined in the editor, so there is no need
+        // to interrupt the user with a dialog
+        match load_flow(&url) {
+            Ok(()) => UiContext::message(&format!("Flow saved to '{}'", path.display())),
+            Err(message) => UiContext::message(&format!("Flow saved to '{}' but not reloaded: {}",
+                                                        path.display(), message))
+        }
+    });
+}
+
+/// Background action that makes an edit to the (sub)flow loaded from 'flow_url', writes the
+/// resulting definition back to the flow's file and then reloads the flow
+pub fn edit_flow(flow_url: String, edit: FlowEdit) {
+    std::thread::spawn(move || {
+        if let Err(e) = make_edit(&flow_url, &edit) {
+            UiContext::ui_error(&e);
+        }
+    });
+}
+
+// The url of the root flow and a copy of the (sub)flow loaded from 'flow_url', to edit
+fn flow_to_edit(flow_url: &str) -> Result<(String, Flow), String> {
+    match UICONTEXT.try_lock() {
+        Ok(context) => {
+            let root = context.flow.as_ref().ok_or("No flow loaded to edit")?;
+            let flow = flow_edit::find_flow(root, flow_url)
+                .ok_or_else(|| format!("Could not find the flow loaded from '{}' to edit", flow_url))?;
+            Ok((root.source_url.clone(), flow.clone()))
+        }
+        _ => Err("Could not get access to uicontext".into())
+    }
+}
+
+// Make an edit to the (sub)flow loaded from 'flow_url', record it so it can be undone and reload
+// the root flow
+fn make_edit(flow_url: &str, edit: &FlowEdit) -> Result<(), String> {
+    let provider = MetaProvider::new(get_lib_search_path());
+    let (root_url, mut flow) = flow_to_edit(flow_url)?;
+
+    flow_edit::apply(&mut flow, edit, &provider)?;
+    let (before, after) = write_flow_definition(&flow, &provider)?;
+    record_change(Change::single(edit.to_string(), flow_url.to_string(), before, after));
+
+    load_flow(&root_url)?;
+    UiContext::message(&format!("Flow '{}' updated", flow.name));
+    Ok(())
+}
+
+/// Background action that moves the processes with 'aliases' in the (sub)flow loaded from 'flow_url'
+/// into a new flow called 'name', in a file next to it, and references the new flow in their place
+pub fn extract_flow(flow_url: String, aliases: Vec<String>, name: String) {
+    std::thread::spawn(move || {
+        if let Err(e) = extract(&flow_url, &aliases, &name) {
+            UiContext::ui_error(&e);
+        }
+    });
+}
+
+fn extract(flow_url: &str, aliases: &[String], name: &str) -> Result<(), String> {
+    let provider = MetaProvider::new(get_lib_search_path());
+    let (root_url, mut flow) = flow_to_edit(flow_url)?;
+    let extraction = refactor::extract(&flow, aliases, name)?;
+
+    let path = Url::parse(flow_url).ok().and_then(|url| url.to_file_path().ok())
+        .and_then(|flow_path| flow_path.parent().map(|directory| directory.join(&extraction.file_name)))
+        .ok_or_else(|| format!("Cannot extract a sub-flow from '{}' as it is not a file", flow_url))?;
+    if path.exists() {
+        return Err(format!("Cannot create the new flow as '{}' already exists", path.display()));
+    }
+    let new_flow_url = Url::from_file_path(&path).map(|url| url.to_string())
+        .map_err(|_| format!("Cannot create the new flow at '{}'", path.display()))?;
+    fs::write(&path, &extraction.definition)
+        .map_err(|e| format!("Could not write the new flow to '{}': {}", path.display(), e))?;
+
+    // Don't leave the new flow behind if it could not be used
+    let (before, after) = flow_edit::apply(&mut flow, &extraction.edit, &provider)
+        .and_then(|_| write_flow_definition(&flow, &provider))
+        .map_err(|e| {
+            let _ = fs::remove_file(&path);
+            e
+        })?;
+
+    // Undoing the extraction deletes the new flow, as well as putting the processes back
+    record_change(Change {
+        description: extraction.edit.to_string(),
+        files: vec!(FileChange { url: flow_url.to_string(), before, after },
+                    FileChange { url: new_flow_url, before: String::new(), after: extraction.definition }),
+        positions: vec!(),
+    });
+
+    load_flow(&root_url)?;
+    UiContext::message(&format!("Flow '{}' updated", flow.name));
+    Ok(())
+}
+
+/// Background action that replaces the sub-flow process 'alias' in the (sub)flow loaded from
+/// 'flow_url' with its contents, once the user has seen and accepted the change to the definition
+pub fn inline_flow(flow_url: String, alias: String) {
+    std::thread::spawn(move || {
+        let change = flow_to_edit(&flow_url)
+            .and_then(|(_, flow)| refactor::inline(&flow, &alias))
+            .and_then(|edit| edit_change(&flow_url, &edit));
+        match change {
+            Ok(change) => UiContext::preview_change(change),
+            Err(e) => UiContext::ui_error(&e)
+        }
+    });
+}
+
+// The change an edit would make to the definition of the (sub)flow loaded from 'flow_url'
+fn edit_change(flow_url: &str, edit: &FlowEdit) -> Result<Change, String> {
+    let provider = MetaProvider::new(get_lib_search_path());
+    let (_, mut flow) = flow_to_edit(flow_url)?;
+    flow_edit::apply(&mut flow, edit, &provider)?;
+    let original = read_definition(flow_url, &provider)?;
+    let changed = definition::write_definition(&flow, &original)?;
+    Ok(Change::single(edit.to_string(), flow_url.to_string(), original, changed))
+}
+
+fn read_definition(url: &str, provider: &dyn Provider) -> Result<String, String> {
+    let contents = provider.get_contents(url)
+        .map_err(|e| format!("Could not read definition '{}': {}", url, e))?;
+    Ok(String::from_utf8_lossy(&contents).to_string())
+}
+
+/// Background action that renames a process, or an input or output, of the (sub)flow loaded from
+/// 'flow_url' to 'name' in all the definitions of the flow that use it, once the user has seen
+/// and accepted the changes to them
+pub fn rename(flow_url: String, renamed: Renamed, name: String) {
+    std::thread::spawn(move || {
+        match rename_change(&flow_url, &renamed, &name) {
+            Ok(change) => UiContext::preview_change(change),
+            Err(e) => UiContext::ui_error(&e)
+        }
+    });
+}
+
+fn rename_change(flow_url: &str, renamed: &Renamed, name: &str) -> Result<Change, String> {
+    let plan = match UICONTEXT.try_lock() {
+        Ok(context) => {
+            let root = context.flow.as_ref().ok_or("No flow loaded to rename in")?;
+            rename::plan(root, flow_url, renamed, name)?
+        }
+        _ => return Err("Could not get access to uicontext".into())
+    };
+
+    let provider = MetaProvider::new(get_lib_search_path());
+    let mut files = vec!();
+    for (url, renames) in plan.definitions {
+        let format = SourceFormat::from_url(&url);
+        let before = read_definition(&url, &provider)?;
+        let mut definition = definition::parse(format, &before)
+            .map_err(|e| format!("Could not read definition '{}': {}", url, e))?;
+        renames.apply(&mut definition);
+        let after = definition::update(format, &before, &definition)?;
+        files.push(FileChange { url, before, after });
+    }
+
+    Ok(Change { description: plan.description, files, positions: plan.positions })
+}
+
+/// Background action that makes a change the user has previewed to the flow's definitions,
+/// records it so it can be undone, and reloads the flow. It is not made if any of the definitions
+/// have changed since the change was worked out.
+pub fn apply_change(change: Change) {
+    std::thread::spawn(move || {
+        let root_url = match UICONTEXT.try_lock() {
+            Ok(context) => context.flow.as_ref().map(|flow| flow.source_url.clone()),
+            _ => None
+        };
+        let root_url = match root_url {
+            Some(root_url) => root_url,
+            None => {
+                UiContext::ui_error("Could not get the flow to change");
+                return;
+            }
+        };
+
+        let expected: Vec<(String, &str)> = change.files.iter()
+            .map(|file| (file.url.clone(), file.before.as_str())).collect();
+        let texts: Vec<(String, &str)> = change.files.iter()
+            .map(|file| (file.url.clone(), file.after.as_str())).collect();
+        if let Err(e) = write_definitions(&expected, &texts) {
+            UiContext::ui_error(&format!("{}, so '{}' was not done", e, change.description));
+            return;
+        }
+
+        let description = change.description.clone();
+        UiContext::move_node_positions(change.positions.clone());
+        record_change(change);
+        match load_flow(&root_url) {
+            Ok(()) => UiContext::message(&format!("Done: {}", description)),
+            Err(message) => UiContext::message(&format!("Done: {}, but the flow could not be reloaded: {}",
+                                                        description, message))
+        }
+    });
+}
+
+/// Copy the processes with 'aliases', and the connections between them, from the (sub)flow loaded
+/// from 'flow_url'. This is done immediately, as the processes are pasted using the flow model as
+/// it is when they are copied.
+pub fn copy_processes(flow_url: &str, aliases: &[String]) -> Result<Fragment, String> {
+    match UICONTEXT.try_lock() {
+        Ok(context) => {
+            let root = context.flow.as_ref().ok_or("No flow loaded to copy from")?;
+            let flow = flow_edit::find_flow(root, flow_url)
+                .ok_or_else(|| format!("Could not find the flow loaded from '{}' to copy from", flow_url))?;
+            flow_edit::copy(flow, aliases)
+        }
+        _ => Err("The flow is busy, try copying again once it has finished".into())
+    }
+}
+
+// Write the definition of 'flow' back to the file it was loaded from, returning the definition
+// before and after
+fn write_flow_definition(flow: &Flow, provider: &dyn Provider) -> Result<(String, String), String> {
+    let path = Url::parse(&flow.source_url).ok().and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("Cannot edit flow '{}' as it was not loaded from a file", flow.name))?;
+    let original = provider.get_contents(&flow.source_url)
+        .map_err(|e| format!("Could not read definition of flow '{}': {}", flow.name, e))?;
+    let original = String::from_utf8_lossy(&original).to_string();
+    let definition = definition::write_definition(flow, &original)?;
+    fs::write(&path, &definition)
+        .map_err(|e| format!("Could not write definition of flow '{}' to '{}': {}", flow.name, path.display(), e))?;
+    Ok((original, definition))
+}
+
+/// Background action that saves the positions of the nodes moved in the graph of the flow at
+/// 'flow_url' next to its definition
+pub fn save_layout(flow_url: String, positions: Positions) {
+    std::thread::spawn(move || {
+        if let Err(e) = layout_file::write_positions(&flow_url, &positions) {
+            UiContext::message(&e);
+        }
+    });
+}
+
+// Record a change made so it can be undone. This waits for the uicontext rather than losing the
+// change if it is busy.
+fn record_change(change: Change) {
+    match UICONTEXT.lock() {
+        Ok(mut context) => context.record_change(change),
+        _ => UiContext::ui_error(&format!("Could not record '{}' so it cannot be undone", change.description))
+    }
+}
+
+// Write the 'texts' of the definitions at the urls, checking first that they all still have the
+// 'expected' text so that changes made outside of the editor are not overwritten
+fn write_definitions(expected: &[(String, &str)], texts: &[(String, &str)]) -> Result<(), String> {
+    let path = |url: &str| Url::parse(url).ok().and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("'{}' is not a file", url));
+
+    // An empty text is a definition that does not exist
+    for (url, text) in expected {
+        let path = path(url)?;
+        let current = if text.is_empty() && !path.exists() { Some(String::new()) } else { fs::read_to_string(&path).ok() };
+        if current.as_deref() != Some(*text) {
+            return Err(format!("'{}' has been changed outside of the editor", path.display()));
+        }
+    }
+
+    for (url, text) in texts {
+        let path = path(url)?;
+        if text.is_empty() {
+            fs::remove_file(&path).map_err(|e| format!("Could not delete '{}': {}", path.display(), e))?;
+        } else {
+            fs::write(&path, text).map_err(|e| format!("Could not write '{}': {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Background action that undoes the last change made to the flow's definitions and reloads it
+pub fn undo() {
+    std::thread::spawn(|| step_history(true));
+}
+
+/// Background action that redoes the last change undone and reloads the flow
+pub fn redo() {
+    std::thread::spawn(|| step_history(false));
+}
+
+// Undo or redo a change by writing back the definitions as they were before or after it. If a file
+// has been changed since by something else the history no longer applies to it, and is cleared.
+// This waits for the uicontext when it is busy, so that a change taken from the history is always
+// put back.
+fn step_history(undo: bool) {
+    let (change, root_url) = match UICONTEXT.lock() {
+        Ok(mut context) => {
+            let root_url = context.flow.as_ref().map(|flow| flow.source_url.clone());
+            // Nothing is taken from the history when there is no flow to put the change back for
+            let change = match root_url {
+                Some(_) if undo => context.history.take_undo(),
+                Some(_) => context.history.take_redo(),
+                None => None
+            };
+            (change, root_url)
+        }
+        _ => {
+            log_error("Could not get access to uicontext");
+            return;
+        }
+    };
+
+    let (change, root_url) = match (change, root_url) {
+        (Some(change), Some(root_url)) => (change, root_url),
+        _ => {
+            UiContext::message(if undo { "Nothing to undo" } else { "Nothing to redo" });
+            return;
+        }
+    };
+
+    let files: Vec<(String, &str)> = change.files.iter()
+        .map(|file| if undo { (file.url.clone(), file.before.as_str()) } else { (file.url.clone(), file.after.as_str()) })
+        .collect();
+    let expected: Vec<(String, &str)> = change.files.iter()
+        .map(|file| if undo { (file.url.clone(), file.after.as_str()) } else { (file.url.clone(), file.before.as_str()) })
+        .collect();
+
+    if let Err(e) = write_definitions(&expected, &files) {
+        UiContext::ui_error(&format!("{}, so the changes made can no longer be undone or redone", e));
+        if let Ok(mut context) = UICONTEXT.lock() {
+            context.clear_history();
+        }
+        return;
+    }
+
+    let action = if undo { "Undid" } else { "Redid" };
+    let description = change.description.clone();
+    let moved = if undo {
+        change.positions.iter().rev().map(MovedPositions::reversed).collect()
+    } else {
+        change.positions.clone()
+    };
+    UiContext::move_node_positions(moved);
+    match UICONTEXT.lock() {
+        Ok(mut context) => context.history_stepped(undo, change),
+        _ => UiContext::ui_error(&format!("Could not record '{}' so it cannot be undone or redone", description))
+    }
+
+    // A definition saved with problems may not load, which the source editor shows
+    match load_flow(&root_url) {
+        Ok(()) => UiContext::message(&format!("{}: {}", action, description)),
+        Err(message) => UiContext::message(&format!("{}: {}, but the flow could not be reloaded: {}",
+                                                    action, description, message))
+    }
+}
+
+/// Background action that parses the text of a flow definition being edited, as it would be when
+/// loaded from 'url', and checks that the processes and routes it references exist. The problems
+/// found are shown in the editor if the text has not changed since ('generation').
+pub fn check_flow_source(url: String, text: String, generation: u64) {
+    std::thread::spawn(move || {
+        let problems = flow_source_problems(&url, &text);
+        UiContext::show_source_problems(generation, problems);
+    });
+}
+
+fn flow_source_problems(url: &str, text: &str) -> Vec<Problem> {
+    let deserializer = match deserializer_helper::get_deserializer::<Process>(url) {
+        Ok(deserializer) => deserializer,
+        Err(e) => return vec!(Problem::at_line_column(text, 1, 1, e.to_string()))
+    };
+
+    let flow = match deserializer.deserialize(text, Some(url)) {
+        Ok(FlowProcess(flow)) => flow,
+        Ok(FunctionProcess(_)) => return vec!(Problem::at_line_column(text, 1, 1,
+            "This is the definition of a function, not a flow".into())),
+        Err(e) => return vec!(Problem::from_error(text, e.to_string()))
+    };
+
+    let mut problems = vec!();
+    // A problem with the value of 'field' in entry 'index' of the list 'key' in the definition,
+    // which is 'found', shown where that first is if the entry cannot be found
+    let format = SourceFormat::from_url(url);
+    let at_entry = |key: &str, index: usize, field: &str, found: &str, message: String| {
+        match definition::find_value(format, text, key, index, field) {
+            Some((start, end)) => Problem::at_range(text, start, end, message),
+            None => Problem::at_text(text, found, message)
+        }
+    };
+
+    // Sources are relative to the flow that references them, as when the flow is loaded
+    let provider = MetaProvider::new(get_lib_search_path());
+    let base_url = Url::parse(url).ok();
+    let extensions = deserializer_helper::get_accepted_extensions();
+    for (index, process_ref) in flow.process_refs.iter().flatten().enumerate() {
+        let found = base_url.as_ref()
+            .and_then(|base| base.join(&process_ref.source).ok())
+            .map(|source_url| provider.resolve_url(source_url.as_str(), "context", extensions));
+        match found {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => problems.push(at_entry("process", index, "source", &process_ref.source,
+                format!("Could not find the source of process '{}': {}", process_ref.alias, e))),
+            None => problems.push(at_entry("process", index, "source", &process_ref.source,
+                format!("'{}' is not a valid source for process '{}'", process_ref.source, process_ref.alias))),
+        }
+    }
+
+    // The first segment of a route is the direction of one of the flow's own IOs or the alias
+    // of one of its processes
+    for (index, connection) in flow.connections.iter().flatten().enumerate() {
+        for (route, field, direction) in &[(connection.from.to_string(), "from", "input"),
+                                           (connection.to.to_string(), "to", "output")] {
+            let first = route.trim_start_matches('/').split('/').next().unwrap_or("");
+            let known = first == *direction || flow.process_refs.iter().flatten()
+                .any(|process_ref| process_ref.alias.to_string() == first);
+            if !known {
+                problems.push(at_entry("connection", index, field, route,
+                    format!("'{}' in route '{}' is not '{}' or the alias of a process", first, route, direction)));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Background action that finds the values that could complete the 'typed' start of a value of
+/// 'kind' in the flow definition being edited. The completions are offered in the editor if the
+/// text has not changed since ('generation').
+pub fn complete_flow_source(url: String, text: String, kind: CompletionKind, typed: String, generation: u64) {
+    std::thread::spawn(move || {
+        let loaded = match UICONTEXT.try_lock() {
+            Ok(context) => context.flow.clone(),
+            _ => None
+        };
+
+        let completions = completion::completions(&url, &text, kind, &typed, loaded.as_ref(),
+                                                  get_lib_search_path());
+        if completions.is_empty() {
+            UiContext::message(&format!("No completions found for '{}'", typed));
+        }
+        UiContext::show_completions(generation, typed, completions);
+    });
+}
+
+/// Background action that draws the graph of the (sub)flow shown in the graph view, with its nodes
+/// where they are shown, and writes it to the file at 'path', in the format (SVG, PNG or Graphviz
+/// DOT) indicated by the file's extension
+pub fn export_graph(path: String) {
+    widgets::do_in_gtk_eventloop(move |refs| {
+        match refs.flow_graph_view().shown_graph() {
+            Some(graph) => {
+                std::thread::spawn(move || {
+                    match export::export_graph(&graph, Path::new(&path)) {
+                        Ok(()) => UiContext::message(&format!("Flow graph exported to '{}'", path)),
+                        Err(e) => UiContext::ui_error(&format!("Could not export flow graph: {}", e))
+                    }
+                });
+            }
+            None => UiContext::ui_error("No flow loaded to export")
         }
     });
 }
@@ -125,13 +891,30 @@ pub fn open_manifest(url: String) {
 }
 
 /// Background action that executes a compiled flow manifest on a thread passing the supplied array
-/// of arguments to the runtime functions for the flow to use.
+/// of arguments to the runtime functions for the flow to use, and the contents of 'stdin_file', if
+/// there is one, as its STDIN. Otherwise STDIN is entered by the user while it runs.
 /// This may result in output to stdout, stderr or other runtime functions that will be reflected on the UI.
-pub fn run_manifest(manifest_url: String, args: Vec<String>) {
+pub fn run_manifest(manifest_url: String, args: Vec<String>, stdin_file: Option<PathBuf>) {
     std::thread::spawn(move || {
+        // Read all of the input first, so the flow is not run if it cannot be read
+        let stdin_contents = match stdin_file.as_ref().map(fs::read_to_string) {
+            Some(Err(e)) => {
+                let path = stdin_file.unwrap_or_default();
+                UiContext::ui_error(&format!("Could not read STDIN from '{}': {}", path.display(), e));
+                return;
+            }
+            Some(Ok(contents)) => Some(contents),
+            None => None
+        };
+
         match Coordinator::server(1, get_lib_search_path(), true /* native */, false, false, None) {
             Ok(runtime_connection) => {
+                STDIN.reset();
                 UiContext::clear_pre_run();
+                if let (Some(path), Some(contents)) = (stdin_file, stdin_contents) {
+                    STDIN.set_contents(&contents);
+                    UiContext::stdin_from_file(path);
+                }
                 UiContext::message(&format!("Submitting manifest for execution with args: '{:?}'", args));
                 let submission = Submission::new(&manifest_url, 1);
                 IdeRuntimeClient::start(runtime_connection, submission, args);
@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::{Inhibit, ScrolledWindow, TextBuffer, TextTag, TextView, TextWindowType, WindowPosition};
+use gtk::prelude::*;
+
+use crate::ui_context::UiContext;
+
+const LINK_TAG: &str = "link";
+const HEADING_TAG: &str = "heading";
+// The pango weight of bold text
+const BOLD: i32 = 700;
+// Size of a window showing the source a diagnostic is about
+const SOURCE_WIDTH: i32 = 800;
+const SOURCE_HEIGHT: i32 = 600;
+
+// Where in a source file a diagnostic in the output is, and the part of the output that says so
+struct Link {
+    start: i32,
+    end: i32,
+    path: PathBuf,
+    line: usize,
+    column: usize,
+}
+
+// State of the console that is shared between the gtk signal handlers
+#[derive(Default)]
+struct ConsoleState {
+    // The directory of the build writing the output, that paths in its diagnostics are relative to
+    directory: Option<PathBuf>,
+    links: Vec<Link>,
+}
+
+/// The output of building the implementations supplied with the functions of a flow, written as
+/// the builds run, where clicking on the location of a compiler diagnostic opens the source it is in
+#[derive(Clone)]
+pub struct BuildConsole {
+    scroll: ScrolledWindow,
+    view: TextView,
+    buffer: TextBuffer,
+    state: Rc<RefCell<ConsoleState>>,
+}
+
+impl BuildConsole {
+    pub fn new() -> Self {
+        let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        let view = TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        scroll.add(&view);
+        let buffer = view.get_buffer().unwrap();
+
+        let console = BuildConsole {
+            scroll,
+            view,
+            buffer,
+            state: Rc::new(RefCell::new(ConsoleState::default())),
+        };
+        console.create_tags();
+        console.connect_signals();
+        console
+    }
+
+    pub fn widget(&self) -> &ScrolledWindow {
+        &self.scroll
+    }
+
+    pub fn clear(&self) {
+        self.buffer.set_text("");
+        let mut state = self.state.borrow_mut();
+        state.directory = None;
+        state.links.clear();
+    }
+
+    /// Start the output of a build run in 'directory', with a heading saying what is being built
+    pub fn start_build(&self, heading: &str, directory: PathBuf) {
+        let mut end = self.buffer.get_end_iter();
+        if end.get_offset() > 0 {
+            self.buffer.insert(&mut end, "\n");
+        }
+        let start = end.get_offset();
+        self.buffer.insert(&mut end, &format!("{}\n", heading));
+        self.buffer.apply_tag_by_name(HEADING_TAG, &self.buffer.get_iter_at_offset(start), &end);
+        self.state.borrow_mut().directory = Some(directory);
+        self.scroll_to_end();
+    }
+
+    /// Add a line written by the build, making the location of a diagnostic in it a link to the source
+    pub fn append(&self, line: &str) {
+        let mut end = self.buffer.get_end_iter();
+        let line_start = end.get_offset();
+        self.buffer.insert(&mut end, &format!("{}\n", line));
+
+        let mut state = self.state.borrow_mut();
+        if let Some((offset, length, path, line, column)) = diagnostic_location(line) {
+            let path = match &state.directory {
+                Some(directory) => directory.join(path),
+                None => path
+            };
+            let start = line_start + offset as i32;
+            let end = start + length as i32;
+            self.buffer.apply_tag_by_name(LINK_TAG, &self.buffer.get_iter_at_offset(start),
+                                          &self.buffer.get_iter_at_offset(end));
+            state.links.push(Link { start, end, path, line, column });
+        }
+        self.scroll_to_end();
+    }
+
+    fn scroll_to_end(&self) {
+        let mut end = self.buffer.get_end_iter();
+        self.view.scroll_to_iter(&mut end, 0.0, false, 0.0, 0.0);
+    }
+
+    fn create_tags(&self) {
+        let tag_table = self.buffer.get_tag_table().unwrap();
+        let link_tag = TextTag::new(Some(LINK_TAG));
+        link_tag.set_property_foreground(Some("#2a5db0"));
+        link_tag.set_property_underline(pango::Underline::Single);
+        tag_table.add(&link_tag);
+
+        let heading_tag = TextTag::new(Some(HEADING_TAG));
+        heading_tag.set_property_weight(BOLD);
+        tag_table.add(&heading_tag);
+    }
+
+    fn connect_signals(&self) {
+        // Open the source at the location of a diagnostic when it is clicked on
+        let state = self.state.clone();
+        self.view.connect_button_release_event(move |view, event| {
+            let (x, y) = event.get_position();
+            let (x, y) = view.window_to_buffer_coords(TextWindowType::Widget, x as i32, y as i32);
+            let offset = match view.get_iter_at_location(x, y) {
+                Some(iter) => iter.get_offset(),
+                None => return Inhibit(false)
+            };
+
+            let state = state.borrow();
+            match state.links.iter().find(|link| offset >= link.start && offset < link.end) {
+                Some(link) => {
+                    show_source(&link.path, link.line, link.column);
+                    Inhibit(true)
+                }
+                None => Inhibit(false)
+            }
+        });
+    }
+}
+
+// The location in a line of rustc's output of the code a diagnostic is about, as in
+// "  --> src/lib.rs:12:5": the offset and length of the location in the line, the path, line and column
+fn diagnostic_location(line: &str) -> Option<(usize, usize, PathBuf, usize, usize)> {
+    let arrow = line.find("--> ")?;
+    if !line[..arrow].trim().is_empty() {
+        return None;
+    }
+    let offset = arrow + "--> ".len();
+    let location = line[offset..].trim_end();
+
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line_number = parts.next()?.parse().ok()?;
+    let path = parts.next().filter(|path| !path.is_empty())?;
+    Some((line[..offset].chars().count(), location.chars().count(), PathBuf::from(path), line_number, column))
+}
+
+// Show the source at 'path' in a window of its own, with the line a diagnostic is about selected
+fn show_source(path: &Path, line: usize, column: usize) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return UiContext::ui_error(&format!("Could not open '{}': {}", path.display(), e))
+    };
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(&format!("{}:{}:{}", path.display(), line, column));
+    window.set_position(WindowPosition::CenterOnParent);
+    window.set_default_size(SOURCE_WIDTH, SOURCE_HEIGHT);
+
+    let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    let view = TextView::new();
+    view.set_editable(false);
+    view.set_monospace(true);
+    scroll.add(&view);
+    window.add(&scroll);
+    let buffer = view.get_buffer().unwrap();
+    buffer.set_text(&text);
+    window.show_all();
+
+    // Lines and columns in diagnostics start at one
+    let line = line.saturating_sub(1) as i32;
+    let start = buffer.get_iter_at_line(line);
+    let mut end = start.clone();
+    end.forward_to_line_end();
+    buffer.select_range(&start, &end);
+    let mut start = buffer.get_iter_at_line_offset(line, column.saturating_sub(1) as i32);
+    view.scroll_to_iter(&mut start, 0.1, true, 0.0, 0.5);
+}
@@ -1,19 +1,29 @@
+use std::env;
 use std::process;
 use std::sync::{Arc, Mutex};
 
-use gtk::{Application, MenuItem, WidgetExt};
+use gtk::{Application, ApplicationWindow, MenuItem, WidgetExt};
 use gtk::prelude::*;
 use gtk_rs_state::gtk_refs;
 use lazy_static::lazy_static;
 use url::Url;
 
-use crate::{actions, ui_layout, log_error};
+use flowclib::deserializers::deserializer_helper;
+
+use crate::{actions, menu, problems, ui_layout, log_error};
+use crate::flow_graph_view::{FlowGraphView, GraphChange};
+use crate::flow_source_editor::FlowSourceEditor;
+use crate::stdin::STDIN;
 use crate::ui_context::UiContext;
 
 // Tabs/Pages in the notebook
 pub const FLOW_GRAPH_PAGE: i32 = 0;
-pub const _FLOW_JSON_PAGE: i32 = 1;
+pub const FLOW_SOURCE_PAGE: i32 = 1;
 pub const MANIFEST_PAGE: i32 = 2;
+// Tabs/Pages in the output notebook
+pub const STDIN_PAGE: i32 = 2;
+pub const COMPILE_ERRORS_PAGE: i32 = 4;
+pub const BUILD_PAGE: i32 = 5;
 
 lazy_static! {
     pub static ref UICONTEXT: Arc<Mutex<UiContext >> = Arc::new(Mutex::new(UiContext::new()));
@@ -25,18 +35,42 @@ gtk_refs!(
     pub mod widgets;   // The macro emits a new module with this name
     struct WidgetRefs; // The macro emits a struct with this name containing references to following fields
     app_window: gtk::ApplicationWindow,
-    flow_buffer: gtk::TextBuffer,
+    flow_graph_view: crate::flow_graph_view::FlowGraphView,
+    flow_source_editor: crate::flow_source_editor::FlowSourceEditor,
+    flow_files: gtk::TreeStore,
     manifest_buffer: gtk::TextBuffer,
     flow_notebook: gtk::Notebook,
+    output_notebook: gtk::Notebook,
     args_buffer: gtk::TextBuffer,
+    stdin_file: gtk::FileChooserButton,
     stdout: gtk::TextBuffer,
     stderr: gtk::TextBuffer,
+    stdin: gtk::TextBuffer,
+    stdin_entry: gtk::Entry,
+    send_stdin: gtk::Button,
+    send_eof: gtk::Button,
+    stdin_status: gtk::Label,
+    problems_view: gtk::TreeView,
+    problems: gtk::ListStore,
+    problems_label: gtk::Label,
+    compile_errors_view: gtk::TreeView,
+    compile_errors: gtk::ListStore,
+    compile_errors_label: gtk::Label,
+    build_console: crate::build_console::BuildConsole,
+    save_flow_menu: gtk::MenuItem,
+    save_flow_as_menu: gtk::MenuItem,
+    undo_menu: gtk::MenuItem,
+    redo_menu: gtk::MenuItem,
+    export_graph_menu: gtk::MenuItem,
     compile_flow_menu: gtk::MenuItem,
+    compile_options_menu: gtk::MenuItem,
     run_manifest_menu: gtk::MenuItem,
-    status_message: gtk::Label
+    status_message: gtk::Label,
+    compile_progress: gtk::ProgressBar,
+    cancel_compile: gtk::Button
 );
 
-fn run_action(run: &MenuItem, args_buffer: gtk::TextBuffer) {
+fn run_action(run: &MenuItem, args_buffer: gtk::TextBuffer, stdin_file: gtk::FileChooserButton) {
     run.connect_activate(move |_| {
         let mut args: Vec<String> = vec!();
         let (start, end) = args_buffer.get_bounds();
@@ -52,7 +86,7 @@ fn run_action(run: &MenuItem, args_buffer: gtk::TextBuffer) {
                     Some(manifest_url) => {
                         // Argument at index zero is the flow name
                         args.insert(0, context.manifest.as_ref().unwrap().get_metadata().name.clone());
-                        actions::run_manifest(manifest_url.into(), args);
+                        actions::run_manifest(manifest_url.into(), args, stdin_file.get_filename());
                     }
                     _ => UiContext::ui_error("No manifest loaded to run")
                 }
@@ -62,7 +96,126 @@ fn run_action(run: &MenuItem, args_buffer: gtk::TextBuffer) {
     });
 }
 
-pub fn build_ui(application: &Application, url: &Option<Url>, flow_args: &[String], _stdin_file: &Option<String>) {
+// wire up the save actions that save the contents of the flow source editor
+fn save_flow_actions(window: &ApplicationWindow, save: &MenuItem, save_as: &MenuItem,
+                     flow_source_editor: FlowSourceEditor) {
+    let editor = flow_source_editor.clone();
+    save.connect_activate(move |_| {
+        actions::save_flow(None, editor.contents());
+    });
+
+    let formats = deserializer_helper::get_accepted_extensions().iter()
+        .map(|extension| (format!("Flow definition ({})", extension), extension.to_string()))
+        .collect();
+    menu::save_action(window, save_as, "Save Flow As", formats, move |path| {
+        match Url::from_file_path(&path) {
+            Ok(url) => actions::save_flow(Some(url.to_string()), flow_source_editor.contents()),
+            Err(_) => UiContext::ui_error(&format!("Could not create a Url for '{}'", path))
+        }
+    });
+}
+
+// Changes to the flow's definitions reload the flow, replacing the text in the flow source editor,
+// so they are not made while it has unsaved changes
+fn has_unsaved_changes(flow_source_editor: &FlowSourceEditor, action: &str) -> bool {
+    let unsaved = flow_source_editor.buffer().get_modified();
+    if unsaved {
+        UiContext::message(&format!("Save the changes made in the Flow Source tab before {}", action));
+    }
+    unsaved
+}
+
+// wire up changes made in the flow graph view, that are written to the flow's definitions
+fn edit_flow_action(flow_graph_view: &FlowGraphView, flow_source_editor: FlowSourceEditor) {
+    flow_graph_view.connect_change(move |flow_url, change| {
+        if has_unsaved_changes(&flow_source_editor, "editing the flow graph") {
+            return;
+        }
+
+        match change {
+            GraphChange::Edit(edit) => actions::edit_flow(flow_url, edit),
+            GraphChange::Extract { aliases, name } => actions::extract_flow(flow_url, aliases, name),
+            GraphChange::Inline { alias } => actions::inline_flow(flow_url, alias),
+            GraphChange::Rename { renamed, name } => actions::rename(flow_url, renamed, name),
+        }
+    });
+}
+
+// wire up undoing and redoing the changes made to the flow, from either the graph or the source
+fn undo_actions(undo: &MenuItem, redo: &MenuItem, flow_source_editor: FlowSourceEditor) {
+    let editor = flow_source_editor.clone();
+    undo.connect_activate(move |_| {
+        if !has_unsaved_changes(&editor, "undoing") {
+            actions::undo();
+        }
+    });
+
+    redo.connect_activate(move |_| {
+        if !has_unsaved_changes(&flow_source_editor, "redoing") {
+            actions::redo();
+        }
+    });
+}
+
+fn cancel_compile_action(cancel: &gtk::Button) {
+    cancel.connect_clicked(|_| actions::cancel_compile());
+}
+
+// wire up sending the line entered, or the end of the input, to STDIN of the running flow
+fn stdin_actions(entry: &gtk::Entry, send: &gtk::Button, send_eof: &gtk::Button) {
+    let send_line = |entry: &gtk::Entry| {
+        let line = entry.get_text().to_string();
+        match STDIN.send_line(&line) {
+            Ok(()) => {
+                UiContext::stdin_sent(Some(line));
+                entry.set_text("");
+            }
+            Err(e) => UiContext::message(&e)
+        }
+    };
+
+    entry.connect_activate(move |entry| send_line(entry));
+    let line_entry = entry.clone();
+    send.connect_clicked(move |_| send_line(&line_entry));
+
+    send_eof.connect_clicked(|_| {
+        STDIN.send_eof();
+        UiContext::stdin_sent(None);
+    });
+}
+
+// wire up selecting a problem in a list of problems to select where it is in the flow graph, and
+// activating it to show where it is in the flow source editor
+fn problems_action(problems_view: &gtk::TreeView, flow_graph_view: FlowGraphView, flow_source_editor: FlowSourceEditor,
+                   flow_notebook: gtk::Notebook) {
+    let view = problems_view.clone();
+    let notebook = flow_notebook.clone();
+    problems_view.get_selection().connect_changed(move |_| {
+        if let Some(entry) = problems::selected_problem(&view) {
+            if entry.alias.is_some() || !entry.location.is_empty() {
+                notebook.set_property_page(FLOW_GRAPH_PAGE);
+                flow_graph_view.select_node(&entry.location, entry.alias.as_deref());
+            }
+        }
+    });
+
+    problems_view.connect_row_activated(move |view, _, _| {
+        let entry = match problems::selected_problem(view) {
+            Some(entry) => entry,
+            None => return
+        };
+        match (entry.url, entry.text) {
+            (Some(url), Some(text)) if flow_source_editor.url().as_deref() == Some(url.as_str()) => {
+                flow_notebook.set_property_page(FLOW_SOURCE_PAGE);
+                flow_source_editor.show_text(&text);
+            }
+            (Some(url), _) => UiContext::message(&format!("The problem is in '{}', which is not open in the Flow Source tab", url)),
+            (None, _) => {}
+        }
+    });
+}
+
+pub fn build_ui(application: &Application, url: &Option<Url>, flow_args: &[String], stdin_file: &Option<String>) {
     let widget_refs = ui_layout::create(application);
 
     widget_refs.app_window.set_title(env!("CARGO_PKG_NAME"));
@@ -78,8 +231,32 @@ pub fn build_ui(application: &Application, url: &Option<Url>, flow_args: &[Strin
         widget_refs.args_buffer.set_text(&flow_args.join(" "));
     }
 
-    // wire up the run action that needs the menu item and the args widget
-    run_action(&widget_refs.run_manifest_menu, widget_refs.args_buffer.clone());
+    // STDIN is read from the file given on the command line, unless another is chosen
+    if let Some(stdin_file) = stdin_file {
+        let path = env::current_dir().map(|cwd| cwd.join(stdin_file)).unwrap_or_else(|_| stdin_file.into());
+        if !widget_refs.stdin_file.set_filename(&path) {
+            log_error(&format!("Could not find STDIN file '{}'", path.display()));
+        }
+    }
+
+    // wire up the run action that needs the menu item and the args and STDIN file widgets
+    run_action(&widget_refs.run_manifest_menu, widget_refs.args_buffer.clone(), widget_refs.stdin_file.clone());
+
+    save_flow_actions(&widget_refs.app_window, &widget_refs.save_flow_menu, &widget_refs.save_flow_as_menu,
+                      widget_refs.flow_source_editor.clone());
+
+    edit_flow_action(&widget_refs.flow_graph_view, widget_refs.flow_source_editor.clone());
+
+    undo_actions(&widget_refs.undo_menu, &widget_refs.redo_menu, widget_refs.flow_source_editor.clone());
+
+    cancel_compile_action(&widget_refs.cancel_compile);
+
+    stdin_actions(&widget_refs.stdin_entry, &widget_refs.send_stdin, &widget_refs.send_eof);
+
+    problems_action(&widget_refs.problems_view, widget_refs.flow_graph_view.clone(),
+                    widget_refs.flow_source_editor.clone(), widget_refs.flow_notebook.clone());
+    problems_action(&widget_refs.compile_errors_view, widget_refs.flow_graph_view.clone(),
+                    widget_refs.flow_source_editor.clone(), widget_refs.flow_notebook.clone());
 
     // do any action prior to running application
     if let Some(ref flow_url) = url {
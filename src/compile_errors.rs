@@ -0,0 +1,248 @@
+use url::Url;
+
+use flowclib::model::flow::Flow;
+use flowclib::model::process::Process::FlowProcess;
+
+use crate::lint::Severity;
+
+/// An error (or warning) from compiling a flow, with what could be found in the loaded flow of
+/// the file and process it is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub severity: Severity,
+    pub message: String,
+    // The url of the definition the error is about
+    pub url: Option<String>,
+    // The aliases of the sub-flows leading to the flow the error is in, and the process in it
+    pub location: Vec<String>,
+    pub alias: Option<String>,
+    // The text in the message used to find what the error is about, to find it in the definition
+    pub text: Option<String>,
+}
+
+// A process of the loaded flow, or one of its sub-flows, that an error may refer to
+struct Referenced {
+    location: Vec<String>,
+    alias: String,
+    // The url of the definition of the flow referencing the process, and of the process
+    flow_url: String,
+    source_url: Option<String>,
+}
+
+/// The errors in the messages of a failed compilation of 'flow', one per distinct line, in order
+pub fn compile_errors(flow: &Flow, messages: &[String]) -> Vec<CompileError> {
+    let mut processes = vec!();
+    referenced(flow, &[], &mut processes);
+
+    let mut lines: Vec<&str> = vec!();
+    for line in messages.iter().flat_map(|message| message.lines()).map(str::trim) {
+        if !line.is_empty() && !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+
+    lines.into_iter().map(|line| compile_error(flow, &processes, line)).collect()
+}
+
+fn referenced(flow: &Flow, location: &[String], processes: &mut Vec<Referenced>) {
+    let base = Url::parse(&flow.source_url).ok();
+    for process_ref in flow.process_refs.iter().flatten() {
+        let alias = process_ref.alias.to_string();
+        processes.push(Referenced {
+            location: location.to_vec(),
+            alias: alias.clone(),
+            flow_url: flow.source_url.clone(),
+            source_url: base.as_ref().and_then(|base| base.join(&process_ref.source).ok()).map(|url| url.to_string()),
+        });
+
+        if let Some(FlowProcess(sub_flow)) = flow.subprocesses.get(&process_ref.alias) {
+            let mut sub_location = location.to_vec();
+            sub_location.push(alias);
+            referenced(sub_flow, &sub_location, processes);
+        }
+    }
+}
+
+fn compile_error(flow: &Flow, processes: &[Referenced], line: &str) -> CompileError {
+    let severity = if line.to_lowercase().starts_with("warning") { Severity::Warning } else { Severity::Error };
+    let quoted = quoted(line);
+
+    // A file named in the message is what it is about, otherwise the process with the most of
+    // the route to it in the message, preferring processes nearer the root flow
+    let file = quoted.iter().find_map(|text| {
+        let url = std::iter::once(flow.source_url.as_str())
+            .chain(processes.iter().filter_map(|process| process.source_url.as_deref()))
+            .find(|url| refers_to_file(text, url))?;
+        Some((url.to_string(), text.clone()))
+    });
+    let process = quoted.iter()
+        .flat_map(|text| processes.iter().map(move |process| (route_match(text, process), process, text)))
+        .filter(|(length, _, _)| *length > 0)
+        .fold(None, |best: Option<(usize, &Referenced, &String)>, candidate| match best {
+            Some(best) if best.0 > candidate.0 || (best.0 == candidate.0 && best.1.location.len() <= candidate.1.location.len()) => Some(best),
+            _ => Some(candidate)
+        });
+
+    let (url, text) = match (&file, process) {
+        (Some((url, text)), _) => (Some(url.clone()), Some(text.clone())),
+        (None, Some((_, process, _))) => (Some(process.flow_url.clone()), Some(process.alias.clone())),
+        (None, None) => (None, None)
+    };
+    // A process is only selected if the error is about the definition it is referenced from
+    let process = process.map(|(_, process, _)| process)
+        .filter(|process| url.as_deref().map_or(true, |url| url == process.flow_url || Some(url) == process.source_url.as_deref()));
+
+    CompileError {
+        severity,
+        message: line.to_string(),
+        url,
+        location: process.map(|process| process.location.clone()).unwrap_or_default(),
+        alias: process.map(|process| process.alias.clone()),
+        text,
+    }
+}
+
+// The texts quoted in a message with single or double quotes
+fn quoted(line: &str) -> Vec<String> {
+    let mut texts = vec!();
+    for quote in &['\'', '"'] {
+        let parts: Vec<&str> = line.split(*quote).collect();
+        // Every other part is inside quotes, if they are balanced
+        texts.extend(parts.iter().skip(1).step_by(2).take((parts.len() - 1) / 2)
+            .filter(|text| !text.is_empty())
+            .map(|text| text.to_string()));
+    }
+    texts
+}
+
+fn refers_to_file(text: &str, url: &str) -> bool {
+    text == url || Url::parse(url).ok().and_then(|url| url.to_file_path().ok())
+        .map_or(false, |path| path.display().to_string() == text)
+}
+
+// How many segments of the route to a process, from the root flow, appear in order in 'text', if
+// it refers to the process at all
+fn route_match(text: &str, process: &Referenced) -> usize {
+    let segments: Vec<&str> = text.split('/').filter(|segment| !segment.is_empty()).collect();
+    let route: Vec<&str> = process.location.iter().map(String::as_str)
+        .chain(std::iter::once(process.alias.as_str()))
+        .collect();
+
+    // The most segments of the end of the route that appear together in the text
+    (1..=route.len()).rev()
+        .find(|length| segments.windows(*length).any(|window| *window == route[route.len() - length..]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use flowclib::deserializers::deserializer_helper;
+    use flowclib::model::flow::Flow;
+    use flowclib::model::process::Process::{self, FlowProcess, FunctionProcess};
+
+    use crate::lint::Severity;
+
+    use super::{compile_errors, quoted, refers_to_file, route_match, CompileError, Referenced};
+
+    const ROOT: &str = "flow = \"root\"
+
+[[process]]
+alias = \"seq\"
+source = \"seq.toml\"
+";
+
+    const SEQ: &str = "flow = \"seq\"
+
+[[process]]
+alias = \"tap\"
+source = \"tap.toml\"
+";
+
+    // The flow defined by 'text' as it is when loaded from 'url'
+    fn flow(url: &str, text: &str) -> Flow {
+        match deserializer_helper::get_deserializer::<Process>(url).unwrap().deserialize(text, Some(url)).unwrap() {
+            FlowProcess(mut flow) => {
+                flow.source_url = url.into();
+                flow
+            }
+            FunctionProcess(_) => panic!("'{}' is not a flow", url)
+        }
+    }
+
+    // The root flow with the 'seq' sub-flow loaded into it
+    fn root() -> Flow {
+        let mut root = flow("file:///flows/root.toml", ROOT);
+        let seq = root.process_refs.iter().flatten().map(|process_ref| process_ref.alias.clone()).next().unwrap();
+        root.subprocesses.insert(seq, FlowProcess(flow("file:///flows/seq.toml", SEQ)));
+        root
+    }
+
+    fn referenced(location: &[&str], alias: &str) -> Referenced {
+        Referenced {
+            location: location.iter().map(|alias| alias.to_string()).collect(),
+            alias: alias.into(),
+            flow_url: "file:///flows/root.toml".into(),
+            source_url: None,
+        }
+    }
+
+    #[test]
+    fn texts_in_either_quotes_found() {
+        assert_eq!(quoted("Could not find 'add' in \"flow.toml\""), vec!("add".to_string(), "flow.toml".to_string()));
+        assert_eq!(quoted("'a' and 'b'"), vec!("a".to_string(), "b".to_string()));
+        assert!(quoted("nothing quoted ''").is_empty());
+    }
+
+    #[test]
+    fn unbalanced_quote_ignored() {
+        assert_eq!(quoted("route 'a/b' and 'c"), vec!("a/b".to_string()));
+        assert!(quoted("no 'end").is_empty());
+    }
+
+    #[test]
+    fn most_of_the_route_matched() {
+        let add = referenced(&["seq"], "add");
+        assert_eq!(route_match("/root/seq/add", &add), 2);
+        assert_eq!(route_match("seq/add/i1", &add), 2);
+        assert_eq!(route_match("add", &add), 1);
+        assert_eq!(route_match("seq/other/add", &add), 1);
+    }
+
+    #[test]
+    fn other_routes_not_matched() {
+        let add = referenced(&["seq"], "add");
+        assert_eq!(route_match("seq", &add), 0);
+        assert_eq!(route_match("adder", &add), 0);
+        assert_eq!(route_match("", &add), 0);
+    }
+
+    #[test]
+    fn file_referred_to_by_url_or_path() {
+        assert!(refers_to_file("file:///flows/root.toml", "file:///flows/root.toml"));
+        assert!(refers_to_file("/flows/root.toml", "file:///flows/root.toml"));
+        assert!(!refers_to_file("root.toml", "file:///flows/root.toml"));
+    }
+
+    #[test]
+    fn errors_found_in_the_loaded_flow() {
+        let messages = vec!("Could not find 'seq/tap' output\nCould not find 'seq/tap' output".to_string(),
+                            "Warning: '/flows/seq.toml' has no outputs".to_string());
+        assert_eq!(compile_errors(&root(), &messages), vec!(
+            CompileError {
+                severity: Severity::Error,
+                message: "Could not find 'seq/tap' output".into(),
+                url: Some("file:///flows/seq.toml".into()),
+                location: vec!("seq".into()),
+                alias: Some("tap".into()),
+                text: Some("tap".into()),
+            },
+            CompileError {
+                severity: Severity::Warning,
+                message: "Warning: '/flows/seq.toml' has no outputs".into(),
+                url: Some("file:///flows/seq.toml".into()),
+                location: vec!(),
+                alias: None,
+                text: Some("/flows/seq.toml".into()),
+            }));
+    }
+}
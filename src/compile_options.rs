@@ -0,0 +1,137 @@
+//! The options for compiling a flow are saved in a "compile" file next to the flow's definition,
+//! e.g. `myflow.compile.toml` for `myflow.toml`, so they are kept with the project. Options not
+//! in the file have their default values, which are those used for a flow without one:
+//!
+//! ```toml
+//! output_dir = "target"      # relative to the flow's directory, where the manifest is written
+//! manifest_name = "manifest" # the manifest is written to "manifest.json"
+//! debug_symbols = true
+//! implementations = "changed" # build supplied implementations if "changed", "always" or "never"
+//! lib_dirs = ["../lib"]       # where to look for libraries, as well as in FLOW_LIB_PATH
+//! ```
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use flowrstructs::manifest::DEFAULT_MANIFEST_FILENAME;
+
+use crate::implementations::Rebuild;
+
+const OPTIONS_EXTENSION: &str = "compile.toml";
+const OUTPUT_DIR: &str = "output_dir";
+const MANIFEST_NAME: &str = "manifest_name";
+const DEBUG_SYMBOLS: &str = "debug_symbols";
+const IMPLEMENTATIONS: &str = "implementations";
+const LIB_DIRS: &str = "lib_dirs";
+
+/// How to compile a flow
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileOptions {
+    // The directory to write the manifest in, relative to the flow's directory unless absolute.
+    // Empty for the flow's directory.
+    pub output_dir: String,
+    // The name of the manifest file, without the ".json" extension
+    pub manifest_name: String,
+    pub debug_symbols: bool,
+    pub implementations: Rebuild,
+    // Directories or urls to look for libraries in, as well as those in FLOW_LIB_PATH
+    pub lib_dirs: Vec<String>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            output_dir: String::new(),
+            manifest_name: DEFAULT_MANIFEST_FILENAME.to_string(),
+            debug_symbols: true,
+            implementations: Rebuild::Changed,
+            lib_dirs: vec!(),
+        }
+    }
+}
+
+impl CompileOptions {
+    /// The url of the manifest to write when compiling the flow defined at 'flow_url'
+    pub fn manifest_url(&self, flow_url: &str) -> Result<Url, String> {
+        let flow_url = Url::parse(flow_url)
+            .map_err(|e| format!("Could not parse the url of the flow '{}': {}", flow_url, e))?;
+        let output_dir = if self.output_dir.is_empty() {
+            flow_url.join("./")
+        } else {
+            flow_url.join(&format!("{}/", self.output_dir.trim_end_matches('/')))
+        };
+        output_dir.and_then(|dir| dir.join(&format!("{}.json", self.manifest_name)))
+            .map_err(|e| format!("Could not create the url of the manifest: {}", e))
+    }
+}
+
+// The path of the options file for the flow defined at 'flow_url', if it is a file
+fn options_path(flow_url: &str) -> Option<PathBuf> {
+    let path = Url::parse(flow_url).ok()?.to_file_path().ok()?;
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    Some(path.with_file_name(format!("{}.{}", stem, OPTIONS_EXTENSION)))
+}
+
+/// Is the file called 'name' a compile options file rather than a flow definition
+pub fn is_options_file(name: &str) -> bool {
+    name.ends_with(&format!(".{}", OPTIONS_EXTENSION))
+}
+
+/// The directory of the flow defined at 'flow_url', that a relative output directory is in
+pub fn flow_dir(flow_url: &str) -> Option<PathBuf> {
+    let path = Url::parse(flow_url).ok()?.to_file_path().ok()?;
+    path.parent().map(Path::to_path_buf)
+}
+
+/// Read the options for compiling the flow defined at 'flow_url'. They are the defaults if it has
+/// no options file, and any options that are not valid are ignored.
+pub fn read_options(flow_url: &str) -> CompileOptions {
+    let mut options = CompileOptions::default();
+
+    let text = match options_path(flow_url).and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => text,
+        None => return options
+    };
+    let table: toml::Value = match toml::from_str(&text) {
+        Ok(table) => table,
+        Err(e) => {
+            crate::log_warn(&format!("Could not read the compile options of flow '{}': {}", flow_url, e));
+            return options;
+        }
+    };
+
+    if let Some(output_dir) = table.get(OUTPUT_DIR).and_then(toml::Value::as_str) {
+        options.output_dir = output_dir.to_string();
+    }
+    if let Some(manifest_name) = table.get(MANIFEST_NAME).and_then(toml::Value::as_str).filter(|name| !name.is_empty()) {
+        options.manifest_name = manifest_name.to_string();
+    }
+    if let Some(debug_symbols) = table.get(DEBUG_SYMBOLS).and_then(toml::Value::as_bool) {
+        options.debug_symbols = debug_symbols;
+    }
+    if let Some(implementations) = table.get(IMPLEMENTATIONS).and_then(toml::Value::as_str).and_then(Rebuild::from_id) {
+        options.implementations = implementations;
+    }
+    if let Some(lib_dirs) = table.get(LIB_DIRS).and_then(toml::Value::as_array) {
+        options.lib_dirs = lib_dirs.iter().filter_map(toml::Value::as_str).map(String::from).collect();
+    }
+
+    options
+}
+
+/// Write the options for compiling the flow defined at 'flow_url' to its options file
+pub fn write_options(flow_url: &str, options: &CompileOptions) -> Result<(), String> {
+    let path = options_path(flow_url)
+        .ok_or_else(|| format!("Cannot save the compile options of flow '{}' as it is not a file", flow_url))?;
+
+    let mut table = toml::value::Table::new();
+    table.insert(OUTPUT_DIR.into(), toml::Value::String(options.output_dir.clone()));
+    table.insert(MANIFEST_NAME.into(), toml::Value::String(options.manifest_name.clone()));
+    table.insert(DEBUG_SYMBOLS.into(), toml::Value::Boolean(options.debug_symbols));
+    table.insert(IMPLEMENTATIONS.into(), toml::Value::String(options.implementations.id().into()));
+    table.insert(LIB_DIRS.into(), toml::Value::Array(options.lib_dirs.iter().cloned().map(toml::Value::String).collect()));
+
+    let text = toml::to_string_pretty(&toml::Value::Table(table)).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| format!("Could not save the compile options to '{}': {}", path.display(), e))
+}
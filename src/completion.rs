@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use url::Url;
+
+use flowclib::compiler::loader;
+use flowclib::deserializers::deserializer_helper;
+use flowclib::model::datatype::HasDataType;
+use flowclib::model::flow::Flow;
+use flowclib::model::io::IO;
+use flowclib::model::name::HasName;
+use flowclib::model::process::Process;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
+use provider::content::provider::{MetaProvider, Provider};
+use simpath::Simpath;
+
+use crate::{compile_options, layout_file};
+use crate::syntax::SourceFormat;
+
+const LIB_SCHEME: &str = "lib://";
+
+/// The kind of value being written where completion was requested
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompletionKind {
+    // The 'source' of a process reference
+    Source,
+    // The 'from' route of a connection
+    From,
+    // The 'to' route of a connection
+    To,
+}
+
+/// A value that can complete what has been typed so far, with a description of what it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub value: String,
+    pub detail: String,
+}
+
+/// Find what kind of value is being written at the end of 'line' (the text of the line up to the
+/// cursor) and the part of it typed so far
+pub fn completion_at(format: SourceFormat, line: &str) -> Option<(CompletionKind, String)> {
+    let separator = match format {
+        SourceFormat::Toml => '=',
+        SourceFormat::Json | SourceFormat::Yaml => ':',
+    };
+
+    let (key, value) = line.split_at(line.find(separator)?);
+    let key = key.trim().trim_start_matches('-').trim().trim_matches('"');
+    let value = value[1..].trim_start();
+
+    // Values must be quoted, except in yaml where the quotes are optional
+    let typed = match value.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => &value[1..],
+        _ if format == SourceFormat::Yaml => value,
+        _ => return None
+    };
+    if typed.contains(|c| c == '"' || c == '\'') {
+        return None;
+    }
+
+    let kind = match key {
+        "source" => CompletionKind::Source,
+        "from" => CompletionKind::From,
+        "to" => CompletionKind::To,
+        _ => return None
+    };
+
+    Some((kind, typed.to_string()))
+}
+
+/// Find the values that could complete 'typed' in the definition 'text' of the flow at 'url'.
+/// 'loaded' is the last version of the flow that was loaded, used to find the ports of processes
+/// without loading them again.
+pub fn completions(url: &str, text: &str, kind: CompletionKind, typed: &str, loaded: Option<&Flow>,
+                   lib_search_path: Simpath) -> Vec<Completion> {
+    let provider = MetaProvider::new(lib_search_path.clone());
+
+    let mut completions = match kind {
+        CompletionKind::Source if typed.starts_with(LIB_SCHEME) =>
+            library_sources(typed, &lib_search_path, &provider),
+        CompletionKind::Source => {
+            let mut sources = relative_sources(url, typed, &provider);
+            sources.push(Completion { value: LIB_SCHEME.into(), detail: "a library on the search path".into() });
+            sources
+        }
+        CompletionKind::From | CompletionKind::To => {
+            // Use the definition as it is now if it can be parsed, as it may reference
+            // processes that have not been loaded yet
+            let parsed = deserializer_helper::get_deserializer::<Process>(url).ok()
+                .and_then(|deserializer| deserializer.deserialize(text, Some(url)).ok());
+            match (parsed, loaded) {
+                (Some(FlowProcess(flow)), _) => routes(url, &flow, kind, loaded, &provider),
+                (_, Some(flow)) => routes(url, flow, kind, loaded, &provider),
+                _ => vec!()
+            }
+        }
+    };
+
+    completions.retain(|completion| completion.value.starts_with(typed));
+    completions.sort_by(|a, b| a.value.cmp(&b.value));
+    completions.dedup_by(|a, b| a.value == b.value);
+    completions
+}
+
+// Sources of the form "lib://{library}/{path}" of the directories and definitions in the
+// libraries found on the library search path
+fn library_sources(typed: &str, lib_search_path: &Simpath, provider: &dyn Provider) -> Vec<Completion> {
+    let path = typed.get(LIB_SCHEME.len()..).unwrap_or("");
+    let (directory, _) = split_directory(path);
+
+    let mut completions = vec!();
+    for lib_root in lib_search_path.directories() {
+        let candidates = entries(&lib_root.join(directory), &format!("{}{}", LIB_SCHEME, directory));
+        completions.extend(candidates.into_iter().filter_map(|completion| library_reference(completion, provider)));
+    }
+    completions
+}
+
+// Sources of the form "{path}" relative to the directory of the flow being edited
+fn relative_sources(url: &str, typed: &str, provider: &dyn Provider) -> Vec<Completion> {
+    let flow_path = match Url::parse(url).ok().and_then(|url| url.to_file_path().ok()) {
+        Some(path) => path,
+        None => return vec!()
+    };
+    let (directory, _) = split_directory(typed);
+    let flow_directory = flow_path.parent().unwrap_or_else(|| Path::new("/"));
+
+    entries(&flow_directory.join(directory), directory).into_iter()
+        .filter_map(|mut completion| {
+            // Check the file can be found by the provider, relative to the flow being edited
+            if !completion.value.ends_with('/') {
+                let source_url = Url::parse(url).ok()?.join(&completion.value).ok()?;
+                provider.resolve_url(source_url.as_str(), "context", deserializer_helper::get_accepted_extensions()).ok()?;
+                completion.detail = source_url.to_string();
+            }
+            Some(completion)
+        })
+        .collect()
+}
+
+// Split a partial path into the directory part (ending in '/') and the start of a name in it
+fn split_directory(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(slash) => path.split_at(slash + 1),
+        None => ("", path)
+    }
+}
+
+// The sub-directories and definition files in 'directory', as completions starting with 'prefix'
+fn entries(directory: &Path, prefix: &str) -> Vec<Completion> {
+    let extensions = deserializer_helper::get_accepted_extensions();
+    let mut completions = vec!();
+
+    if let Ok(read_dir) = fs::read_dir(directory) {
+        for entry in read_dir.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || layout_file::is_layout_file(&name) || compile_options::is_options_file(&name) {
+                continue;
+            }
+
+            let path = entry.path();
+            if path.is_dir() {
+                completions.push(Completion {
+                    value: format!("{}{}/", prefix, name),
+                    detail: "directory".into(),
+                });
+            } else if let Some(extension) = path.extension().map(|e| e.to_string_lossy().to_string()) {
+                if extensions.contains(&extension.as_str()) {
+                    completions.push(Completion {
+                        value: format!("{}{}", prefix, name),
+                        detail: path.display().to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    completions
+}
+
+// Library definitions are referenced without their extension, and are only offered if the provider
+// can resolve the reference
+fn library_reference(mut completion: Completion, provider: &dyn Provider) -> Option<Completion> {
+    if completion.value.ends_with('/') {
+        return Some(completion);
+    }
+
+    if let Some(dot) = completion.value.rfind('.') {
+        completion.value.truncate(dot);
+    }
+    let (resolved, _) = provider.resolve_url(&completion.value, "context", deserializer_helper::get_accepted_extensions()).ok()?;
+    completion.detail = resolved;
+    Some(completion)
+}
+
+// Routes that can be the 'from' or 'to' of a connection in 'flow': the flow's own inputs (or
+// outputs) and the outputs (or inputs) of its processes
+fn routes(url: &str, flow: &Flow, kind: CompletionKind, loaded: Option<&Flow>, provider: &dyn Provider)
+          -> Vec<Completion> {
+    let (direction, flow_ios) = match kind {
+        CompletionKind::From => ("input", &flow.inputs),
+        _ => ("output", &flow.outputs),
+    };
+
+    let mut completions: Vec<Completion> = flow_ios.iter().flatten()
+        .map(|io| route_completion(format!("{}/{}", direction, io.name()), io, "the flow's"))
+        .collect();
+
+    let mut loaded_processes: HashMap<String, Process> = HashMap::new();
+    for process_ref in flow.process_refs.iter().flatten() {
+        let alias = process_ref.alias.to_string();
+
+        // Processes already loaded with the flow are used if they still have the same source
+        let known = loaded.and_then(|loaded_flow| {
+            let loaded_ref = loaded_flow.process_refs.iter().flatten()
+                .find(|loaded_ref| loaded_ref.alias == process_ref.alias)?;
+            if loaded_ref.source == process_ref.source {
+                loaded_flow.subprocesses.get(&process_ref.alias).cloned()
+            } else {
+                None
+            }
+        });
+        let process = match known {
+            Some(process) => Some(process),
+            None => Url::parse(url).ok()
+                .and_then(|base| base.join(&process_ref.source).ok())
+                .and_then(|source_url| loader::load(source_url.as_str(), provider).ok())
+        };
+
+        completions.push(Completion { value: alias.clone(), detail: format!("process '{}'", alias) });
+        if let Some(process) = process {
+            loaded_processes.insert(alias, process);
+        }
+    }
+
+    for (alias, process) in &loaded_processes {
+        let ios: Vec<IO> = match (process, kind) {
+            (FlowProcess(sub_flow), CompletionKind::From) => sub_flow.outputs.iter().flatten().cloned().collect(),
+            (FlowProcess(sub_flow), _) => sub_flow.inputs.iter().flatten().cloned().collect(),
+            (FunctionProcess(function), CompletionKind::From) => function.get_outputs().iter().flatten().cloned().collect(),
+            (FunctionProcess(function), _) => function.get_inputs().iter().flatten().cloned().collect(),
+        };
+        for io in &ios {
+            // The default port of a process with a single port has no name
+            let name = io.name().to_string();
+            if !name.is_empty() {
+                completions.push(route_completion(format!("{}/{}", alias, name), io, &format!("'{}'", alias)));
+            }
+        }
+    }
+
+    completions
+}
+
+fn route_completion(value: String, io: &IO, owner: &str) -> Completion {
+    Completion {
+        value,
+        detail: format!("{} {} of type {}", owner, io.name(), io.datatype(0)),
+    }
+}
@@ -0,0 +1,951 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use flowclib::model::flow::Flow;
+
+use crate::flow_edit;
+use crate::syntax::SourceFormat;
+
+/// Write the processes and connections of 'flow' into its definition 'original', in the format
+/// of the definition. Only the entries of processes and connections that were added, removed or
+/// changed are written, the rest of the text of the definition is kept as it was.
+pub fn write_definition(flow: &Flow, original: &str) -> Result<String, String> {
+    let format = SourceFormat::from_url(&flow.source_url);
+    let mut definition = match parse(format, original)? {
+        Value::Object(map) => map,
+        _ => return Err(format!("The definition of flow '{}' is not a table", flow.name))
+    };
+
+    let original_processes = entries(&definition, "process");
+    let processes: Vec<Value> = flow.process_refs.iter().flatten()
+        .map(|process_ref| {
+            let alias = process_ref.alias.to_string();
+            original_processes.iter()
+                .find(|entry| match entry.get("alias").and_then(Value::as_str) {
+                    Some(entry_alias) => entry_alias == alias,
+                    // A process without an alias was given the name of its definition
+                    None => entry.get("source").and_then(Value::as_str) == Some(&process_ref.source),
+                })
+                .cloned()
+                .unwrap_or_else(|| serde_json::to_value(process_ref).map(flow_edit::without_nulls)
+                    .unwrap_or_else(|_| json!({"alias": alias, "source": process_ref.source})))
+        })
+        .collect();
+    set_entries(&mut definition, "process", processes);
+
+    let original_connections = entries(&definition, "connection");
+    let connections: Vec<Value> = flow.connections.iter().flatten()
+        .map(|connection| {
+            let (from, to) = (connection.from.to_string(), connection.to.to_string());
+            original_connections.iter()
+                .find(|entry| entry.get("from").and_then(Value::as_str) == Some(&from) &&
+                    entry.get("to").and_then(Value::as_str) == Some(&to))
+                .cloned()
+                .unwrap_or_else(|| json!({"from": from, "to": to}))
+        })
+        .collect();
+    set_entries(&mut definition, "connection", connections);
+
+    update(format, original, &Value::Object(definition))
+}
+
+fn entries(definition: &Map<String, Value>, key: &str) -> Vec<Value> {
+    match definition.get(key) {
+        Some(Value::Array(entries)) => entries.clone(),
+        _ => vec!()
+    }
+}
+
+fn set_entries(definition: &mut Map<String, Value>, key: &str, entries: Vec<Value>) {
+    if entries.is_empty() {
+        definition.remove(key);
+    } else {
+        definition.insert(key.into(), Value::Array(entries));
+    }
+}
+
+/// Read the text of a definition in 'format' as a json value
+pub fn parse(format: SourceFormat, text: &str) -> Result<Value, String> {
+    match format {
+        SourceFormat::Toml => {
+            let value: toml::Value = toml::from_str(text).map_err(|e| e.to_string())?;
+            serde_json::to_value(value).map_err(|e| e.to_string())
+        }
+        SourceFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        SourceFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+    }
+}
+
+/// Write a definition, held as a json value, as text in 'format'
+pub fn serialize(format: SourceFormat, definition: &Value) -> Result<String, String> {
+    match format {
+        SourceFormat::Toml => {
+            // toml writes the values of a table before its sub-tables, as it must
+            let value = toml::Value::try_from(definition).map_err(|e| e.to_string())?;
+            toml::to_string_pretty(&value).map_err(|e| e.to_string())
+        }
+        SourceFormat::Json => serde_json::to_string_pretty(definition).map_err(|e| e.to_string()),
+        SourceFormat::Yaml => serde_yaml::to_string(definition).map_err(|e| e.to_string()),
+    }
+}
+
+/// Change the text of the definition 'original', in 'format', so it is the definition 'updated'.
+/// The entries of the lists in the definition, such as its processes and connections, that are
+/// added, removed or changed are written in the text where they are, or after the other entries
+/// of their list when added, and the rest of the text, including its comments, is kept as it was.
+/// A definition that cannot be changed that way, e.g. one written with inline tables, is written
+/// out again in full.
+pub fn update(format: SourceFormat, original: &str, updated: &Value) -> Result<String, String> {
+    let (before, after) = match (parse(format, original)?, updated) {
+        (Value::Object(before), Value::Object(after)) => (before, after.clone()),
+        _ => return serialize(format, updated)
+    };
+
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut text = original.to_string();
+    for key in keys {
+        if before.get(key) == after.get(key) {
+            continue;
+        }
+        let patched = list(before.get(key))
+            .and_then(|before| list(after.get(key)).map(|after| (before, after)))
+            .and_then(|(before, after)| patch_list(format, &text, key, &before, &after));
+        match patched {
+            Some(patched) => text = patched,
+            None => return serialize(format, updated)
+        }
+    }
+
+    // In case the text was not laid out as expected, check it reads as the updated definition
+    match parse(format, &text) {
+        Ok(patched) if without_empty(&patched) == without_empty(updated) => Ok(text),
+        _ => serialize(format, updated)
+    }
+}
+
+// The entries of a list in a definition, with a list that is not there having none
+fn list(value: Option<&Value>) -> Option<Vec<Value>> {
+    match value {
+        None | Some(Value::Null) => Some(vec!()),
+        Some(Value::Array(entries)) => Some(entries.clone()),
+        _ => None
+    }
+}
+
+// A definition without the lists that have no entries, which may be left in the text or not
+fn without_empty(definition: &Value) -> Value {
+    match definition {
+        Value::Object(map) => Value::Object(map.iter()
+            .filter(|(_, value)| !value.is_null() && value.as_array().map_or(true, |entries| !entries.is_empty()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()),
+        _ => definition.clone()
+    }
+}
+
+// What to do with each of the entries of a list to change them from those before to those after
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Keep(usize),
+    Replace(usize, usize),
+    Remove(usize),
+    Insert(usize),
+}
+
+// The steps to change the entries 'before' into the entries 'after', keeping as many as possible
+// of them. Entries removed where others are inserted are replaced by them, so an entry that is
+// changed stays where it was.
+fn steps(before: &[Value], after: &[Value]) -> Vec<Step> {
+    let (m, n) = (before.len(), after.len());
+    // The length of the longest common sequence of the entries from each position on
+    let mut common = vec![vec![0; n + 1]; m + 1];
+    for i in (0..m).rev() {
+        for j in (0..n).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = vec!();
+    let (mut removed, mut inserted) = (vec!(), vec!());
+    let (mut i, mut j) = (0, 0);
+    while i < m || j < n {
+        if i < m && j < n && before[i] == after[j] {
+            pair_up(&mut steps, &mut removed, &mut inserted);
+            steps.push(Step::Keep(i));
+            i += 1;
+            j += 1;
+        } else if j < n && (i == m || common[i][j + 1] >= common[i + 1][j]) {
+            inserted.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+    pair_up(&mut steps, &mut removed, &mut inserted);
+    steps
+}
+
+fn pair_up(steps: &mut Vec<Step>, removed: &mut Vec<usize>, inserted: &mut Vec<usize>) {
+    let pairs = removed.len().min(inserted.len());
+    steps.extend(removed.iter().zip(inserted.iter()).map(|(&i, &j)| Step::Replace(i, j)));
+    steps.extend(removed[pairs..].iter().map(|&i| Step::Remove(i)));
+    steps.extend(inserted[pairs..].iter().map(|&j| Step::Insert(j)));
+    removed.clear();
+    inserted.clear();
+}
+
+// Change the entries of the list 'key' in 'text' from 'before' to 'after', or None if they are
+// not where expected
+fn patch_list(format: SourceFormat, text: &str, key: &str, before: &[Value], after: &[Value]) -> Option<String> {
+    if before.is_empty() && after.is_empty() {
+        return Some(text.to_string());
+    }
+    let steps = steps(before, after);
+    match format {
+        SourceFormat::Toml => {
+            let spans = toml_spans(text, key);
+            if spans.len() != before.len() {
+                return None;
+            }
+            if spans.is_empty() {
+                let entries = after.iter().map(|entry| toml_entry(key, entry)).collect::<Option<Vec<_>>>()?;
+                return Some(append(text, &entries.join("\n"), "\n"));
+            }
+            let fields = |span, before: &Value, after: &Value|
+                patch_fields(before, after, |field| line_field(text, span, field, '='), toml_value);
+            patch_spans(text, &spans, &steps, (before, after), "\n", |entry| toml_entry(key, entry), fields)
+        }
+        SourceFormat::Yaml => {
+            let block = match yaml_block(text, key)? {
+                Some(block) => block,
+                None => {
+                    let entries = after.iter().map(|entry| yaml_entry(entry, 2)).collect::<Option<String>>()?;
+                    return Some(append(text, &format!("{}:\n{}", key, entries), ""));
+                }
+            };
+            if block.spans.len() != before.len() {
+                return None;
+            }
+            if after.is_empty() {
+                let mut patched = text.to_string();
+                patched.replace_range(block.start..block.end, "");
+                return Some(patched);
+            }
+            if block.spans.is_empty() {
+                let entries = after.iter().map(|entry| yaml_entry(entry, 2)).collect::<Option<String>>()?;
+                let mut patched = text.to_string();
+                patched.insert_str(block.items, &entries);
+                return Some(patched);
+            }
+            let indent = block.indent;
+            let fields = |span, before: &Value, after: &Value|
+                patch_fields(before, after, |field| line_field(text, span, field, ':'), yaml_value);
+            patch_spans(text, &block.spans, &steps, (before, after), "", |entry| yaml_entry(entry, indent), fields)
+        }
+        SourceFormat::Json => patch_json(text, key, &steps, before, after),
+    }
+}
+
+// Add 'entries' at the end of 'text', after 'separator'
+fn append(text: &str, entries: &str, separator: &str) -> String {
+    let mut patched = text.to_string();
+    if !patched.is_empty() && !patched.ends_with('\n') {
+        patched.push('\n');
+    }
+    if !patched.trim().is_empty() {
+        patched.push_str(separator);
+    }
+    patched.push_str(entries);
+    patched
+}
+
+// Where an entry of a list is in the text of a toml or yaml definition: from the comments just
+// before it to the start of what follows it, and the lines of the entry itself within that
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    body_start: usize,
+    body_end: usize,
+    end: usize,
+}
+
+impl Span {
+    fn new(text: &str, start: usize, body_start: usize, end: usize) -> Self {
+        let last = body_start + text[body_start..end].trim_end().len();
+        let body_end = text[last..end].find('\n').map_or(end, |newline| last + newline + 1);
+        Span { start, body_start, body_end, end }
+    }
+}
+
+// Change the entries of a list in the text of a toml or yaml definition in place, writing each
+// entry with 'entry' and putting 'separator' between entries that are inserted and the others.
+// Only the values of the fields of an entry that are changed are written, with 'fields', if they
+// can be.
+fn patch_spans<F, G>(text: &str, spans: &[Span], steps: &[Step], (before, after): (&[Value], &[Value]),
+                     separator: &str, entry: F, fields: G) -> Option<String>
+    where F: Fn(&Value) -> Option<String>,
+          G: Fn(Span, &Value, &Value) -> Option<Vec<((usize, usize), String)>> {
+    // The changes to the text, by where they start and end, applied from the end of the text back
+    let mut changes: BTreeMap<(usize, usize), String> = BTreeMap::new();
+    let first_kept = steps.iter().find_map(|step| match step {
+        Step::Keep(i) | Step::Replace(i, _) => Some(spans[*i].start),
+        _ => None
+    }).unwrap_or(spans[0].start);
+    let mut previous = None;
+    for step in steps {
+        match *step {
+            Step::Keep(i) => previous = Some(i),
+            Step::Replace(i, j) => {
+                match fields(spans[i], &before[i], &after[j]) {
+                    Some(values) => changes.extend(values),
+                    None => {
+                        changes.insert((spans[i].body_start, spans[i].body_end), entry(&after[j])?);
+                    }
+                }
+                previous = Some(i);
+            }
+            Step::Remove(i) => {
+                changes.insert((spans[i].start, spans[i].end), String::new());
+            }
+            Step::Insert(j) => {
+                let inserted = entry(&after[j])?;
+                match previous {
+                    Some(i) => changes.entry((spans[i].body_end, spans[i].body_end)).or_default()
+                        .push_str(&format!("{}{}", separator, inserted)),
+                    None => changes.entry((first_kept, first_kept)).or_default()
+                        .push_str(&format!("{}{}", inserted, separator)),
+                }
+            }
+        }
+    }
+
+    let mut patched = text.to_string();
+    for ((start, end), replacement) in changes.iter().rev() {
+        patched.replace_range(start..end, replacement);
+    }
+    // Removing the last entries leaves the blank lines that were before them
+    if !text.ends_with("\n\n") {
+        while patched.ends_with("\n\n") {
+            patched.pop();
+        }
+    }
+    Some(patched)
+}
+
+// The changes to the text of an entry that change the values of its fields from those 'before' to
+// those 'after': where each value is, found with 'field', and its new value written with 'value'.
+// None if fields are added or removed, or a value that is a table or list is changed, when the
+// entry has to be written again.
+fn patch_fields<F, G>(before: &Value, after: &Value, field: F, value: G) -> Option<Vec<((usize, usize), String)>>
+    where F: Fn(&str) -> Option<(usize, usize)>,
+          G: Fn(&Value) -> Option<String> {
+    let (before, after) = (before.as_object()?, after.as_object()?);
+    if before.len() != after.len() || before.keys().any(|key| !after.contains_key(key)) {
+        return None;
+    }
+    let is_scalar = |value: &Value| !value.is_object() && !value.is_array();
+    before.iter()
+        .filter(|(key, old)| after.get(*key) != Some(*old))
+        .map(|(key, old)| {
+            let new = after.get(key)?;
+            if !is_scalar(old) || !is_scalar(new) {
+                return None;
+            }
+            Some((field(key)?, value(new)?))
+        })
+        .collect()
+}
+
+// The start and end, including the newline, of each line of 'text'
+fn lines(text: &str) -> Vec<(usize, usize)> {
+    let mut lines = vec!();
+    let mut start = 0;
+    while start < text.len() {
+        let end = text[start..].find('\n').map_or(text.len(), |newline| start + newline + 1);
+        lines.push((start, end));
+        start = end;
+    }
+    lines
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+// The start of the comment lines just before the line at 'index', or of the line if there are none
+fn leading_comments(text: &str, lines: &[(usize, usize)], index: usize) -> usize {
+    let mut first = index;
+    while first > 0 && is_comment(&text[lines[first - 1].0..lines[first - 1].1]) {
+        first -= 1;
+    }
+    lines[first].0
+}
+
+// The entries of the array of tables 'key' in the text of a toml definition, each of which runs
+// from its "[[key]]" header, and those of its sub-tables, to the next header of another table
+fn toml_spans(text: &str, key: &str) -> Vec<Span> {
+    let lines = lines(text);
+    let sub_table = format!("{}.", key);
+    let mut spans = vec!();
+    let mut entry: Option<(usize, usize)> = None;
+    for (index, &(start, end)) in lines.iter().enumerate() {
+        let header = text[start..end].split('#').next().unwrap_or_default().trim();
+        if !header.starts_with('[') {
+            continue;
+        }
+        let name = header.trim_matches(|c: char| c == '[' || c == ']').trim();
+        if name.starts_with(&sub_table) {
+            continue;
+        }
+
+        let boundary = leading_comments(text, &lines, index);
+        if let Some((entry_start, body_start)) = entry.take() {
+            spans.push(Span::new(text, entry_start, body_start, boundary));
+        }
+        if header.starts_with("[[") && name == key {
+            entry = Some((boundary, start));
+        }
+    }
+    if let Some((entry_start, body_start)) = entry {
+        spans.push(Span::new(text, entry_start, body_start, text.len()));
+    }
+    spans
+}
+
+// A toml entry of the array of tables 'key'
+fn toml_entry(key: &str, entry: &Value) -> Option<String> {
+    let mut table = Map::new();
+    table.insert(key.into(), Value::Array(vec!(entry.clone())));
+    let value = toml::Value::try_from(Value::Object(table)).ok()?;
+    // Written without toml's pretty printing, so strings are in double quotes as in flow definitions
+    toml::to_string(&value).ok().map(|text| format!("{}\n", text.trim()))
+}
+
+// A value of a field of a toml entry, as written after the '='
+fn toml_value(value: &Value) -> Option<String> {
+    let mut table = Map::new();
+    table.insert("value".into(), value.clone());
+    let text = toml::to_string(&toml::Value::try_from(Value::Object(table)).ok()?).ok()?;
+    text.trim().strip_prefix("value = ").map(String::from)
+}
+
+// The sequence that is the value of 'key' in the text of a yaml definition
+struct Block {
+    // From the comments before the key to the start of what follows its entries
+    start: usize,
+    end: usize,
+    // Where the entries start, after the line with the key
+    items: usize,
+    indent: usize,
+    spans: Vec<Span>,
+}
+
+// The sequence 'key' in the text of a yaml definition, with an entry starting at each "- " line.
+// Some(None) if 'key' is not in the definition, None if its value is not written as a sequence
+// of entries on the lines after it.
+fn yaml_block(text: &str, key: &str) -> Option<Option<Block>> {
+    let lines = lines(text);
+    let key_line = lines.iter().position(|&(start, end)| {
+        let line = &text[start..end];
+        !line.starts_with(char::is_whitespace) && line.split(':').next().map(|name| name.trim_matches(|c: char| c == '"' || c == '\'')) == Some(key)
+    });
+    let key_line = match key_line {
+        Some(key_line) => key_line,
+        None => return Some(None)
+    };
+    let (key_start, key_end) = lines[key_line];
+    let value = text[key_start..key_end].splitn(2, ':').nth(1).unwrap_or_default();
+    if !value.split('#').next().unwrap_or_default().trim().is_empty() {
+        return None;
+    }
+
+    let mut block = Block { start: leading_comments(text, &lines, key_line), end: text.len(), items: key_end, indent: 2, spans: vec!() };
+    let mut indent = None;
+    let mut entry: Option<(usize, usize)> = None;
+    for (index, &(start, end)) in lines.iter().enumerate().skip(key_line + 1) {
+        let line = &text[start..end];
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let line_indent = line.len() - line.trim_start().len();
+        let is_entry = trimmed == "-" || trimmed.starts_with("- ");
+        if indent.is_none() {
+            if is_entry {
+                indent = Some(line_indent);
+            } else if line_indent == 0 {
+                // The value of the key is empty
+                block.end = leading_comments(text, &lines, index);
+                break;
+            } else {
+                return None;
+            }
+        }
+        let entry_indent = indent.unwrap_or_default();
+
+        if is_entry && line_indent == entry_indent {
+            let boundary = leading_comments(text, &lines, index);
+            if let Some((entry_start, body_start)) = entry.take() {
+                block.spans.push(Span::new(text, entry_start, body_start, boundary));
+            }
+            entry = Some((boundary, start));
+        } else if line_indent < entry_indent || (line_indent == entry_indent && !is_entry) {
+            block.end = leading_comments(text, &lines, index);
+            break;
+        }
+    }
+    if let Some((entry_start, body_start)) = entry {
+        block.spans.push(Span::new(text, entry_start, body_start, block.end));
+    }
+    block.indent = indent.unwrap_or(2);
+    Some(Some(block))
+}
+
+// A yaml entry of a sequence, indented by 'indent'
+fn yaml_entry(entry: &Value, indent: usize) -> Option<String> {
+    let text = serde_yaml::to_string(entry).ok()?;
+    let text = text.trim_start_matches("---").trim();
+    let indent = " ".repeat(indent);
+    Some(text.lines().enumerate()
+        .map(|(line_number, line)| match line_number {
+            0 => format!("{}- {}\n", indent, line),
+            _ => format!("{}  {}\n", indent, line)
+        })
+        .collect())
+}
+
+// A value of a field of a yaml entry that fits on the line of the field
+fn yaml_value(value: &Value) -> Option<String> {
+    let text = serde_yaml::to_string(value).ok()?;
+    let text = text.trim_start_matches("---").trim();
+    if text.contains('\n') {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+// Change the entries of the array 'key' in the text of a json definition, keeping the text of
+// the entries that are not changed
+fn patch_json(text: &str, key: &str, steps: &[Step], before: &[Value], after: &[Value]) -> Option<String> {
+    let (members, close) = json_members(text)?;
+    let entry = |value: &Value, indent: &str| serde_json::to_string_pretty(value).ok()
+        .map(|entry| entry.replace('\n', &format!("\n{}", indent)));
+    let mut patched = text.to_string();
+
+    match members.iter().find(|member| member.key == key) {
+        Some(member) => {
+            if !text[member.value_start..].starts_with('[') {
+                return None;
+            }
+            let elements = json_elements(text, member.value_start)?;
+            if elements.len() != before.len() {
+                return None;
+            }
+            let close_indent = indentation(text, member.start);
+            let indent = elements.first().map(|&(start, _)| indentation(text, start).to_string())
+                .unwrap_or_else(|| format!("{}  ", close_indent));
+
+            let mut entries = vec!();
+            for step in steps {
+                match *step {
+                    Step::Keep(i) => entries.push(text[elements[i].0..elements[i].1].to_string()),
+                    Step::Replace(i, j) => match json_fields(text, elements[i], &before[i], &after[j]) {
+                        Some(fields) => entries.push(fields),
+                        None => entries.push(entry(&after[j], &indent)?)
+                    },
+                    Step::Insert(j) => entries.push(entry(&after[j], &indent)?),
+                    Step::Remove(_) => {}
+                }
+            }
+            let inside = if entries.is_empty() {
+                String::new()
+            } else {
+                format!("\n{}{}\n{}", indent, entries.join(&format!(",\n{}", indent)), close_indent)
+            };
+            patched.replace_range(member.value_start + 1..member.value_end - 1, &inside);
+        }
+        None => {
+            if after.is_empty() {
+                return Some(patched);
+            }
+            let indent = members.first().map(|member| indentation(text, member.start).to_string())
+                .unwrap_or_else(|| "  ".into());
+            let entry_indent = format!("{}  ", indent);
+            let entries = after.iter().map(|value| entry(value, &entry_indent)).collect::<Option<Vec<_>>>()?;
+            let added = format!("\"{}\": [\n{}{}\n{}]", key, entry_indent,
+                                entries.join(&format!(",\n{}", entry_indent)), indent);
+            match members.last() {
+                Some(last) => patched.insert_str(last.value_end, &format!(",\n{}{}", indent, added)),
+                None => patched.insert_str(close, &format!("\n{}{}\n", indent, added)),
+            }
+        }
+    }
+    Some(patched)
+}
+
+// The text of the json entry from 'start' to 'end', with the values of its fields that are changed
+// from those 'before' to those 'after' written where they are
+fn json_fields(text: &str, (start, end): (usize, usize), before: &Value, after: &Value) -> Option<String> {
+    let (members, _) = json_object(text, start)?;
+    let field = |key: &str| members.iter().find(|member| member.key == key)
+        .map(|member| (member.value_start - start, member.value_end - start));
+    let mut values = patch_fields(before, after, field, |value| serde_json::to_string(value).ok())?;
+    values.sort_by_key(|&(position, _)| position);
+
+    let mut entry = text[start..end].to_string();
+    for ((value_start, value_end), value) in values.iter().rev() {
+        entry.replace_range(value_start..value_end, value);
+    }
+    Some(entry)
+}
+
+/// Where the value of 'field' in entry 'index' of the list 'key' is in the text of a definition
+/// in 'format', as the start and end, in bytes, of the value without any quotes around it. None
+/// if the entry, or the field in it, cannot be found.
+pub fn find_value(format: SourceFormat, text: &str, key: &str, index: usize, field: &str) -> Option<(usize, usize)> {
+    match format {
+        SourceFormat::Toml => line_value(text, *toml_spans(text, key).get(index)?, field, '='),
+        SourceFormat::Yaml => line_value(text, *yaml_block(text, key)??.spans.get(index)?, field, ':'),
+        SourceFormat::Json => {
+            let (members, _) = json_members(text)?;
+            let list = members.iter().find(|member| member.key == key)?;
+            if !text[list.value_start..].starts_with('[') {
+                return None;
+            }
+            let (entry_start, _) = *json_elements(text, list.value_start)?.get(index)?;
+            let (fields, _) = json_object(text, entry_start)?;
+            let value = fields.iter().find(|member| member.key == field)?;
+            Some(unquoted(text, value.value_start, value.value_end))
+        }
+    }
+}
+
+// The value of 'field', on a line of its own in an entry of a toml or yaml definition, where the
+// field's name is followed by 'separator', without any quotes around it
+fn line_value(text: &str, span: Span, field: &str, separator: char) -> Option<(usize, usize)> {
+    line_field(text, span, field, separator).map(|(start, end)| unquoted(text, start, end))
+}
+
+// The value of 'field' as it is written on its line in an entry, up to any comment after it
+fn line_field(text: &str, span: Span, field: &str, separator: char) -> Option<(usize, usize)> {
+    let mut line_start = span.body_start;
+    for line in text[span.body_start..span.body_end].split('\n') {
+        let name = line.trim_start().trim_start_matches('-').trim_start();
+        let rest = name.strip_prefix(field)
+            .or_else(|| name.strip_prefix(&format!("\"{}\"", field)))
+            .map(str::trim_start);
+        if let Some(value) = rest.and_then(|rest| rest.strip_prefix(separator)) {
+            let value = value.trim_start();
+            let value_start = line_start + line.len() - value.len();
+            let value_end = match value.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => value[1..].find(quote).map_or(value.len(), |end| end + 2),
+                _ => value.split(" #").next().unwrap_or(value).trim_end().len()
+            };
+            return Some((value_start, value_start + value_end));
+        }
+        line_start += line.len() + 1;
+    }
+    None
+}
+
+// The text from 'start' to 'end' without the quotes around it, if it is quoted
+fn unquoted(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let value = &text[start..end];
+    if value.len() >= 2 && (value.starts_with('"') && value.ends_with('"') || value.starts_with('\'') && value.ends_with('\'')) {
+        (start + 1, end - 1)
+    } else {
+        (start, end)
+    }
+}
+
+// A member of a json object
+struct Member {
+    key: String,
+    start: usize,
+    value_start: usize,
+    value_end: usize,
+}
+
+// The members of the top-level object of a json definition, and where its closing brace is
+fn json_members(text: &str) -> Option<(Vec<Member>, usize)> {
+    json_object(text, skip_whitespace(text.as_bytes(), 0))
+}
+
+// The members of the json object starting at 'open', and where its closing brace is
+fn json_object(text: &str, open: usize) -> Option<(Vec<Member>, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.get(open) != Some(&b'{') {
+        return None;
+    }
+    let mut i = open + 1;
+
+    let mut members = vec!();
+    loop {
+        i = skip_whitespace(bytes, i);
+        if *bytes.get(i)? == b'}' {
+            return Some((members, i));
+        }
+        let start = i;
+        let key_end = skip_string(bytes, start)?;
+        i = skip_whitespace(bytes, key_end);
+        if *bytes.get(i)? != b':' {
+            return None;
+        }
+        let value_start = skip_whitespace(bytes, i + 1);
+        let value_end = skip_value(bytes, value_start)?;
+        members.push(Member { key: text[start + 1..key_end - 1].to_string(), start, value_start, value_end });
+
+        i = skip_whitespace(bytes, value_end);
+        match *bytes.get(i)? {
+            b',' => i += 1,
+            b'}' => return Some((members, i)),
+            _ => return None
+        }
+    }
+}
+
+// The start and end of each element of the json array starting at 'open'
+fn json_elements(text: &str, open: usize) -> Option<Vec<(usize, usize)>> {
+    let bytes = text.as_bytes();
+    let mut elements = vec!();
+    let mut i = open + 1;
+    loop {
+        i = skip_whitespace(bytes, i);
+        if *bytes.get(i)? == b']' {
+            return Some(elements);
+        }
+        let end = skip_value(bytes, i)?;
+        elements.push((i, end));
+        i = skip_whitespace(bytes, end);
+        match *bytes.get(i)? {
+            b',' => i += 1,
+            b']' => return Some(elements),
+            _ => return None
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+// The end of the json string starting at 'i'
+fn skip_string(bytes: &[u8], mut i: usize) -> Option<usize> {
+    if bytes.get(i) != Some(&b'"') {
+        return None;
+    }
+    i += 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1
+        }
+    }
+    None
+}
+
+// The end of the json value starting at 'i'
+fn skip_value(bytes: &[u8], mut i: usize) -> Option<usize> {
+    match *bytes.get(i)? {
+        b'"' => skip_string(bytes, i),
+        b'{' | b'[' => {
+            let mut depth = 0;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'"' => {
+                        i = skip_string(bytes, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            None
+        }
+        _ => {
+            while i < bytes.len() && !matches!(bytes[i], b',' | b'}' | b']') && !bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            Some(i)
+        }
+    }
+}
+
+// The whitespace at the start of the line that 'position' is in
+fn indentation(text: &str, position: usize) -> &str {
+    let line_start = text[..position].rfind('\n').map_or(0, |newline| newline + 1);
+    let line = &text[line_start..];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::syntax::SourceFormat;
+
+    use super::{find_value, parse, update};
+
+    const TOML_FLOW: &str = "# A flow that says hello
+flow = \"hello\"
+
+# Who to say hello to
+[[process]]
+alias = \"name\" # the name
+source = \"lib://flowstdlib/data/buffer\"
+
+# Print the greeting
+[[process]]
+source = \"lib://flowruntime/stdio/stdout\"
+
+[[connection]]
+from = \"name\"
+to = \"stdout\"
+";
+
+    const YAML_FLOW: &str = "# A flow that says hello
+flow: hello
+process:
+  # Who to say hello to
+  - alias: name
+    source: buffer
+  - source: stdout
+connection:
+  - from: name
+    to: stdout
+";
+
+    // The definition 'text' with 'edit' made to its processes
+    fn edited(format: SourceFormat, text: &str, edit: impl Fn(&mut Vec<Value>)) -> Value {
+        let mut definition = parse(format, text).unwrap();
+        edit(definition.get_mut("process").and_then(Value::as_array_mut).unwrap());
+        definition
+    }
+
+    #[test]
+    fn toml_process_added_after_the_others() {
+        let definition = edited(SourceFormat::Toml, TOML_FLOW,
+                                |processes| processes.push(json!({"alias": "count", "source": "count.toml"})));
+
+        let expected = TOML_FLOW.replace("stdout\"\n\n[[connection]]",
+                                         "stdout\"\n\n[[process]]\nalias = \"count\"\nsource = \"count.toml\"\n\n[[connection]]");
+        assert_eq!(update(SourceFormat::Toml, TOML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn toml_process_removed_with_its_comments() {
+        let definition = edited(SourceFormat::Toml, TOML_FLOW, |processes| { processes.remove(0); });
+
+        let expected = TOML_FLOW.replace("# Who to say hello to\n[[process]]\nalias = \"name\" # the name\n\
+            source = \"lib://flowstdlib/data/buffer\"\n\n", "");
+        assert_eq!(update(SourceFormat::Toml, TOML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn toml_changed_process_stays_where_it_was() {
+        let definition = edited(SourceFormat::Toml, TOML_FLOW,
+                                |processes| processes[0]["alias"] = json!("who"));
+
+        let expected = TOML_FLOW.replace("alias = \"name\" # the name", "alias = \"who\" # the name");
+        assert_eq!(update(SourceFormat::Toml, TOML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn toml_process_with_a_field_added_written_again() {
+        let definition = edited(SourceFormat::Toml, TOML_FLOW,
+                                |processes| processes[1]["alias"] = json!("print"));
+
+        let expected = TOML_FLOW.replace("[[process]]\nsource = \"lib://flowruntime/stdio/stdout\"",
+                                         "[[process]]\nalias = \"print\"\nsource = \"lib://flowruntime/stdio/stdout\"");
+        assert_eq!(update(SourceFormat::Toml, TOML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn toml_connections_removed() {
+        let mut definition = parse(SourceFormat::Toml, TOML_FLOW).unwrap();
+        definition.as_object_mut().unwrap().remove("connection");
+
+        let expected = TOML_FLOW.replace("\n[[connection]]\nfrom = \"name\"\nto = \"stdout\"\n", "");
+        assert_eq!(update(SourceFormat::Toml, TOML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn yaml_process_removed_and_added() {
+        let definition = edited(SourceFormat::Yaml, YAML_FLOW, |processes| {
+            processes.remove(0);
+            processes.push(json!({"alias": "count", "source": "count"}));
+        });
+
+        let expected = YAML_FLOW
+            .replace("  # Who to say hello to\n  - alias: name\n    source: buffer\n", "")
+            .replace("  - source: stdout\n", "  - source: stdout\n  - alias: count\n    source: count\n");
+        assert_eq!(update(SourceFormat::Yaml, YAML_FLOW, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn yaml_changed_process_keeps_its_comments() {
+        let text = YAML_FLOW.replace("source: buffer", "source: buffer # the names");
+        let definition = edited(SourceFormat::Yaml, &text, |processes| processes[0]["source"] = json!("names"));
+
+        let expected = text.replace("source: buffer # the names", "source: names # the names");
+        assert_eq!(update(SourceFormat::Yaml, &text, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn json_changed_process_keeps_its_layout() {
+        let original = "{\n  \"process\": [\n    {\"alias\": \"a\",  \"source\": \"stdout\"}\n  ]\n}\n";
+        let definition = edited(SourceFormat::Json, original, |processes| processes[0]["alias"] = json!("b"));
+
+        let expected = original.replace("\"a\"", "\"b\"");
+        assert_eq!(update(SourceFormat::Json, original, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn json_process_added_keeping_the_layout_of_the_others() {
+        let original = "{\n  \"flow\": \"hello\",\n  \"process\": [\n    {\"source\": \"stdout\"}\n  ]\n}\n";
+        let definition = edited(SourceFormat::Json, original,
+                                |processes| processes.push(json!({"source": "count"})));
+
+        let expected = "{\n  \"flow\": \"hello\",\n  \"process\": [\n    {\"source\": \"stdout\"},\n    {\n      \"source\": \"count\"\n    }\n  ]\n}\n";
+        assert_eq!(update(SourceFormat::Json, original, &definition).unwrap(), expected);
+    }
+
+    #[test]
+    fn value_found_in_its_entry() {
+        let (start, end) = find_value(SourceFormat::Toml, TOML_FLOW, "connection", 0, "to").unwrap();
+        assert_eq!(&TOML_FLOW[start..end], "stdout");
+        assert!(start > TOML_FLOW.find("[[connection]]").unwrap());
+
+        let (start, end) = find_value(SourceFormat::Toml, TOML_FLOW, "process", 0, "alias").unwrap();
+        assert_eq!(&TOML_FLOW[start..end], "name");
+
+        let (start, end) = find_value(SourceFormat::Yaml, YAML_FLOW, "process", 1, "source").unwrap();
+        assert_eq!(&YAML_FLOW[start..end], "stdout");
+        assert!(start < YAML_FLOW.find("connection").unwrap());
+
+        let json = "{\"process\": [{\"source\": \"a\"}, {\"alias\": \"b\", \"source\": \"b\"}]}";
+        let (start, end) = find_value(SourceFormat::Json, json, "process", 1, "source").unwrap();
+        assert_eq!((&json[start..end], start), ("b", json.rfind("\"b\"").unwrap() + 1));
+    }
+}
@@ -0,0 +1,43 @@
+/// How a line of the text after a change differs from the text before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineChange {
+    Same,
+    Added,
+    Removed,
+}
+
+/// Compare two texts line by line, returning all the lines of both in order with how each changed.
+/// Definitions are small, so the longest common sequence of lines is found directly.
+pub fn line_diff(before: &str, after: &str) -> Vec<(LineChange, String)> {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+
+    // common[i][j] is the length of the longest common sequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec!();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((LineChange::Same, old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push((LineChange::Added, new[j].to_string()));
+            j += 1;
+        } else {
+            lines.push((LineChange::Removed, old[i].to_string()));
+            i += 1;
+        }
+    }
+    lines
+}
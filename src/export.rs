@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::Path;
+
+use cairo::{Context, Format, ImageSurface, SvgSurface};
+use image::{Rgba, RgbaImage};
+
+use crate::flow_graph::{self, Endpoint, FlowGraph, Node};
+use crate::graph_painter::{self, Highlight};
+
+/// Write the graph to 'path' in the format indicated by the file's extension:
+/// "svg", "png" or "dot" (Graphviz)
+pub fn export_graph(graph: &FlowGraph, path: &Path) -> Result<(), String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "svg" => write_svg(graph, path),
+        "png" => write_png(graph, path),
+        "dot" | "gv" => fs::write(path, to_dot(graph)).map_err(|e| e.to_string()),
+        _ => Err(format!("Cannot export graph to '{}', use a '.svg', '.png' or '.dot' file",
+                         path.display()))
+    }
+}
+
+fn write_svg(graph: &FlowGraph, path: &Path) -> Result<(), String> {
+    let surface = SvgSurface::new(graph.bounds.width, graph.bounds.height, Some(path))
+        .map_err(|e| format!("Could not create SVG file: {:?}", e))?;
+    {
+        let cr = Context::new(&surface);
+        graph_painter::paint(&cr, graph, &Highlight::default());
+    }
+    surface.finish();
+    Ok(())
+}
+
+// Draw the graph on a cairo image surface and save its pixels as PNG using the `image` crate
+fn write_png(graph: &FlowGraph, path: &Path) -> Result<(), String> {
+    let width = graph.bounds.width.ceil() as i32;
+    let height = graph.bounds.height.ceil() as i32;
+    let surface = ImageSurface::create(Format::ARgb32, width, height)
+        .map_err(|e| format!("Could not create image of the graph: {:?}", e))?;
+    {
+        let cr = Context::new(&surface);
+        graph_painter::paint(&cr, graph, &Highlight::default());
+    }
+
+    let stride = surface.get_stride() as usize;
+    let mut image = RgbaImage::new(width as u32, height as u32);
+    surface.with_data(|data| {
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            // cairo's ARgb32 is a native-endian u32 with the color premultiplied by alpha
+            let offset = y as usize * stride + x as usize * 4;
+            let argb = u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+            let alpha = (argb >> 24) as u8;
+            let unpremultiply = |channel: u32| if alpha == 0 {
+                0
+            } else {
+                ((channel & 0xff) * 255 / alpha as u32) as u8
+            };
+            *pixel = Rgba([unpremultiply(argb >> 16), unpremultiply(argb >> 8), unpremultiply(argb), alpha]);
+        }
+    }).map_err(|e| format!("Could not read image of the graph: {:?}", e))?;
+
+    image.save(path).map_err(|e| e.to_string())
+}
+
+/// Describe the graph in Graphviz "dot" format, with each process as a record with its inputs
+/// on the left and outputs on the right
+pub fn to_dot(graph: &FlowGraph) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", escape_quoted(&graph.name));
+    dot.push_str("    rankdir=LR;\n");
+    dot.push_str("    node [shape=record, fontname=\"Sans\", fontsize=10];\n");
+
+    for input in &graph.inputs {
+        dot.push_str(&format!("    \"input/{}\" [shape=circle, label=\"{}\"];\n",
+                              escape_quoted(&input.name), escape_quoted(&input.name)));
+    }
+    for output in &graph.outputs {
+        dot.push_str(&format!("    \"output/{}\" [shape=doublecircle, label=\"{}\"];\n",
+                              escape_quoted(&output.name), escape_quoted(&output.name)));
+    }
+
+    for node in &graph.nodes {
+        dot.push_str(&format!("    \"{}\" [label=\"{}\"{}];\n", escape_quoted(&node.alias), record_label(node),
+                              if node.sub_graph.is_some() { ", style=filled, fillcolor=\"#eef7e6\"" } else { "" }));
+    }
+
+    for edge in &graph.edges {
+        dot.push_str(&format!("    {} -> {};\n", dot_endpoint(graph, &edge.from), dot_endpoint(graph, &edge.to)));
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+// A record label of the form "{{<i0> a|<i1> b}|alias\nname|{<o0> c}}"
+fn record_label(node: &Node) -> String {
+    let ports = |prefix: &str, names: Vec<&String>| names.iter().enumerate()
+        .map(|(index, name)| format!("<{}{}> {}", prefix, index, escape_record(name)))
+        .collect::<Vec<String>>()
+        .join("|");
+
+    let mut fields = vec!();
+    if !node.inputs.is_empty() {
+        fields.push(format!("{{{}}}", ports("i", node.inputs.iter().map(|p| &p.name).collect())));
+    }
+    fields.push(format!("{}\\n{}", escape_record(&node.alias), escape_record(&node.name)));
+    if !node.outputs.is_empty() {
+        fields.push(format!("{{{}}}", ports("o", node.outputs.iter().map(|p| &p.name).collect())));
+    }
+    format!("{{{}}}", fields.join("|"))
+}
+
+fn dot_endpoint(graph: &FlowGraph, endpoint: &Endpoint) -> String {
+    match endpoint {
+        Endpoint::FlowInput(name) => format!("\"input/{}\"", escape_quoted(name)),
+        Endpoint::FlowOutput(name) => format!("\"output/{}\"", escape_quoted(name)),
+        Endpoint::NodeInput(index, port) => {
+            let node = &graph.nodes[*index];
+            match flow_graph::find_port(&node.inputs, port) {
+                Some(port_index) => format!("\"{}\":i{}", escape_quoted(&node.alias), port_index),
+                None => format!("\"{}\"", escape_quoted(&node.alias))
+            }
+        }
+        Endpoint::NodeOutput(index, port) => {
+            let node = &graph.nodes[*index];
+            match flow_graph::find_port(&node.outputs, port) {
+                Some(port_index) => format!("\"{}\":o{}", escape_quoted(&node.alias), port_index),
+                None => format!("\"{}\"", escape_quoted(&node.alias))
+            }
+        }
+    }
+}
+
+fn escape_quoted(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Characters that have a meaning inside a record label must be escaped
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "{}|<>\"\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
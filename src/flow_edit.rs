@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use serde_json::{json, Value};
+use url::Url;
+
+use flowclib::compiler::loader;
+use flowclib::model::connection::Connection;
+use flowclib::model::flow::Flow;
+use flowclib::model::process::Process::FlowProcess;
+use flowclib::model::process_reference::ProcessReference;
+use provider::content::provider::Provider;
+
+/// An edit of the processes and connections in the definition of a flow
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowEdit {
+    AddProcess { alias: String, source: String },
+    // Removing a process also removes the connections to and from it
+    RemoveProcess { alias: String },
+    AddConnection { from: String, to: String },
+    RemoveConnection { from: String, to: String },
+    // Add copies of the processes in a fragment, renaming any whose alias is already used
+    Paste { fragment: Fragment },
+    // Several edits made together, that are undone together
+    Batch { description: String, edits: Vec<FlowEdit> },
+}
+
+/// Processes, and the connections between them, copied from a flow so they can be pasted into
+/// the same flow or another one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    // The url of the flow they were copied from, that the sources of the processes are relative to
+    pub flow_url: String,
+    // The process references as they would be written in a definition
+    pub processes: Vec<Value>,
+    pub connections: Vec<(String, String)>,
+}
+
+impl Fragment {
+    /// The aliases of the processes in the fragment
+    pub fn aliases(&self) -> Vec<String> {
+        self.processes.iter()
+            .filter_map(|process| process.get("alias").and_then(Value::as_str).map(|alias| alias.to_string()))
+            .collect()
+    }
+}
+
+impl fmt::Display for FlowEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowEdit::AddProcess { alias, .. } => write!(f, "Add process '{}'", alias),
+            FlowEdit::RemoveProcess { alias } => write!(f, "Remove process '{}'", alias),
+            FlowEdit::AddConnection { from, to } => write!(f, "Connect '{}' to '{}'", from, to),
+            FlowEdit::RemoveConnection { from, to } => write!(f, "Disconnect '{}' from '{}'", from, to),
+            FlowEdit::Paste { fragment } => match fragment.processes.len() {
+                1 => write!(f, "Paste a process"),
+                count => write!(f, "Paste {} processes", count),
+            },
+            FlowEdit::Batch { description, .. } => write!(f, "{}", description),
+        }
+    }
+}
+
+/// Apply an edit to 'flow', loading any process it adds with 'provider' so the flow remains
+/// complete. The flow is left unchanged if the edit cannot be made.
+pub fn apply(flow: &mut Flow, edit: &FlowEdit, provider: &dyn Provider) -> Result<(), String> {
+    match edit {
+        FlowEdit::AddProcess { alias, source } => {
+            if alias.is_empty() || alias.contains('/') {
+                return Err(format!("'{}' is not a valid alias for a process", alias));
+            }
+            if has_process(flow, alias) {
+                return Err(format!("Flow '{}' already has a process called '{}'", flow.name, alias));
+            }
+
+            add_process(flow, alias, json!({"alias": alias, "source": source}), provider)?;
+        }
+        FlowEdit::RemoveProcess { alias } => {
+            if !has_process(flow, alias) {
+                return Err(format!("Flow '{}' has no process called '{}'", flow.name, alias));
+            }
+
+            if let Some(process_refs) = flow.process_refs.as_mut() {
+                process_refs.retain(|process_ref| process_ref.alias.to_string() != *alias);
+            }
+            flow.subprocesses.retain(|name, _| name.to_string() != *alias);
+            if let Some(connections) = flow.connections.as_mut() {
+                connections.retain(|connection| route_alias(&connection.from.to_string()) != alias &&
+                    route_alias(&connection.to.to_string()) != alias);
+            }
+        }
+        FlowEdit::AddConnection { from, to } => {
+            if has_connection(flow, from, to) {
+                return Err(format!("'{}' is already connected to '{}'", from, to));
+            }
+
+            let connection: Connection = serde_json::from_value(json!({"from": from, "to": to}))
+                .map_err(|e| e.to_string())?;
+            flow.connections.get_or_insert_with(Vec::new).push(connection);
+        }
+        FlowEdit::RemoveConnection { from, to } => {
+            if !has_connection(flow, from, to) {
+                return Err(format!("'{}' is not connected to '{}'", from, to));
+            }
+
+            if let Some(connections) = flow.connections.as_mut() {
+                connections.retain(|connection| !(connection.from.to_string() == *from &&
+                    connection.to.to_string() == *to));
+            }
+        }
+        FlowEdit::Paste { fragment } => {
+            let existing: Vec<String> = flow.process_refs.iter().flatten()
+                .map(|process_ref| process_ref.alias.to_string()).collect();
+            let renames = paste_aliases(&existing, fragment);
+            let mut pasted = flow.clone();
+
+            for process in &fragment.processes {
+                let mut process = process.clone();
+                let alias = process.get("alias").and_then(Value::as_str).unwrap_or("").to_string();
+                let source = process.get("source").and_then(Value::as_str).unwrap_or("").to_string();
+                let new_alias = renames.get(&alias).cloned().unwrap_or(alias);
+                process["alias"] = Value::String(new_alias.clone());
+                process["source"] = Value::String(rebase_source(&fragment.flow_url, &flow.source_url, &source));
+                add_process(&mut pasted, &new_alias, process, provider)?;
+            }
+
+            for (from, to) in &fragment.connections {
+                let connection: Connection = serde_json::from_value(json!({
+                    "from": rename_route(from, &renames), "to": rename_route(to, &renames)}))
+                    .map_err(|e| e.to_string())?;
+                pasted.connections.get_or_insert_with(Vec::new).push(connection);
+            }
+
+            *flow = pasted;
+        }
+        FlowEdit::Batch { edits, .. } => {
+            // Apply all of the edits or none of them
+            let mut edited = flow.clone();
+            for edit in edits {
+                apply(&mut edited, edit, provider)?;
+            }
+            *flow = edited;
+        }
+    }
+
+    Ok(())
+}
+
+// Add the process referenced by 'process_ref', as it would be written in a definition, to 'flow'.
+// The reference is built the same way the deserializers would build it from a definition.
+fn add_process(flow: &mut Flow, alias: &str, process_ref: Value, provider: &dyn Provider) -> Result<(), String> {
+    let process_ref: ProcessReference = serde_json::from_value(process_ref).map_err(|e| e.to_string())?;
+    let source_url = Url::parse(&flow.source_url).and_then(|base| base.join(&process_ref.source))
+        .map_err(|e| format!("Could not find source '{}': {}", process_ref.source, e))?;
+    let process = loader::load(source_url.as_str(), provider)
+        .map_err(|e| format!("Could not load process '{}' from '{}': {}", alias, process_ref.source, e))?;
+
+    flow.subprocesses.insert(process_ref.alias.clone(), process);
+    flow.process_refs.get_or_insert_with(Vec::new).push(process_ref);
+    Ok(())
+}
+
+/// Copy the processes of 'flow' with 'aliases', and the connections between them, into a fragment
+pub fn copy(flow: &Flow, aliases: &[String]) -> Result<Fragment, String> {
+    let processes = flow.process_refs.iter().flatten()
+        .filter(|process_ref| aliases.contains(&process_ref.alias.to_string()))
+        .map(|process_ref| serde_json::to_value(process_ref).map(without_nulls).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<Value>, String>>()?;
+
+    let connections = flow.connections.iter().flatten()
+        .map(|connection| (connection.from.to_string(), connection.to.to_string()))
+        .filter(|(from, to)| aliases.iter().any(|alias| alias == route_alias(from)) &&
+            aliases.iter().any(|alias| alias == route_alias(to)))
+        .collect();
+
+    Ok(Fragment { flow_url: flow.source_url.clone(), processes, connections })
+}
+
+/// A process reference as it would be written in a definition: without the fields that are not set
+pub fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect()),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value
+    }
+}
+
+/// The aliases the processes of 'fragment' are given when pasted into a flow that has processes
+/// with the 'existing' aliases, by their alias in the fragment. Only aliases already used change.
+pub fn paste_aliases(existing: &[String], fragment: &Fragment) -> HashMap<String, String> {
+    let mut used = existing.to_vec();
+    let mut renames = HashMap::new();
+    for alias in fragment.aliases() {
+        let new_alias = numbered_alias(&used, &alias);
+        used.push(new_alias.clone());
+        renames.insert(alias, new_alias);
+    }
+    renames
+}
+
+// Change the process alias a route starts with if it has been renamed
+fn rename_route(route: &str, renames: &HashMap<String, String>) -> String {
+    let alias = route_alias(route);
+    match renames.get(alias) {
+        Some(new_alias) => format!("{}{}", new_alias, &route.trim_start_matches('/')[alias.len()..]),
+        None => route.to_string()
+    }
+}
+
+// The source to use for a process copied from the flow at 'from_url' when it is pasted into the
+// flow at 'to_url'. Relative sources are relative to the flow they are written in.
+fn rebase_source(from_url: &str, to_url: &str, source: &str) -> String {
+    if Url::parse(source).is_ok() || from_url == to_url {
+        return source.to_string();
+    }
+
+    match Url::parse(from_url).and_then(|base| base.join(source)) {
+        Ok(absolute) => relative_source(to_url, absolute.as_str()),
+        Err(_) => source.to_string()
+    }
+}
+
+fn has_process(flow: &Flow, alias: &str) -> bool {
+    flow.process_refs.iter().flatten().any(|process_ref| process_ref.alias.to_string() == alias)
+}
+
+fn has_connection(flow: &Flow, from: &str, to: &str) -> bool {
+    flow.connections.iter().flatten()
+        .any(|connection| connection.from.to_string() == from && connection.to.to_string() == to)
+}
+
+/// The alias of the process a route refers to, or "input"/"output" for the flow's own IOs
+pub fn route_alias(route: &str) -> &str {
+    route.trim_start_matches('/').split('/').next().unwrap_or("")
+}
+
+/// Find the flow, or sub-flow at any depth, that was loaded from 'url'
+pub fn find_flow<'a>(flow: &'a Flow, url: &str) -> Option<&'a Flow> {
+    if flow.source_url == url {
+        return Some(flow);
+    }
+
+    flow.subprocesses.values().find_map(|process| match process {
+        FlowProcess(sub_flow) => find_flow(sub_flow, url),
+        _ => None
+    })
+}
+
+/// An alias for a new process with 'source' that is not one of the 'aliases' already used in the
+/// flow: the name of the source, followed by a number if needed
+pub fn unique_alias(aliases: &[String], source: &str) -> String {
+    let trimmed = source.trim_end_matches('/');
+    let last = trimmed.rsplit('/').next().unwrap_or(trimmed);
+    let base = Path::new(last).file_stem().map(|stem| stem.to_string_lossy().to_string())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| "process".into());
+    numbered_alias(aliases, &base)
+}
+
+/// 'base' as an alias if it is not one of the 'aliases' already used in the flow, or else 'base'
+/// followed by the first number that makes it unused
+pub fn numbered_alias(aliases: &[String], base: &str) -> String {
+    let mut alias = base.to_string();
+    let mut count = 1;
+    while aliases.contains(&alias) || alias == "input" || alias == "output" {
+        count += 1;
+        alias = format!("{}_{}", base, count);
+    }
+    alias
+}
+
+/// The source to use in the flow at 'flow_url' to reference the definition at 'source'. Files in
+/// the same directory as the flow, or below it, are referenced by a relative path.
+pub fn relative_source(flow_url: &str, source: &str) -> String {
+    let (flow_path, source_path) = match (file_path(flow_url), file_path(source)) {
+        (Some(flow_path), Some(source_path)) => (flow_path, source_path),
+        _ => return source.to_string()
+    };
+
+    match flow_path.parent().and_then(|directory| source_path.strip_prefix(directory).ok()) {
+        Some(relative) => relative.to_string_lossy().to_string(),
+        None => source.to_string()
+    }
+}
+
+fn file_path(url: &str) -> Option<std::path::PathBuf> {
+    Url::parse(url).ok()?.to_file_path().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{paste_aliases, rebase_source, rename_route, route_alias, unique_alias, Fragment};
+
+    fn aliases(aliases: &[&str]) -> Vec<String> {
+        aliases.iter().map(|alias| alias.to_string()).collect()
+    }
+
+    fn fragment(aliases: &[&str]) -> Fragment {
+        Fragment {
+            flow_url: "file:///flows/copied.toml".into(),
+            processes: aliases.iter().map(|alias| json!({"alias": alias, "source": "add.toml"})).collect(),
+            connections: vec!(),
+        }
+    }
+
+    #[test]
+    fn pasted_aliases_already_used_numbered() {
+        let renames = paste_aliases(&aliases(&["add", "add_2", "print"]), &fragment(&["add", "print", "sum"]));
+        assert_eq!(renames["add"], "add_3");
+        assert_eq!(renames["print"], "print_2");
+        assert_eq!(renames["sum"], "sum");
+    }
+
+    #[test]
+    fn pasted_aliases_not_used_kept_as_they_are() {
+        let renames = paste_aliases(&aliases(&["add"]), &fragment(&["v1.2", "input"]));
+        assert_eq!(renames["v1.2"], "v1.2");
+        assert_eq!(renames["input"], "input_2");
+    }
+
+    #[test]
+    fn pasting_twice_numbers_the_second_copy() {
+        let first = paste_aliases(&aliases(&["add"]), &fragment(&["add"]));
+        let second = paste_aliases(&aliases(&["add", &first["add"]]), &fragment(&["add"]));
+        assert_eq!((first["add"].as_str(), second["add"].as_str()), ("add_2", "add_3"));
+    }
+
+    #[test]
+    fn routes_of_renamed_processes_renamed() {
+        let renames = paste_aliases(&aliases(&["add"]), &fragment(&["add", "sum"]));
+        assert_eq!(rename_route("add/i1", &renames), "add_2/i1");
+        assert_eq!(rename_route("/add", &renames), "add_2");
+        assert_eq!(rename_route("adder/i1", &renames), "adder/i1");
+        assert_eq!(rename_route("sum/result/0", &renames), "sum/result/0");
+    }
+
+    #[test]
+    fn alias_from_source() {
+        assert_eq!(unique_alias(&[], "lib://flowstdlib/math/add"), "add");
+        assert_eq!(unique_alias(&aliases(&["add"]), "lib://flowstdlib/math/add"), "add_2");
+        assert_eq!(unique_alias(&[], "sub/count.toml"), "count");
+        assert_eq!(unique_alias(&[], "input.toml"), "input_2");
+        assert_eq!(route_alias("/count/output/0"), "count");
+    }
+
+    #[test]
+    fn relative_source_rebased_for_the_flow_pasted_into() {
+        assert_eq!(rebase_source("file:///flows/sub/a.toml", "file:///flows/b.toml", "p.toml"), "sub/p.toml");
+        assert_eq!(rebase_source("file:///flows/a.toml", "file:///flows/sub/b.toml", "p.toml"), "file:///flows/p.toml");
+        assert_eq!(rebase_source("file:///flows/a.toml", "file:///other/b.toml", "lib://flowstdlib/math/add"),
+                   "lib://flowstdlib/math/add");
+        assert_eq!(rebase_source("file:///flows/a.toml", "file:///flows/a.toml", "p.toml"), "p.toml");
+    }
+}
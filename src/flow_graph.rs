@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+
+use flowclib::model::datatype::HasDataType;
+use flowclib::model::flow::Flow;
+use flowclib::model::io::IO;
+use flowclib::model::name::HasName;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
+
+use crate::layout;
+use crate::log_warn;
+
+// Geometry used when sizing nodes of the graph
+pub const NODE_MIN_WIDTH: f64 = 140.0;
+pub const HEADER_HEIGHT: f64 = 34.0;
+pub const PORT_SPACING: f64 = 18.0;
+pub const NODE_PADDING: f64 = 8.0;
+pub const GRAPH_MARGIN: f64 = 40.0;
+// Approximate width of a character, used to size nodes before any cairo context exists
+pub const CHAR_WIDTH: f64 = 7.0;
+// Scale at which a sub-flow's contents are drawn inside the sub-flow's node
+pub const NESTED_SCALE: f64 = 0.5;
+// How close to a port or connection a point must be to be on it
+const HIT_DISTANCE: f64 = 6.0;
+// Points each curved connection is split into when checking if a point is on it
+const CURVE_SAMPLES: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x <= self.right() && y >= self.y && y <= self.bottom()
+    }
+}
+
+/// An input or output of a process or of the flow itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub datatype: String,
+}
+
+impl Port {
+    fn from_io(io: &IO) -> Self {
+        Port {
+            name: io.name().to_string(),
+            datatype: io.datatype(0).to_string(),
+        }
+    }
+
+    // The text used to label the port on the graph: its name, which is empty for the default port
+    // of a process with a single input or output, so that port is drawn without a label
+    pub fn label(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Function,
+    Flow,
+}
+
+/// A process referenced by the flow, drawn as a box with its inputs on the left edge and
+/// its outputs on the right edge
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub alias: String,
+    pub name: String,
+    pub source: String,
+    pub kind: NodeKind,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    pub sub_graph: Option<FlowGraph>,
+    pub bounds: Rect,
+}
+
+impl Node {
+    // Calculate the size the node needs for its labels, ports and any nested sub-flow
+    fn size(&self) -> (f64, f64) {
+        let widest_input = self.inputs.iter().map(|p| p.label().len()).max().unwrap_or(0);
+        let widest_output = self.outputs.iter().map(|p| p.label().len()).max().unwrap_or(0);
+        let title = self.alias.len().max(self.name.len());
+
+        let mut width = NODE_MIN_WIDTH
+            .max(title as f64 * CHAR_WIDTH + 2.0 * NODE_PADDING)
+            .max((widest_input + widest_output) as f64 * CHAR_WIDTH + 4.0 * NODE_PADDING);
+        let ports = self.inputs.len().max(self.outputs.len()).max(1);
+        let mut height = HEADER_HEIGHT + ports as f64 * PORT_SPACING + NODE_PADDING;
+
+        if let Some(ref sub_graph) = self.sub_graph {
+            let ports_width = (widest_input + widest_output) as f64 * CHAR_WIDTH;
+            width = width.max(sub_graph.bounds.width * NESTED_SCALE + ports_width + 2.0 * NODE_PADDING);
+            height = height.max(HEADER_HEIGHT + sub_graph.bounds.height * NESTED_SCALE + NODE_PADDING);
+        }
+
+        (width, height)
+    }
+
+    // The area inside the node where a nested sub-flow is drawn
+    pub fn content_origin(&self) -> (f64, f64) {
+        let sub_width = self.sub_graph.as_ref().map(|g| g.bounds.width * NESTED_SCALE).unwrap_or(0.0);
+        (self.bounds.x + (self.bounds.width - sub_width) / 2.0, self.bounds.y + HEADER_HEIGHT)
+    }
+
+    pub fn input_anchor(&self, port_name: &str) -> (f64, f64) {
+        let index = find_port(&self.inputs, port_name);
+        (self.bounds.x, port_y(self.bounds.y + HEADER_HEIGHT, index, self.inputs.len()))
+    }
+
+    pub fn output_anchor(&self, port_name: &str) -> (f64, f64) {
+        let index = find_port(&self.outputs, port_name);
+        (self.bounds.right(), port_y(self.bounds.y + HEADER_HEIGHT, index, self.outputs.len()))
+    }
+}
+
+/// Find the index of a port by name. A route to the process with no port name refers to its
+/// only port
+pub fn find_port(ports: &[Port], name: &str) -> Option<usize> {
+    match ports.iter().position(|p| p.name == name) {
+        Some(index) => Some(index),
+        None if name.is_empty() && ports.len() == 1 => Some(0),
+        None => None
+    }
+}
+
+// vertical position of the port 'index' on the side of a box whose ports start at 'top'
+fn port_y(top: f64, index: Option<usize>, count: usize) -> f64 {
+    match index {
+        Some(i) => top + (i as f64 + 0.5) * PORT_SPACING,
+        None => top + (count.max(1) as f64 * PORT_SPACING) / 2.0
+    }
+}
+
+/// One end of a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    FlowInput(String),
+    FlowOutput(String),
+    NodeInput(usize, String),
+    NodeOutput(usize, String),
+}
+
+impl Endpoint {
+    pub fn node(&self) -> Option<usize> {
+        match self {
+            Endpoint::NodeInput(node, _) | Endpoint::NodeOutput(node, _) => Some(*node),
+            _ => None
+        }
+    }
+
+    /// Is this an endpoint a connection can start from: an input of the flow or an output of
+    /// one of its processes
+    pub fn is_source(&self) -> bool {
+        matches!(self, Endpoint::FlowInput(_) | Endpoint::NodeOutput(_, _))
+    }
+}
+
+/// The horizontal distance of the control points of the curve drawn between two points of a
+/// connection, giving horizontal tangents in and out of the ports
+pub fn curve_control(x0: f64, x1: f64) -> f64 {
+    ((x1 - x0).abs() / 2.0).max(20.0)
+}
+
+// Distance from (x, y) to the line segment between 'start' and 'end'
+fn distance_to_segment(x: f64, y: f64, start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((x - start.0) * dx + (y - start.1) * dy) / length_squared).max(0.0).min(1.0)
+    };
+    let (nearest_x, nearest_y) = (start.0 + t * dx, start.1 + t * dy);
+    ((x - nearest_x).powi(2) + (y - nearest_y).powi(2)).sqrt()
+}
+
+/// A connection between two endpoints, with the routes of the endpoints as written in the
+/// flow's definition and the points of the path it is drawn along.
+/// `loop_back` is set for connections that go back to an earlier process to form a loop.
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: Endpoint,
+    pub to: Endpoint,
+    pub from_route: String,
+    pub to_route: String,
+    pub name: Option<String>,
+    pub points: Vec<(f64, f64)>,
+    pub loop_back: bool,
+}
+
+impl Edge {
+    // The points of the line the edge is drawn along, with its curves split into straight segments
+    fn path(&self) -> Vec<(f64, f64)> {
+        if self.loop_back || self.points.len() < 2 {
+            return self.points.clone();
+        }
+
+        let mut path = vec!(self.points[0]);
+        for window in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+            let control = curve_control(x0, x1);
+            let (c0, c1) = ((x0 + control, y0), (x1 - control, y1));
+            for step in 1..=CURVE_SAMPLES {
+                let t = step as f64 / CURVE_SAMPLES as f64;
+                let u = 1.0 - t;
+                let point = |p0: f64, p1: f64, p2: f64, p3: f64|
+                    u * u * u * p0 + 3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t * p3;
+                path.push((point(x0, c0.0, c1.0, x1), point(y0, c0.1, c1.1, y1)));
+            }
+        }
+        path
+    }
+
+    /// Is the point (x, y) on the line the edge is drawn along
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.path().windows(2).any(|segment| distance_to_segment(x, y, segment[0], segment[1]) <= HIT_DISTANCE)
+    }
+}
+
+/// The graph of a `Flow` ready to be drawn
+#[derive(Debug, Clone)]
+pub struct FlowGraph {
+    pub name: String,
+    pub source_url: String,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub bounds: Rect,
+}
+
+impl FlowGraph {
+    /// Create the graph for a flow that has been loaded, including its sub-flows
+    pub fn from_flow(flow: &Flow) -> Self {
+        let mut nodes = vec!();
+
+        for process_ref in flow.process_refs.iter().flatten() {
+            let alias = process_ref.alias.to_string();
+            let node = match flow.subprocesses.get(&process_ref.alias) {
+                Some(FlowProcess(sub_flow)) => Node {
+                    alias,
+                    name: sub_flow.name.to_string(),
+                    source: process_ref.source.clone(),
+                    kind: NodeKind::Flow,
+                    inputs: sub_flow.inputs.iter().flatten().map(Port::from_io).collect(),
+                    outputs: sub_flow.outputs.iter().flatten().map(Port::from_io).collect(),
+                    sub_graph: Some(Self::from_flow(sub_flow)),
+                    bounds: Rect::default(),
+                },
+                Some(FunctionProcess(function)) => Node {
+                    alias,
+                    name: function.name().to_string(),
+                    source: process_ref.source.clone(),
+                    kind: NodeKind::Function,
+                    inputs: function.get_inputs().iter().flatten().map(Port::from_io).collect(),
+                    outputs: function.get_outputs().iter().flatten().map(Port::from_io).collect(),
+                    sub_graph: None,
+                    bounds: Rect::default(),
+                },
+                None => {
+                    log_warn(&format!("Process '{}' referenced in flow '{}' was not loaded",
+                                      alias, flow.name));
+                    continue;
+                }
+            };
+            nodes.push(node);
+        }
+
+        let mut graph = FlowGraph {
+            name: flow.name.to_string(),
+            source_url: flow.source_url.clone(),
+            inputs: flow.inputs.iter().flatten().map(Port::from_io).collect(),
+            outputs: flow.outputs.iter().flatten().map(Port::from_io).collect(),
+            nodes,
+            edges: vec!(),
+            bounds: Rect::default(),
+        };
+
+        for connection in flow.connections.iter().flatten() {
+            let from_route = connection.from.to_string();
+            let to_route = connection.to.to_string();
+            let from = graph.endpoint(&from_route, true);
+            let to = graph.endpoint(&to_route, false);
+            match (from, to) {
+                (Some(from), Some(to)) => graph.edges.push(Edge {
+                    from,
+                    to,
+                    from_route,
+                    to_route,
+                    name: connection.name.as_ref().map(|n| n.to_string()),
+                    points: vec!(),
+                    loop_back: false,
+                }),
+                _ => log_warn(&format!("Could not find both ends of connection '{}' -> '{}' in flow '{}'",
+                                       connection.from, connection.to, flow.name))
+            }
+        }
+
+        graph.layout();
+        graph
+    }
+
+    // Find the endpoint a route in the flow definition refers to. Routes are of the form
+    // "input/{name}", "output/{name}", "{alias}/{port}" or just "{alias}" for a process with a
+    // single default port
+    fn endpoint(&self, route: &str, source: bool) -> Option<Endpoint> {
+        let mut segments = route.trim_start_matches('/').splitn(3, '/');
+        let first = segments.next().unwrap_or("");
+        let port = segments.next().unwrap_or("").to_string();
+
+        match (first, source) {
+            ("input", true) => Some(Endpoint::FlowInput(port)),
+            ("output", false) => Some(Endpoint::FlowOutput(port)),
+            (alias, _) => {
+                let node = self.nodes.iter().position(|n| n.alias == alias)?;
+                if source {
+                    Some(Endpoint::NodeOutput(node, port))
+                } else {
+                    Some(Endpoint::NodeInput(node, port))
+                }
+            }
+        }
+    }
+
+    // Size the nodes, innermost sub-flows first as they were laid out when created, then lay
+    // out the graph
+    fn layout(&mut self) {
+        for node in self.nodes.iter_mut() {
+            let (width, height) = node.size();
+            node.bounds.width = width;
+            node.bounds.height = height;
+        }
+
+        layout::layered(self);
+    }
+
+    /// The position on the graph where a connection to or from an endpoint is attached
+    pub fn anchor(&self, endpoint: &Endpoint) -> (f64, f64) {
+        match endpoint {
+            Endpoint::FlowInput(name) => (self.bounds.x, self.flow_port_y(&self.inputs, name)),
+            Endpoint::FlowOutput(name) => (self.bounds.right(), self.flow_port_y(&self.outputs, name)),
+            Endpoint::NodeInput(node, port) => self.nodes[*node].input_anchor(port),
+            Endpoint::NodeOutput(node, port) => self.nodes[*node].output_anchor(port),
+        }
+    }
+
+    fn flow_port_y(&self, ports: &[Port], name: &str) -> f64 {
+        port_y(self.bounds.y + GRAPH_MARGIN, find_port(ports, name), ports.len())
+    }
+
+    /// Find the top-level node at a position on the graph
+    pub fn node_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.nodes.iter().rposition(|node| node.bounds.contains(x, y))
+    }
+
+    /// Find the port of a top-level node, or of the flow itself, at a position on the graph
+    pub fn port_at(&self, x: f64, y: f64) -> Option<Endpoint> {
+        let near = |(port_x, port_y): (f64, f64)| (port_x - x).abs() <= HIT_DISTANCE && (port_y - y).abs() <= HIT_DISTANCE;
+
+        let mut endpoints: Vec<Endpoint> = self.inputs.iter().map(|p| Endpoint::FlowInput(p.name.clone()))
+            .chain(self.outputs.iter().map(|p| Endpoint::FlowOutput(p.name.clone())))
+            .collect();
+        for (index, node) in self.nodes.iter().enumerate() {
+            endpoints.extend(node.inputs.iter().map(|p| Endpoint::NodeInput(index, p.name.clone())));
+            endpoints.extend(node.outputs.iter().map(|p| Endpoint::NodeOutput(index, p.name.clone())));
+        }
+
+        endpoints.into_iter().find(|endpoint| near(self.anchor(endpoint)))
+    }
+
+    /// Find the edge drawn through a position on the graph
+    pub fn edge_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.edges.iter().position(|edge| edge.contains(x, y))
+    }
+
+    /// The route used for an endpoint in the connections of the flow's definition
+    pub fn route(&self, endpoint: &Endpoint) -> String {
+        let process_route = |node: &usize, port: &String| {
+            let alias = &self.nodes[*node].alias;
+            if port.is_empty() { alias.clone() } else { format!("{}/{}", alias, port) }
+        };
+
+        match endpoint {
+            Endpoint::FlowInput(name) => format!("input/{}", name),
+            Endpoint::FlowOutput(name) => format!("output/{}", name),
+            Endpoint::NodeInput(node, port) | Endpoint::NodeOutput(node, port) => process_route(node, port),
+        }
+    }
+
+    /// Move the top-level node 'index' so its top left corner is at (x, y), keeping it inside the
+    /// margins of the graph
+    pub fn move_node(&mut self, index: usize, x: f64, y: f64) {
+        let mut moved = HashSet::new();
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.bounds.x = x.max(GRAPH_MARGIN);
+            node.bounds.y = y.max(GRAPH_MARGIN);
+            moved.insert(index);
+        }
+        self.nodes_moved(&moved);
+    }
+
+    /// Move the top-level nodes that have a position in 'positions', by alias, to that position.
+    /// Positions of processes the flow no longer has are ignored.
+    pub fn place_nodes(&mut self, positions: &HashMap<String, (f64, f64)>) {
+        let mut moved = HashSet::new();
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            if let Some((x, y)) = positions.get(&node.alias) {
+                node.bounds.x = x.max(GRAPH_MARGIN);
+                node.bounds.y = y.max(GRAPH_MARGIN);
+                moved.insert(index);
+            }
+        }
+
+        if !moved.is_empty() {
+            self.nodes_moved(&moved);
+        }
+    }
+
+    // Grow the graph to fit the nodes that have moved, and draw the connections to them, and to
+    // the flow's outputs that move with the edge of the graph, directly between their ports
+    fn nodes_moved(&mut self, moved: &HashSet<usize>) {
+        let right = self.nodes.iter().map(|n| n.bounds.right()).fold(0.0, f64::max) + 2.0 * GRAPH_MARGIN;
+        let bottom = self.nodes.iter().map(|n| n.bounds.bottom()).fold(0.0, f64::max) + GRAPH_MARGIN;
+        let resized = right > self.bounds.width || bottom > self.bounds.height;
+        self.bounds.width = self.bounds.width.max(right);
+        self.bounds.height = self.bounds.height.max(bottom);
+
+        for index in 0..self.edges.len() {
+            let edge = &self.edges[index];
+            let touches_moved = [edge.from.node(), edge.to.node()].iter()
+                .any(|node| node.map_or(false, |node| moved.contains(&node)));
+            let touches_output = match edge.to {
+                Endpoint::FlowOutput(_) => resized,
+                _ => false
+            };
+
+            if touches_moved || touches_output {
+                let points = vec!(self.anchor(&edge.from), self.anchor(&edge.to));
+                let edge = &mut self.edges[index];
+                edge.points = points;
+                edge.loop_back = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,1196 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use gdk::{DragAction, EventMask, EventType, ModifierType, ScrollDirection};
+use gdk::keys::constants as keys;
+use gtk::{Align, Button, DestDefaults, DrawingArea, Inhibit, Label, Overlay, TargetEntry, TargetFlags};
+use gtk::prelude::*;
+
+use crate::actions;
+use crate::flow_edit::{self, FlowEdit, Fragment};
+use crate::flow_graph::{Endpoint, FlowGraph, Node, NodeKind};
+use crate::graph_painter::{self, Highlight};
+use crate::history::MovedPositions;
+use crate::layout_file::{self, Positions};
+use crate::palette::PROCESS_SOURCE_TARGET;
+use crate::rename::Renamed;
+use crate::ui_context::UiContext;
+
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 4.0;
+// Factor the zoom changes by for each step of the mouse wheel or zoom buttons
+const ZOOM_STEP: f64 = 1.15;
+const MINIMAP_WIDTH: i32 = 180;
+const MINIMAP_HEIGHT: i32 = 120;
+
+/// The zoom and scroll position of the view, such that a point on the graph is shown at
+/// (graph_x * zoom + x, graph_y * zoom + y) on the view
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Viewport {
+    zoom: f64,
+    x: f64,
+    y: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport { zoom: 1.0, x: 0.0, y: 0.0 }
+    }
+}
+
+impl Viewport {
+    fn to_graph(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.x) / self.zoom, (y - self.y) / self.zoom)
+    }
+
+    // Change the zoom keeping the graph point under view point (x, y) where it is
+    fn zoom_at(&mut self, zoom: f64, x: f64, y: f64) {
+        let (graph_x, graph_y) = self.to_graph(x, y);
+        self.zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+        self.x = x - graph_x * self.zoom;
+        self.y = y - graph_y * self.zoom;
+    }
+
+    // Center the graph point (x, y) in a view of size (width, height)
+    fn center_on(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.x = width / 2.0 - x * self.zoom;
+        self.y = height / 2.0 - y * self.zoom;
+    }
+
+    // The zoom and position that shows all of the graph in a view of size (width, height)
+    fn fit(graph: &FlowGraph, width: f64, height: f64) -> Self {
+        let zoom = (width / graph.bounds.width).min(height / graph.bounds.height).min(1.0).max(MIN_ZOOM);
+        let mut viewport = Viewport { zoom, x: 0.0, y: 0.0 };
+        viewport.center_on(graph.bounds.width / 2.0, graph.bounds.height / 2.0, width, height);
+        viewport
+    }
+}
+
+// The scale and offset used to draw the whole graph in the minimap
+fn minimap_transform(graph: &FlowGraph) -> (f64, f64, f64) {
+    let scale = (MINIMAP_WIDTH as f64 / graph.bounds.width).min(MINIMAP_HEIGHT as f64 / graph.bounds.height);
+    ((MINIMAP_WIDTH as f64 - graph.bounds.width * scale) / 2.0,
+     (MINIMAP_HEIGHT as f64 - graph.bounds.height * scale) / 2.0,
+     scale)
+}
+
+// Sub-flows referenced from these libraries are shown read-only
+const READ_ONLY_SCHEMES: &[&str] = &["lib://", "context://"];
+// Distance the pointer must move with a node before it is moved rather than just selected
+const DRAG_THRESHOLD: f64 = 3.0;
+// Offset of duplicated processes from the originals
+const DUPLICATE_OFFSET: f64 = 30.0;
+
+/// A change the user asks for in the view to the flow being shown
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphChange {
+    Edit(FlowEdit),
+    // Move the processes with these aliases into a new sub-flow called 'name'
+    Extract { aliases: Vec<String>, name: String },
+    // Replace the sub-flow process 'alias' with its contents
+    Inline { alias: String },
+    // Rename a process, or an input or output, to 'name' everywhere it is used
+    Rename { renamed: Renamed, name: String },
+}
+
+// Called with the url of the flow being shown and a change the user has asked for
+type ChangeHandler = Rc<dyn Fn(String, GraphChange)>;
+
+// Move the nodes of 'graph', and of its sub-flows, that have been moved by the user
+fn place_nodes(graph: &mut FlowGraph, location: &str, positions: &Positions) {
+    for node in graph.nodes.iter_mut() {
+        let alias = node.alias.clone();
+        if let Some(sub_graph) = node.sub_graph.as_mut() {
+            place_nodes(sub_graph, &format!("{}/{}", location, alias), positions);
+        }
+    }
+
+    if let Some(flow_positions) = positions.get(location) {
+        graph.place_nodes(flow_positions);
+    }
+}
+
+// What is renamed for the port 'name' of 'node'. Only the ports of sub-flows can be renamed, as
+// those of functions are defined by their implementation.
+fn renamed_port(node: &Node, name: String, input: bool) -> Result<(Renamed, String), String> {
+    if name.is_empty() {
+        return Err(format!("The default port of '{}' has no name to rename", node.alias));
+    }
+
+    match node.sub_graph.as_ref().filter(|_| node.kind == NodeKind::Flow) {
+        Some(sub_graph) => Ok((Renamed::Port { flow_url: sub_graph.source_url.clone(), name: name.clone(), input }, name)),
+        None => Err(format!("The ports of function '{}' are defined by its implementation and cannot be renamed",
+                            node.alias))
+    }
+}
+
+// State of the graph view that is shared between the gtk signal handlers
+#[derive(Default)]
+struct ViewState {
+    root: Option<FlowGraph>,
+    // Indexes of the sub-flow nodes followed from the root flow down to the flow being shown
+    path: Vec<usize>,
+    highlight: Highlight,
+    viewport: Viewport,
+    // The last viewport used for each (sub)flow shown, by location
+    viewports: HashMap<String, Viewport>,
+    // Fit the graph to the view the next time it is drawn, once its size is known
+    fit_pending: bool,
+    // Where a drag to pan the view started: the pointer position and the viewport at that time
+    pan_start: Option<((f64, f64), Viewport)>,
+    // Positions (top left corner) the user has moved nodes to, by alias, for each (sub)flow by
+    // location
+    positions: Positions,
+    // A node being dragged: its index, the offset of the pointer from its top left corner and
+    // whether it has moved yet
+    moving: Option<(usize, (f64, f64), bool)>,
+    // A connection being drawn from a port to the pointer position on the graph
+    connecting: Option<(Endpoint, (f64, f64))>,
+    change_handler: Option<ChangeHandler>,
+    // The last position of the pointer on the graph, where copied processes are pasted
+    pointer: Option<(f64, f64)>,
+    // Processes copied, with their positions relative to the top left of the group copied
+    clipboard: Option<(Fragment, HashMap<String, (f64, f64)>)>,
+}
+
+/// A view that draws the graph of the loaded flow and lets the user select nodes in it, zoom
+/// and pan around it, with a minimap showing the part of the graph currently visible.
+/// Double clicking a sub-flow shows that sub-flow in the view, and a breadcrumb bar above the
+/// graph shows the path to it from the root flow, for going back up.
+/// Flows that are not read-only can be edited: processes dropped on the view from the palette
+/// are added, dragging from an output to an input connects them, nodes can be moved, and the
+/// selected node or connection is deleted with the Delete key. Several processes can be selected
+/// with Ctrl+click (or all of them with Ctrl+A), copied (Ctrl+C) and pasted (Ctrl+V) into the same
+/// or another flow, duplicated (Ctrl+D) or extracted into a new sub-flow (Ctrl+E). A selected
+/// sub-flow can be replaced by its contents (Ctrl+I). The port under the pointer, or else the
+/// selected process, can be renamed everywhere it is used (F2).
+#[derive(Clone)]
+pub struct FlowGraphView {
+    container: gtk::Box,
+    breadcrumbs: gtk::Box,
+    overlay: Overlay,
+    drawing_area: DrawingArea,
+    minimap: DrawingArea,
+    state: Rc<RefCell<ViewState>>,
+}
+
+impl FlowGraphView {
+    pub fn new() -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let breadcrumbs = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        breadcrumbs.set_margin_start(4);
+        container.pack_start(&breadcrumbs, false, false, 2);
+
+        let overlay = Overlay::new();
+        let drawing_area = DrawingArea::new();
+        drawing_area.add_events(EventMask::POINTER_MOTION_MASK | EventMask::BUTTON_PRESS_MASK |
+            EventMask::BUTTON_RELEASE_MASK | EventMask::SCROLL_MASK | EventMask::SMOOTH_SCROLL_MASK |
+            EventMask::KEY_PRESS_MASK);
+        drawing_area.set_can_focus(true);
+        drawing_area.drag_dest_set(DestDefaults::ALL,
+                                   &[TargetEntry::new(PROCESS_SOURCE_TARGET, TargetFlags::SAME_APP, 0)],
+                                   DragAction::COPY);
+        drawing_area.set_hexpand(true);
+        drawing_area.set_vexpand(true);
+        overlay.add(&drawing_area);
+
+        let minimap = DrawingArea::new();
+        minimap.set_size_request(MINIMAP_WIDTH, MINIMAP_HEIGHT);
+        minimap.set_halign(Align::End);
+        minimap.set_valign(Align::End);
+        minimap.set_margin_end(8);
+        minimap.set_margin_bottom(8);
+        minimap.add_events(EventMask::BUTTON_PRESS_MASK | EventMask::BUTTON1_MOTION_MASK);
+        overlay.add_overlay(&minimap);
+        container.pack_start(&overlay, true, true, 0);
+
+        let view = FlowGraphView {
+            container,
+            breadcrumbs,
+            overlay,
+            drawing_area,
+            minimap,
+            state: Rc::new(RefCell::new(ViewState::default())),
+        };
+
+        view.overlay.add_overlay(&view.zoom_buttons());
+        view.connect_signals();
+        view.connect_edit_signals();
+        view.connect_minimap_signals();
+        view
+    }
+
+    /// Set the function to call with the url of the flow being shown and each change the user
+    /// asks for to it
+    pub fn connect_change<F: Fn(String, GraphChange) + 'static>(&self, handler: F) {
+        self.state.borrow_mut().change_handler = Some(Rc::new(handler));
+    }
+
+    /// Set the positions of the nodes moved by the user in the flow at 'root_url' and its sub-flows,
+    /// replacing any already known. They are used when the graph of that flow is next set.
+    pub fn set_positions(&self, root_url: &str, positions: Positions) {
+        let mut state = self.state.borrow_mut();
+        let sub_flow_prefix = format!("{}/", root_url);
+        state.positions.retain(|location, _| location != root_url && !location.starts_with(&sub_flow_prefix));
+        state.positions.extend(positions);
+    }
+
+    /// Move the positions of renamed processes to their new aliases, once the rename has been made
+    /// or undone, and save them
+    pub fn move_positions(&self, moved: &[MovedPositions]) {
+        {
+            let mut state = self.state.borrow_mut();
+            for MovedPositions { location, from, to } in moved {
+                layout_file::move_positions(&mut state.positions, location, from, to);
+            }
+        }
+        self.save_positions();
+    }
+
+    // Save the positions of the nodes moved in the flow being shown, and its sub-flows, next
+    // to its definition
+    fn save_positions(&self) {
+        let state = self.state.borrow();
+        if let Some(root) = state.root.as_ref() {
+            if root.source_url.starts_with("file:") {
+                let sub_flow_prefix = format!("{}/", root.source_url);
+                let positions: Positions = state.positions.iter()
+                    .filter(|(location, _)| **location == root.source_url || location.starts_with(&sub_flow_prefix))
+                    .map(|(location, flow_positions)| (location.clone(), flow_positions.clone()))
+                    .collect();
+                actions::save_layout(root.source_url.clone(), positions);
+            }
+        }
+    }
+
+    /// The graph of the (sub)flow being shown, with its nodes where they are shown
+    pub fn shown_graph(&self) -> Option<FlowGraph> {
+        self.state.borrow().graph().cloned()
+    }
+
+    /// The top level widget of the view, to add to containers
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Set the graph of the root flow to show, or clear the view if `None`. If the same flow is
+    /// being reloaded then the sub-flow being shown stays shown if it still exists.
+    pub fn set_graph(&self, graph: Option<FlowGraph>) {
+        {
+            let mut state = self.state.borrow_mut();
+            let same_flow = match (&state.root, &graph) {
+                (Some(old), Some(new)) => old.source_url == new.source_url,
+                _ => false
+            };
+            state.remember_viewport();
+            state.root = graph;
+            state.place_nodes();
+            if !same_flow || state.graph().is_none() {
+                state.path.clear();
+            }
+        }
+
+        self.path_changed();
+    }
+
+    /// Show the (sub)flow reached from the root flow through the sub-flows with 'sub_flow_aliases',
+    /// selecting and centering on its process 'alias' if given
+    pub fn select_node(&self, sub_flow_aliases: &[String], alias: Option<&str>) {
+        let (path, selected) = {
+            let state = self.state.borrow();
+            let mut graph = match state.root.as_ref() {
+                Some(root) => root,
+                None => return
+            };
+            let mut path = vec!();
+            for sub_flow_alias in sub_flow_aliases {
+                let index = graph.nodes.iter().position(|node| node.alias == *sub_flow_alias);
+                match index.and_then(|index| graph.nodes[index].sub_graph.as_ref().map(|sub_graph| (index, sub_graph))) {
+                    Some((index, sub_graph)) => {
+                        path.push(index);
+                        graph = sub_graph;
+                    }
+                    None => return
+                }
+            }
+            (path, alias.and_then(|alias| graph.nodes.iter().position(|node| node.alias == alias)))
+        };
+
+        if path != self.state.borrow().path {
+            self.show_path(path);
+        }
+
+        let width = self.drawing_area.get_allocated_width() as f64;
+        let height = self.drawing_area.get_allocated_height() as f64;
+        {
+            let mut state = self.state.borrow_mut();
+            state.highlight.selected = selected.into_iter().collect();
+            state.highlight.selected_edge = None;
+            // A flow not shown before is fitted to the view when drawn, which shows the node anyway
+            let center = selected.and_then(|index| state.graph().map(|graph| graph.nodes[index].bounds));
+            if let Some(bounds) = center.filter(|_| !state.fit_pending) {
+                state.viewport.center_on(bounds.x + bounds.width / 2.0, bounds.y + bounds.height / 2.0, width, height);
+            }
+        }
+        self.redraw();
+    }
+
+    // Show the (sub)flow at 'path' from the root flow
+    fn show_path(&self, path: Vec<usize>) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.remember_viewport();
+            state.path = path;
+        }
+
+        self.path_changed();
+    }
+
+    // Update the view after the flow being shown has changed. The zoom and position last used for
+    // it are restored, or a flow not shown before is fitted to the view.
+    fn path_changed(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.fit_pending = false;
+            let saved_viewport = state.location().and_then(|location| state.viewports.get(&location).cloned());
+            match saved_viewport {
+                Some(viewport) => state.viewport = viewport,
+                None => {
+                    state.viewport = Viewport::default();
+                    state.fit_pending = true;
+                }
+            }
+            state.highlight = Highlight::default();
+            state.pan_start = None;
+            state.moving = None;
+            state.connecting = None;
+        }
+
+        self.update_breadcrumbs();
+        self.drawing_area.set_tooltip_text(None);
+        self.redraw();
+    }
+
+    // Rebuild the breadcrumb bar with a button for each flow from the root down to the one being
+    // shown, that goes back to that flow when clicked
+    fn update_breadcrumbs(&self) {
+        for child in self.breadcrumbs.get_children() {
+            self.breadcrumbs.remove(&child);
+        }
+
+        let (crumbs, read_only) = {
+            let state = self.state.borrow();
+            (state.crumbs(), state.is_read_only())
+        };
+
+        let crumb_count = crumbs.len();
+        for (depth, crumb) in crumbs.into_iter().enumerate() {
+            if depth > 0 {
+                self.breadcrumbs.pack_start(&Label::new(Some(">")), false, false, 2);
+            }
+            let button = Button::with_label(&crumb);
+            button.set_relief(gtk::ReliefStyle::None);
+            button.set_sensitive(depth + 1 < crumb_count);
+            let view = self.clone();
+            button.connect_clicked(move |_| {
+                let mut path = view.state.borrow().path.clone();
+                path.truncate(depth);
+                view.show_path(path);
+            });
+            self.breadcrumbs.pack_start(&button, false, false, 0);
+        }
+
+        if read_only {
+            self.breadcrumbs.pack_start(&Label::new(Some("(read-only)")), false, false, 6);
+        }
+
+        self.breadcrumbs.show_all();
+    }
+
+    /// Zoom and position the view to show all of the graph
+    pub fn fit_to_window(&self) {
+        let width = self.drawing_area.get_allocated_width() as f64;
+        let height = self.drawing_area.get_allocated_height() as f64;
+        {
+            let mut state = self.state.borrow_mut();
+            if let Some(viewport) = state.graph().map(|graph| Viewport::fit(graph, width, height)) {
+                state.viewport = viewport;
+            }
+        }
+        self.redraw();
+    }
+
+    fn zoom_by(&self, factor: f64) {
+        let width = self.drawing_area.get_allocated_width() as f64;
+        let height = self.drawing_area.get_allocated_height() as f64;
+        {
+            let mut state = self.state.borrow_mut();
+            let zoom = state.viewport.zoom * factor;
+            state.viewport.zoom_at(zoom, width / 2.0, height / 2.0);
+        }
+        self.redraw();
+    }
+
+    fn redraw(&self) {
+        self.drawing_area.queue_draw();
+        self.minimap.queue_draw();
+    }
+
+    fn zoom_buttons(&self) -> gtk::Box {
+        let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        buttons.set_halign(Align::End);
+        buttons.set_valign(Align::Start);
+        buttons.set_margin_end(8);
+        buttons.set_margin_top(8);
+
+        let zoom_out = Button::with_label("-");
+        zoom_out.set_tooltip_text(Some("Zoom out"));
+        let view = self.clone();
+        zoom_out.connect_clicked(move |_| view.zoom_by(1.0 / ZOOM_STEP));
+        buttons.pack_start(&zoom_out, false, false, 0);
+
+        let zoom_in = Button::with_label("+");
+        zoom_in.set_tooltip_text(Some("Zoom in"));
+        let view = self.clone();
+        zoom_in.connect_clicked(move |_| view.zoom_by(ZOOM_STEP));
+        buttons.pack_start(&zoom_in, false, false, 0);
+
+        let actual_size = Button::with_label("1:1");
+        actual_size.set_tooltip_text(Some("Zoom to actual size"));
+        let view = self.clone();
+        actual_size.connect_clicked(move |_| {
+            let zoom = view.state.borrow().viewport.zoom;
+            view.zoom_by(1.0 / zoom);
+        });
+        buttons.pack_start(&actual_size, false, false, 0);
+
+        let fit = Button::with_label("Fit");
+        fit.set_tooltip_text(Some("Fit the flow to the window"));
+        let view = self.clone();
+        fit.connect_clicked(move |_| view.fit_to_window());
+        buttons.pack_start(&fit, false, false, 0);
+
+        buttons
+    }
+
+    fn connect_signals(&self) {
+        let view = self.clone();
+        self.drawing_area.connect_draw(move |area, cr| {
+            let mut state = view.state.borrow_mut();
+            if state.fit_pending {
+                let fitted = state.graph().map(|graph| Viewport::fit(graph,
+                                                                      area.get_allocated_width() as f64,
+                                                                      area.get_allocated_height() as f64));
+                if let Some(viewport) = fitted {
+                    state.viewport = viewport;
+                }
+                state.fit_pending = false;
+                view.minimap.queue_draw();
+            }
+
+            if let Some(graph) = state.graph() {
+                cr.translate(state.viewport.x, state.viewport.y);
+                cr.scale(state.viewport.zoom, state.viewport.zoom);
+                graph_painter::paint(cr, graph, &state.highlight);
+
+                if let Some((ref from, (x, y))) = state.connecting {
+                    let (start_x, start_y) = graph.anchor(from);
+                    cr.set_source_rgb(0.30, 0.50, 0.85);
+                    cr.set_line_width(1.5);
+                    cr.set_dash(&[4.0, 3.0], 0.0);
+                    cr.move_to(start_x, start_y);
+                    cr.line_to(x, y);
+                    cr.stroke();
+                }
+            }
+            Inhibit(false)
+        });
+
+        // The mouse wheel zooms in and out around the pointer position
+        let view = self.clone();
+        self.drawing_area.connect_scroll_event(move |_, event| {
+            let (x, y) = event.get_position();
+            let factor = match event.get_direction() {
+                ScrollDirection::Up => ZOOM_STEP,
+                ScrollDirection::Down => 1.0 / ZOOM_STEP,
+                ScrollDirection::Smooth => ZOOM_STEP.powf(-event.get_delta().1),
+                _ => 1.0
+            };
+            {
+                let mut state = view.state.borrow_mut();
+                let zoom = state.viewport.zoom * factor;
+                state.viewport.zoom_at(zoom, x, y);
+            }
+            view.redraw();
+            Inhibit(true)
+        });
+
+        // Highlight the node under the mouse pointer and describe it in a tooltip, or pan the
+        // view, move a node or draw a connection if dragging
+        let view = self.clone();
+        self.drawing_area.connect_motion_notify_event(move |area, event| {
+            let (x, y) = event.get_position();
+            let mut state = view.state.borrow_mut();
+
+            let pan_start = state.pan_start;
+            if let Some(((start_x, start_y), viewport)) = pan_start {
+                state.viewport.x = viewport.x + x - start_x;
+                state.viewport.y = viewport.y + y - start_y;
+                drop(state);
+                view.redraw();
+                return Inhibit(false);
+            }
+
+            let (graph_x, graph_y) = state.viewport.to_graph(x, y);
+            state.pointer = Some((graph_x, graph_y));
+            if let Some((_, ref mut pointer)) = state.connecting {
+                *pointer = (graph_x, graph_y);
+                area.queue_draw();
+                return Inhibit(false);
+            }
+
+            if let Some((index, (offset_x, offset_y), moved)) = state.moving {
+                let (left, top) = (graph_x - offset_x, graph_y - offset_y);
+                let current = state.graph().and_then(|graph| graph.nodes.get(index)).map(|node| (node.bounds.x, node.bounds.y));
+                let started = moved || current.map_or(false, |(node_x, node_y)|
+                    (left - node_x).abs() > DRAG_THRESHOLD || (top - node_y).abs() > DRAG_THRESHOLD);
+                if started {
+                    state.moving = Some((index, (offset_x, offset_y), true));
+                    if let Some(graph) = state.graph_mut() {
+                        graph.move_node(index, left, top);
+                    }
+                    drop(state);
+                    view.redraw();
+                }
+                return Inhibit(false);
+            }
+
+            let hovered = state.graph().and_then(|graph| graph.node_at(graph_x, graph_y));
+            if hovered != state.highlight.hovered {
+                state.highlight.hovered = hovered;
+                let tooltip = hovered.and_then(|index| state.graph()
+                    .map(|graph| Self::describe_node(graph, index)));
+                area.set_tooltip_text(tooltip.as_deref());
+                area.queue_draw();
+            }
+            Inhibit(false)
+        });
+
+        // Pressing on an output starts drawing a connection from it, and pressing on a node
+        // selects it and starts moving it. Clicking on a connection selects it. Clicking on the
+        // background clears the selection and starts panning, as does the middle button anywhere.
+        // Double clicking on a sub-flow shows that sub-flow.
+        let view = self.clone();
+        self.drawing_area.connect_button_press_event(move |area, event| {
+            let (x, y) = event.get_position();
+            let mut state = view.state.borrow_mut();
+            let (graph_x, graph_y) = state.viewport.to_graph(x, y);
+            let clicked = state.graph().and_then(|graph| graph.node_at(graph_x, graph_y));
+
+            if event.get_event_type() == EventType::DoubleButtonPress && event.get_button() == 1 {
+                let sub_flow = clicked.filter(|index| state.graph()
+                    .map(|graph| graph.nodes[*index].kind == NodeKind::Flow && graph.nodes[*index].sub_graph.is_some())
+                    .unwrap_or(false));
+                if let Some(index) = sub_flow {
+                    let mut path = state.path.clone();
+                    path.push(index);
+                    drop(state);
+                    view.show_path(path);
+                }
+                return Inhibit(true);
+            }
+
+            if event.get_event_type() != EventType::ButtonPress {
+                return Inhibit(false);
+            }
+
+            if event.get_button() == 1 {
+                area.grab_focus();
+                let source_port = state.graph().and_then(|graph| graph.port_at(graph_x, graph_y))
+                    .filter(Endpoint::is_source);
+                if let Some(port) = source_port {
+                    if state.is_read_only() {
+                        UiContext::message("Flows referenced from a library cannot be edited");
+                    } else {
+                        state.connecting = Some((port, (graph_x, graph_y)));
+                    }
+                    return Inhibit(true);
+                }
+
+                let clicked_edge = match clicked {
+                    Some(_) => None,
+                    None => state.graph().and_then(|graph| graph.edge_at(graph_x, graph_y))
+                };
+                // Ctrl+click adds a node to or removes it from the selection, while clicking on a
+                // node already selected keeps the selection so it can be copied
+                let toggle = event.get_state().contains(ModifierType::CONTROL_MASK);
+                let selected = &mut state.highlight.selected;
+                match clicked {
+                    Some(index) if toggle => match selected.iter().position(|node| *node == index) {
+                        Some(position) => {
+                            selected.remove(position);
+                        }
+                        None => selected.push(index)
+                    },
+                    Some(index) if selected.contains(&index) => {}
+                    Some(index) => *selected = vec!(index),
+                    None => selected.clear()
+                }
+                state.highlight.selected_edge = clicked_edge;
+
+                let node_position = clicked.and_then(|index| state.graph()
+                    .map(|graph| (graph.nodes[index].bounds.x, graph.nodes[index].bounds.y)));
+                if let (Some(index), Some((node_x, node_y)), false) = (clicked, node_position, toggle) {
+                    state.moving = Some((index, (graph_x - node_x, graph_y - node_y), false));
+                }
+            }
+            if event.get_button() == 2 || (event.get_button() == 1 && clicked.is_none() &&
+                state.highlight.selected_edge.is_none()) {
+                let viewport = state.viewport;
+                state.pan_start = Some(((x, y), viewport));
+            }
+            area.queue_draw();
+            Inhibit(false)
+        });
+
+        // Releasing a connection being drawn on an input connects to it, and a node that was
+        // moved keeps its new position when the flow is reloaded
+        let view = self.clone();
+        self.drawing_area.connect_button_release_event(move |area, event| {
+            let (x, y) = event.get_position();
+            let mut state = view.state.borrow_mut();
+            state.pan_start = None;
+            let (graph_x, graph_y) = state.viewport.to_graph(x, y);
+
+            if let Some((from, _)) = state.connecting.take() {
+                let routes = state.graph().and_then(|graph| {
+                    let to = graph.port_at(graph_x, graph_y).filter(|port| !port.is_source())?;
+                    Some((graph.route(&from), graph.route(&to)))
+                });
+                area.queue_draw();
+                drop(state);
+                if let Some((from, to)) = routes {
+                    view.emit_edit(FlowEdit::AddConnection { from, to });
+                }
+                return Inhibit(false);
+            }
+
+            if let Some((index, _, true)) = state.moving.take() {
+                let moved = state.graph().and_then(|graph| graph.nodes.get(index))
+                    .map(|node| (node.alias.clone(), (node.bounds.x, node.bounds.y)));
+                if let (Some(location), Some((alias, position))) = (state.location(), moved) {
+                    state.positions.entry(location).or_insert_with(HashMap::new).insert(alias, position);
+                    drop(state);
+                    view.save_positions();
+                }
+            }
+            Inhibit(false)
+        });
+    }
+
+    // Deleting, copying and pasting the selected nodes, and dropping processes from the palette
+    fn connect_edit_signals(&self) {
+        let view = self.clone();
+        self.drawing_area.connect_key_press_event(move |area, event| {
+            let key = event.get_keyval();
+            let control = event.get_state().contains(ModifierType::CONTROL_MASK);
+
+            if key == keys::Delete || key == keys::BackSpace {
+                view.delete_selected();
+            } else if control && (key == keys::a || key == keys::A) {
+                {
+                    let mut state = view.state.borrow_mut();
+                    let count = state.graph().map_or(0, |graph| graph.nodes.len());
+                    state.highlight.selected = (0..count).collect();
+                    state.highlight.selected_edge = None;
+                }
+                area.queue_draw();
+            } else if control && (key == keys::c || key == keys::C) {
+                if let Some((fragment, offsets, _)) = view.copy_selected() {
+                    view.state.borrow_mut().clipboard = Some((fragment, offsets));
+                }
+            } else if control && (key == keys::v || key == keys::V) {
+                let (clipboard, pointer) = {
+                    let state = view.state.borrow();
+                    (state.clipboard.clone(), state.pointer)
+                };
+                match clipboard {
+                    Some((fragment, offsets)) => view.paste(fragment, &offsets, pointer.unwrap_or((0.0, 0.0))),
+                    None => UiContext::message("No processes have been copied to paste")
+                }
+            } else if control && (key == keys::d || key == keys::D) {
+                if let Some((fragment, offsets, (left, top))) = view.copy_selected() {
+                    view.paste(fragment, &offsets, (left + DUPLICATE_OFFSET, top + DUPLICATE_OFFSET));
+                }
+            } else if control && (key == keys::e || key == keys::E) {
+                view.extract_selected();
+            } else if control && (key == keys::i || key == keys::I) {
+                view.inline_selected();
+            } else if key == keys::F2 {
+                view.rename_at_pointer();
+            } else {
+                return Inhibit(false);
+            }
+
+            Inhibit(true)
+        });
+
+        let view = self.clone();
+        self.drawing_area.connect_drag_data_received(move |_, _, x, y, data, _, _| {
+            if let Some(source) = data.get_text() {
+                view.add_process(&source, x as f64, y as f64);
+            }
+        });
+    }
+
+    // Remove the selected nodes, with their connections, or the selected connection
+    fn delete_selected(&self) {
+        let edit = {
+            let state = self.state.borrow();
+            let graph = match state.graph() {
+                Some(graph) => graph,
+                None => return
+            };
+
+            let mut removals: Vec<FlowEdit> = state.highlight.selected.iter()
+                .filter_map(|index| graph.nodes.get(*index))
+                .map(|node| FlowEdit::RemoveProcess { alias: node.alias.clone() })
+                .collect();
+            match removals.len() {
+                0 => state.highlight.selected_edge.and_then(|index| graph.edges.get(index))
+                    .map(|edge| FlowEdit::RemoveConnection { from: edge.from_route.clone(), to: edge.to_route.clone() }),
+                1 => removals.pop(),
+                count => Some(FlowEdit::Batch { description: format!("Remove {} processes", count), edits: removals })
+            }
+        };
+
+        if let Some(edit) = edit {
+            self.emit_edit(edit);
+        }
+    }
+
+    // Copy the selected nodes from the flow being shown, returning them with their positions
+    // relative to the top left of the group, and the position of the group on the graph
+    fn copy_selected(&self) -> Option<(Fragment, HashMap<String, (f64, f64)>, (f64, f64))> {
+        let (flow_url, nodes) = {
+            let state = self.state.borrow();
+            let graph = state.graph()?;
+            let nodes: Vec<(String, f64, f64)> = state.highlight.selected.iter()
+                .filter_map(|index| graph.nodes.get(*index))
+                .map(|node| (node.alias.clone(), node.bounds.x, node.bounds.y))
+                .collect();
+            (graph.source_url.clone(), nodes)
+        };
+
+        if nodes.is_empty() {
+            UiContext::message("Select the processes to copy first");
+            return None;
+        }
+
+        let aliases: Vec<String> = nodes.iter().map(|(alias, _, _)| alias.clone()).collect();
+        match actions::copy_processes(&flow_url, &aliases) {
+            Ok(fragment) => {
+                let left = nodes.iter().map(|(_, x, _)| *x).fold(f64::MAX, f64::min);
+                let top = nodes.iter().map(|(_, _, y)| *y).fold(f64::MAX, f64::min);
+                let offsets = nodes.into_iter().map(|(alias, x, y)| (alias, (x - left, y - top))).collect();
+                UiContext::message(&format!("Copied {} processes", aliases.len()));
+                Some((fragment, offsets, (left, top)))
+            }
+            Err(message) => {
+                UiContext::message(&message);
+                None
+            }
+        }
+    }
+
+    // Paste copied processes into the flow being shown, with the top left of the group at 'origin'
+    fn paste(&self, fragment: Fragment, offsets: &HashMap<String, (f64, f64)>, (left, top): (f64, f64)) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.is_read_only() {
+                drop(state);
+                UiContext::message("Flows referenced from a library cannot be edited");
+                return;
+            }
+
+            // Place the pasted processes using the aliases they will be given
+            let existing: Vec<String> = match state.graph() {
+                Some(graph) => graph.nodes.iter().map(|node| node.alias.clone()).collect(),
+                None => return
+            };
+            let renames = flow_edit::paste_aliases(&existing, &fragment);
+            if let Some(location) = state.location() {
+                let flow_positions = state.positions.entry(location).or_insert_with(HashMap::new);
+                for (alias, (x, y)) in offsets {
+                    let new_alias = renames.get(alias).unwrap_or(alias);
+                    flow_positions.insert(new_alias.clone(), (left + x, top + y));
+                }
+            }
+        }
+
+        self.save_positions();
+        self.emit_edit(FlowEdit::Paste { fragment });
+    }
+
+    // Ask for the name of a new flow to move the selected nodes into, and put the node for it where
+    // they were
+    fn extract_selected(&self) {
+        let (aliases, remaining, position) = {
+            let state = self.state.borrow();
+            if state.is_read_only() {
+                drop(state);
+                UiContext::message("Flows referenced from a library cannot be edited");
+                return;
+            }
+            let graph = match state.graph() {
+                Some(graph) => graph,
+                None => return
+            };
+
+            let selected: Vec<&Node> = state.highlight.selected.iter().filter_map(|index| graph.nodes.get(*index)).collect();
+            let remaining: Vec<String> = graph.nodes.iter().map(|node| node.alias.clone())
+                .filter(|alias| !selected.iter().any(|node| node.alias == *alias))
+                .collect();
+            let left = selected.iter().map(|node| node.bounds.x).fold(f64::MAX, f64::min);
+            let top = selected.iter().map(|node| node.bounds.y).fold(f64::MAX, f64::min);
+            (selected.iter().map(|node| node.alias.clone()).collect::<Vec<String>>(), remaining, (left, top))
+        };
+
+        if aliases.is_empty() {
+            UiContext::message("Select the processes to extract into a new flow first");
+            return;
+        }
+
+        if let Some(name) = self.ask_for_name("Extract Sub-flow", "Name of the new flow", "") {
+            let alias = flow_edit::unique_alias(&remaining, &name);
+            {
+                let mut state = self.state.borrow_mut();
+                if let Some(location) = state.location() {
+                    state.positions.entry(location).or_insert_with(HashMap::new).insert(alias, position);
+                }
+            }
+            self.save_positions();
+            self.emit(GraphChange::Extract { aliases, name });
+        }
+    }
+
+    // Replace the selected sub-flow with its contents
+    fn inline_selected(&self) {
+        let alias = {
+            let state = self.state.borrow();
+            let graph = match state.graph() {
+                Some(graph) => graph,
+                None => return
+            };
+            match state.highlight.selected.as_slice() {
+                [index] => graph.nodes.get(*index).filter(|node| node.kind == NodeKind::Flow).map(|node| node.alias.clone()),
+                _ => None
+            }
+        };
+
+        match alias {
+            Some(alias) if self.state.borrow().is_read_only() => {
+                UiContext::message(&format!("'{}' cannot be inlined as the flow it is in cannot be edited", alias));
+            }
+            Some(alias) => self.emit(GraphChange::Inline { alias }),
+            None => UiContext::message("Select a sub-flow to inline first")
+        }
+    }
+
+    // Rename the port under the pointer, or else the selected process, everywhere it is used
+    fn rename_at_pointer(&self) {
+        let target = {
+            let state = self.state.borrow();
+            let graph = match state.graph() {
+                Some(graph) => graph,
+                None => return
+            };
+
+            let port = state.pointer.and_then(|(x, y)| graph.port_at(x, y));
+            match port {
+                Some(Endpoint::FlowInput(name)) =>
+                    Ok((Renamed::Port { flow_url: graph.source_url.clone(), name: name.clone(), input: true }, name)),
+                Some(Endpoint::FlowOutput(name)) =>
+                    Ok((Renamed::Port { flow_url: graph.source_url.clone(), name: name.clone(), input: false }, name)),
+                Some(Endpoint::NodeInput(index, name)) => renamed_port(&graph.nodes[index], name, true),
+                Some(Endpoint::NodeOutput(index, name)) => renamed_port(&graph.nodes[index], name, false),
+                None => match state.highlight.selected.as_slice() {
+                    [index] => graph.nodes.get(*index)
+                        .map(|node| (Renamed::Process { alias: node.alias.clone() }, node.alias.clone()))
+                        .ok_or_else(|| "Select a process, or point at a port, to rename first".to_string()),
+                    _ => Err("Select a process, or point at a port, to rename first".into())
+                }
+            }
+        };
+
+        let (renamed, current) = match target {
+            Ok(target) if self.state.borrow().is_read_only() => {
+                UiContext::message(&format!("'{}' cannot be renamed as the flow it is in cannot be edited", target.1));
+                return;
+            }
+            Ok(target) => target,
+            Err(message) => {
+                UiContext::message(&message);
+                return;
+            }
+        };
+
+        let name = match self.ask_for_name("Rename", "New name", &current) {
+            Some(name) if name != current => name,
+            _ => return
+        };
+
+        self.emit(GraphChange::Rename { renamed, name });
+    }
+
+    // Ask the user for a name in a dialog, returning None if they cancel or give no name
+    fn ask_for_name(&self, title: &str, prompt: &str, initial: &str) -> Option<String> {
+        let window = self.container.get_toplevel().and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok());
+        let dialog = gtk::Dialog::with_buttons(Some(title), window.as_ref(), gtk::DialogFlags::MODAL,
+                                               &[("OK", gtk::ResponseType::Ok),
+                                                   ("Cancel", gtk::ResponseType::Cancel)]);
+
+        let content = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        content.set_border_width(10);
+        content.pack_start(&Label::new(Some(prompt)), false, false, 0);
+        let entry = gtk::Entry::new();
+        entry.set_text(initial);
+        entry.set_activates_default(true);
+        content.pack_start(&entry, true, true, 0);
+        dialog.get_content_area().pack_start(&content, true, true, 0);
+        dialog.set_default_response(gtk::ResponseType::Ok);
+        dialog.show_all();
+
+        let response = dialog.run();
+        let name = entry.get_text().trim().to_string();
+        unsafe { dialog.destroy(); }
+
+        if response == gtk::ResponseType::Ok && !name.is_empty() {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    // Add a process with 'source', dropped at (x, y) on the view, to the flow being shown
+    fn add_process(&self, source: &str, x: f64, y: f64) {
+        let edit = {
+            let mut state = self.state.borrow_mut();
+            if state.is_read_only() {
+                drop(state);
+                UiContext::message("Flows referenced from a library cannot be edited");
+                return;
+            }
+
+            let (graph_x, graph_y) = state.viewport.to_graph(x, y);
+            let (aliases, flow_url) = match state.graph() {
+                Some(graph) => (graph.nodes.iter().map(|node| node.alias.clone()).collect::<Vec<String>>(),
+                                graph.source_url.clone()),
+                None => return
+            };
+            let alias = flow_edit::unique_alias(&aliases, source);
+            if let Some(location) = state.location() {
+                state.positions.entry(location).or_insert_with(HashMap::new)
+                    .insert(alias.clone(), (graph_x, graph_y));
+            }
+            FlowEdit::AddProcess { alias, source: flow_edit::relative_source(&flow_url, source) }
+        };
+
+        self.save_positions();
+        self.emit_edit(edit);
+    }
+
+    fn emit_edit(&self, edit: FlowEdit) {
+        self.emit(GraphChange::Edit(edit));
+    }
+
+    // Pass a change of the flow being shown to the change handler. The state is not borrowed while
+    // the handler runs, as it may set a new graph on the view.
+    fn emit(&self, change: GraphChange) {
+        let (handler, flow_url) = {
+            let state = self.state.borrow();
+            match (state.change_handler.clone(), state.graph()) {
+                (Some(handler), Some(graph)) if !state.is_read_only() => (handler, graph.source_url.clone()),
+                _ => return
+            }
+        };
+
+        handler(flow_url, change);
+    }
+
+    // The minimap draws the whole graph with the part visible in the main view outlined.
+    // Clicking or dragging in it moves the main view to center on that point.
+    fn connect_minimap_signals(&self) {
+        let view = self.clone();
+        self.minimap.connect_draw(move |_, cr| {
+            let state = view.state.borrow();
+            if let Some(graph) = state.graph() {
+                let (offset_x, offset_y, scale) = minimap_transform(graph);
+                cr.save();
+                cr.translate(offset_x, offset_y);
+                cr.scale(scale, scale);
+                graph_painter::paint(cr, graph, &Highlight::default());
+                cr.restore();
+
+                let width = view.drawing_area.get_allocated_width() as f64;
+                let height = view.drawing_area.get_allocated_height() as f64;
+                let (left, top) = state.viewport.to_graph(0.0, 0.0);
+                let (right, bottom) = state.viewport.to_graph(width, height);
+                cr.set_source_rgba(0.30, 0.50, 0.85, 0.9);
+                cr.set_line_width(1.5);
+                cr.rectangle(offset_x + left * scale, offset_y + top * scale,
+                             (right - left) * scale, (bottom - top) * scale);
+                cr.stroke();
+
+                cr.set_source_rgb(0.5, 0.5, 0.5);
+                cr.set_line_width(1.0);
+                cr.rectangle(0.5, 0.5, MINIMAP_WIDTH as f64 - 1.0, MINIMAP_HEIGHT as f64 - 1.0);
+                cr.stroke();
+            }
+            Inhibit(false)
+        });
+
+        let view = self.clone();
+        self.minimap.connect_button_press_event(move |_, event| {
+            let (x, y) = event.get_position();
+            view.center_from_minimap(x, y);
+            Inhibit(true)
+        });
+
+        let view = self.clone();
+        self.minimap.connect_motion_notify_event(move |_, event| {
+            if event.get_state().contains(ModifierType::BUTTON1_MASK) {
+                let (x, y) = event.get_position();
+                view.center_from_minimap(x, y);
+            }
+            Inhibit(true)
+        });
+    }
+
+    fn center_from_minimap(&self, x: f64, y: f64) {
+        let width = self.drawing_area.get_allocated_width() as f64;
+        let height = self.drawing_area.get_allocated_height() as f64;
+        {
+            let mut state = self.state.borrow_mut();
+            let transform = state.graph().map(minimap_transform);
+            if let Some((offset_x, offset_y, scale)) = transform {
+                state.viewport.center_on((x - offset_x) / scale, (y - offset_y) / scale, width, height);
+            }
+        }
+        self.redraw();
+    }
+
+    fn describe_node(graph: &FlowGraph, index: usize) -> String {
+        let node = &graph.nodes[index];
+        let mut description = format!("{} ({})\nsource: {}", node.alias, node.name, node.source);
+        for input in &node.inputs {
+            description.push_str(&format!("\ninput '{}': {}", input.name, input.datatype));
+        }
+        for output in &node.outputs {
+            description.push_str(&format!("\noutput '{}': {}", output.name, output.datatype));
+        }
+        description
+    }
+}
+
+impl ViewState {
+    // The graph of the (sub)flow being shown
+    fn graph(&self) -> Option<&FlowGraph> {
+        let mut graph = self.root.as_ref()?;
+        for index in &self.path {
+            graph = graph.nodes.get(*index)?.sub_graph.as_ref()?;
+        }
+        Some(graph)
+    }
+
+    fn graph_mut(&mut self) -> Option<&mut FlowGraph> {
+        let mut graph = self.root.as_mut()?;
+        for index in &self.path {
+            graph = graph.nodes.get_mut(*index)?.sub_graph.as_mut()?;
+        }
+        Some(graph)
+    }
+
+    // Move the nodes the user has moved back to where they were moved to, in the root flow and
+    // its sub-flows
+    fn place_nodes(&mut self) {
+        if let Some(root) = self.root.as_mut() {
+            let location = root.source_url.clone();
+            place_nodes(root, &location, &self.positions);
+        }
+    }
+
+    // A key for the (sub)flow being shown: the root flow's url and the aliases leading to it
+    fn location(&self) -> Option<String> {
+        let root = self.root.as_ref()?;
+        let mut location = root.source_url.clone();
+        let mut graph = root;
+        for index in &self.path {
+            let node = graph.nodes.get(*index)?;
+            location.push('/');
+            location.push_str(&node.alias);
+            graph = node.sub_graph.as_ref()?;
+        }
+        Some(location)
+    }
+
+    // The labels for the breadcrumb bar: the root flow's name then the alias of each sub-flow
+    fn crumbs(&self) -> Vec<String> {
+        let mut crumbs = vec!();
+        if let Some(mut graph) = self.root.as_ref() {
+            crumbs.push(graph.name.clone());
+            for index in &self.path {
+                match graph.nodes.get(*index) {
+                    Some(node) => {
+                        crumbs.push(node.alias.clone());
+                        match node.sub_graph.as_ref() {
+                            Some(sub_graph) => graph = sub_graph,
+                            None => break
+                        }
+                    }
+                    None => break
+                }
+            }
+        }
+        crumbs
+    }
+
+    // A flow is read-only if it, or any flow between it and the root, was referenced from a
+    // library or the runtime context. The root flow is read-only if not loaded from a file.
+    fn is_read_only(&self) -> bool {
+        let root = match self.root.as_ref() {
+            Some(root) => root,
+            None => return true
+        };
+
+        if !root.source_url.starts_with("file:") {
+            return true;
+        }
+
+        let mut graph = root;
+        for index in &self.path {
+            match graph.nodes.get(*index) {
+                Some(node) => {
+                    if READ_ONLY_SCHEMES.iter().any(|scheme| node.source.starts_with(scheme)) {
+                        return true;
+                    }
+                    match node.sub_graph.as_ref() {
+                        Some(sub_graph) => graph = sub_graph,
+                        None => return true
+                    }
+                }
+                None => return true
+            }
+        }
+
+        false
+    }
+
+    // Save the viewport of the flow currently shown so it can be restored if shown again
+    fn remember_viewport(&mut self) {
+        if let Some(location) = self.location() {
+            self.viewports.insert(location, self.viewport);
+        }
+    }
+}
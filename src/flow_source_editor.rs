@@ -0,0 +1,322 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gdk::ModifierType;
+use gdk::keys::constants as keys;
+use gtk::{Inhibit, Label, ListBox, PolicyType, Popover, PositionType, ScrolledWindow, TextBuffer, TextTag, TextView,
+          TextWindowType};
+use gtk::prelude::*;
+
+use crate::{actions, completion};
+use crate::completion::Completion;
+use crate::syntax::{self, Problem, SourceFormat, TokenKind, TOKEN_KINDS};
+use crate::ui_context::UiContext;
+
+// How long to wait after typing stops before checking the definition, in milliseconds
+const CHECK_DELAY: u32 = 600;
+const PROBLEM_TAG: &str = "problem";
+// The pango weight of bold text
+const BOLD: i32 = 700;
+// Height of the list of completions, which scrolls if there are more
+const COMPLETION_HEIGHT: i32 = 200;
+
+// State of the editor that is shared between the gtk signal handlers
+#[derive(Default)]
+struct EditorState {
+    url: Option<String>,
+    // Incremented on every change, so that only the result of checking the latest text is shown
+    generation: u64,
+    problems: Vec<Problem>,
+    // The completions being offered and the number of characters before the cursor they replace
+    completions: Vec<Completion>,
+    typed_length: usize,
+}
+
+/// An editor for the definition of the flow, that highlights its syntax, underlines problems
+/// found when the definition is parsed and its processes loaded, and offers completions (Ctrl+Space)
+/// of process sources and connection routes
+#[derive(Clone)]
+pub struct FlowSourceEditor {
+    scroll: ScrolledWindow,
+    view: TextView,
+    buffer: TextBuffer,
+    completion_popover: Popover,
+    completion_list: ListBox,
+    state: Rc<RefCell<EditorState>>,
+}
+
+impl FlowSourceEditor {
+    pub fn new() -> Self {
+        let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        let view = TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.set_has_tooltip(true);
+        scroll.add(&view);
+        let buffer = view.get_buffer().unwrap();
+
+        let completion_popover = Popover::new(Some(&view));
+        completion_popover.set_position(PositionType::Bottom);
+        let completion_scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        completion_scroll.set_policy(PolicyType::Never, PolicyType::Automatic);
+        completion_scroll.set_min_content_height(COMPLETION_HEIGHT);
+        let completion_list = ListBox::new();
+        completion_scroll.add(&completion_list);
+        completion_popover.add(&completion_scroll);
+
+        let editor = FlowSourceEditor {
+            scroll,
+            view,
+            buffer,
+            completion_popover,
+            completion_list,
+            state: Rc::new(RefCell::new(EditorState::default())),
+        };
+        editor.create_tags();
+        editor.connect_signals();
+        editor
+    }
+
+    pub fn widget(&self) -> &ScrolledWindow {
+        &self.scroll
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
+    /// Show the definition loaded from 'url', or clear the editor if there is none. Only
+    /// definitions that can be saved back to where they were loaded from can be edited.
+    pub fn set_source(&self, url: Option<String>, text: Option<&str>, editable: bool) {
+        self.state.borrow_mut().url = url;
+        self.buffer.set_text(text.unwrap_or(""));
+        self.buffer.set_modified(false);
+        self.view.set_editable(editable);
+    }
+
+    /// The url the definition being edited was loaded from, if any
+    pub fn url(&self) -> Option<String> {
+        self.state.borrow().url.clone()
+    }
+
+    /// All the text currently in the editor
+    pub fn contents(&self) -> String {
+        let (start, end) = self.buffer.get_bounds();
+        self.buffer.get_text(&start, &end, false).map(|text| text.to_string()).unwrap_or_default()
+    }
+
+    /// Select the first occurrence of 'text' in the definition, preferring one that is quoted, and
+    /// scroll to show it
+    pub fn show_text(&self, text: &str) {
+        let found = Problem::at_text(&self.contents(), text, String::new());
+        let start = self.buffer.get_iter_at_offset(found.start as i32);
+        let end = self.buffer.get_iter_at_offset(found.end as i32);
+        self.buffer.select_range(&start, &end);
+        let mut start = start;
+        self.view.scroll_to_iter(&mut start, 0.1, false, 0.0, 0.0);
+        self.view.grab_focus();
+    }
+
+    /// Underline the problems found when checking the text of version 'generation' of the
+    /// definition. Returns the number of problems, or None if the text has changed since.
+    pub fn show_problems(&self, generation: u64, problems: Vec<Problem>) -> Option<usize> {
+        let mut state = self.state.borrow_mut();
+        if state.generation != generation {
+            return None;
+        }
+
+        let (start, end) = self.buffer.get_bounds();
+        self.buffer.remove_tag_by_name(PROBLEM_TAG, &start, &end);
+        for problem in &problems {
+            let start = self.buffer.get_iter_at_offset(problem.start as i32);
+            let end = self.buffer.get_iter_at_offset(problem.end as i32);
+            self.buffer.apply_tag_by_name(PROBLEM_TAG, &start, &end);
+        }
+
+        state.problems = problems;
+        Some(state.problems.len())
+    }
+
+    /// Offer the completions found for the 'typed' start of a value at the cursor, if the text
+    /// has not changed since completion was requested ('generation')
+    pub fn show_completions(&self, generation: u64, typed: &str, completions: Vec<Completion>) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.generation != generation || completions.is_empty() {
+                return;
+            }
+            state.typed_length = typed.chars().count();
+            state.completions = completions;
+        }
+
+        for row in self.completion_list.get_children() {
+            self.completion_list.remove(&row);
+        }
+        for completion in &self.state.borrow().completions {
+            let label = Label::new(Some(&completion.value));
+            label.set_xalign(0.0);
+            label.set_tooltip_text(Some(&completion.detail));
+            self.completion_list.add(&label);
+        }
+
+        // Point at the cursor, converting its position from buffer to widget coordinates
+        let cursor = self.buffer.get_iter_at_mark(&self.buffer.get_insert().unwrap());
+        let location = self.view.get_iter_location(&cursor);
+        let (x, y) = self.view.buffer_to_window_coords(TextWindowType::Widget, location.x, location.y);
+        self.completion_popover.set_pointing_to(&gdk::Rectangle { x, y, width: 1, height: location.height });
+
+        self.completion_popover.show_all();
+        self.completion_list.select_row(self.completion_list.get_row_at_index(0).as_ref());
+        if let Some(row) = self.completion_list.get_row_at_index(0) {
+            row.grab_focus();
+        }
+    }
+
+    // Find what is being written at the cursor and ask for the completions of it
+    fn request_completion(&self) {
+        let (url, generation) = {
+            let state = self.state.borrow();
+            match state.url {
+                Some(ref url) if self.view.get_editable() => (url.clone(), state.generation),
+                _ => return
+            }
+        };
+
+        let cursor = self.buffer.get_iter_at_mark(&self.buffer.get_insert().unwrap());
+        let line_start = self.buffer.get_iter_at_line(cursor.get_line());
+        let line = self.buffer.get_text(&line_start, &cursor, false).map(|text| text.to_string()).unwrap_or_default();
+
+        match completion::completion_at(SourceFormat::from_url(&url), &line) {
+            Some((kind, typed)) => actions::complete_flow_source(url, self.contents(), kind, typed, generation),
+            None => UiContext::message("Completions are offered for the 'source' of a process and the \
+                                        'from' and 'to' of a connection")
+        }
+    }
+
+    // Replace what had been typed before the cursor with the completion chosen
+    fn complete(&self, index: usize) {
+        let (value, typed_length) = {
+            let state = self.state.borrow();
+            match state.completions.get(index) {
+                Some(completion) => (completion.value.clone(), state.typed_length),
+                None => return
+            }
+        };
+
+        self.completion_popover.hide();
+        let mut end = self.buffer.get_iter_at_mark(&self.buffer.get_insert().unwrap());
+        let mut start = end.clone();
+        start.backward_chars(typed_length as i32);
+        self.buffer.delete(&mut start, &mut end);
+        self.buffer.insert(&mut start, &value);
+        self.view.grab_focus();
+    }
+
+    fn create_tags(&self) {
+        let tag_table = self.buffer.get_tag_table().unwrap();
+        for kind in TOKEN_KINDS {
+            let tag = TextTag::new(Some(kind.tag()));
+            match kind {
+                TokenKind::Comment => {
+                    tag.set_property_foreground(Some("#7f8c8d"));
+                    tag.set_property_style(pango::Style::Italic);
+                }
+                TokenKind::Key => tag.set_property_foreground(Some("#2a5db0")),
+                TokenKind::String => tag.set_property_foreground(Some("#3c7a3c")),
+                TokenKind::Number => tag.set_property_foreground(Some("#8e44ad")),
+                TokenKind::Keyword => tag.set_property_foreground(Some("#c0392b")),
+                TokenKind::Table => {
+                    tag.set_property_foreground(Some("#a04000"));
+                    tag.set_property_weight(BOLD);
+                }
+            }
+            tag_table.add(&tag);
+        }
+
+        let problem_tag = TextTag::new(Some(PROBLEM_TAG));
+        problem_tag.set_property_underline(pango::Underline::Error);
+        tag_table.add(&problem_tag);
+    }
+
+    // Highlight the whole of the text, flow definitions are small enough to do so on every change
+    fn highlight(&self) {
+        let format = SourceFormat::from_url(self.state.borrow().url.as_deref().unwrap_or(""));
+        let (start, end) = self.buffer.get_bounds();
+        for kind in TOKEN_KINDS {
+            self.buffer.remove_tag_by_name(kind.tag(), &start, &end);
+        }
+
+        for token in syntax::tokens(format, &self.contents()) {
+            let start = self.buffer.get_iter_at_offset(token.start as i32);
+            let end = self.buffer.get_iter_at_offset(token.end as i32);
+            self.buffer.apply_tag_by_name(token.kind.tag(), &start, &end);
+        }
+    }
+
+    // Check the definition once typing has stopped for a while, as parsing it and loading the
+    // processes it references may take some time
+    fn schedule_check(&self) {
+        let generation = {
+            let mut state = self.state.borrow_mut();
+            state.generation += 1;
+            state.generation
+        };
+
+        let editor = self.clone();
+        glib::timeout_add_local(CHECK_DELAY, move || {
+            let url = {
+                let state = editor.state.borrow();
+                if state.generation != generation {
+                    return glib::Continue(false);
+                }
+                state.url.clone()
+            };
+
+            if let Some(url) = url {
+                actions::check_flow_source(url, editor.contents(), generation);
+            }
+            glib::Continue(false)
+        });
+    }
+
+    fn connect_signals(&self) {
+        let editor = self.clone();
+        self.buffer.connect_changed(move |_| {
+            editor.highlight();
+            editor.schedule_check();
+        });
+
+        let editor = self.clone();
+        self.view.connect_key_press_event(move |_, event| {
+            if event.get_keyval() == keys::space && event.get_state().contains(ModifierType::CONTROL_MASK) {
+                editor.request_completion();
+                Inhibit(true)
+            } else {
+                Inhibit(false)
+            }
+        });
+
+        let editor = self.clone();
+        self.completion_list.connect_row_activated(move |_, row| {
+            editor.complete(row.get_index() as usize);
+        });
+
+        // Show the message for a problem when the pointer is over its underline
+        let state = self.state.clone();
+        self.view.connect_query_tooltip(move |view, x, y, _keyboard_mode, tooltip| {
+            let (x, y) = view.window_to_buffer_coords(TextWindowType::Widget, x, y);
+            let offset = match view.get_iter_at_location(x, y) {
+                Some(iter) => iter.get_offset() as usize,
+                None => return false
+            };
+
+            match state.borrow().problems.iter().find(|p| offset >= p.start && offset < p.end) {
+                Some(problem) => {
+                    tooltip.set_text(Some(&problem.message));
+                    true
+                }
+                None => false
+            }
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use std::f64::consts::PI;
+
+use cairo::{Context, FontSlant, FontWeight};
+
+use crate::flow_graph::{curve_control, Endpoint, FlowGraph, Node, NodeKind, Port, NESTED_SCALE};
+
+const PORT_RADIUS: f64 = 4.0;
+// Sub-flows nested deeper than this are drawn without their contents
+const MAX_NESTING: usize = 3;
+const FONT_SIZE: f64 = 11.0;
+
+// Colours used for the parts of the graph
+const BACKGROUND: (f64, f64, f64) = (1.0, 1.0, 1.0);
+const FUNCTION_FILL: (f64, f64, f64) = (0.87, 0.92, 0.98);
+const FLOW_FILL: (f64, f64, f64) = (0.93, 0.97, 0.90);
+const OUTLINE: (f64, f64, f64) = (0.25, 0.25, 0.30);
+const SELECTED: (f64, f64, f64) = (0.90, 0.45, 0.10);
+const HOVERED: (f64, f64, f64) = (0.30, 0.50, 0.85);
+const EDGE: (f64, f64, f64) = (0.35, 0.35, 0.40);
+const TEXT: (f64, f64, f64) = (0.10, 0.10, 0.10);
+const PORT_TEXT: (f64, f64, f64) = (0.35, 0.35, 0.35);
+
+/// The nodes and edge of the graph to draw highlighted
+#[derive(Debug, Clone, Default)]
+pub struct Highlight {
+    pub selected: Vec<usize>,
+    pub hovered: Option<usize>,
+    pub selected_edge: Option<usize>,
+}
+
+fn set_colour(cr: &Context, (r, g, b): (f64, f64, f64)) {
+    cr.set_source_rgb(r, g, b);
+}
+
+/// Paint the whole of the graph, in graph coordinates, onto the cairo context
+pub fn paint(cr: &Context, graph: &FlowGraph, highlight: &Highlight) {
+    set_colour(cr, BACKGROUND);
+    cr.paint();
+
+    cr.select_font_face("Sans", FontSlant::Normal, FontWeight::Normal);
+    cr.set_font_size(FONT_SIZE);
+
+    paint_graph(cr, graph, highlight, 0);
+}
+
+// Paint the flow's own ports, its connections and then its nodes on top of them
+fn paint_graph(cr: &Context, graph: &FlowGraph, highlight: &Highlight, depth: usize) {
+    paint_flow_ports(cr, graph);
+
+    for (index, edge) in graph.edges.iter().enumerate() {
+        let touches_selected = edge.from.node().map_or(false, |node| highlight.selected.contains(&node)) ||
+            edge.to.node().map_or(false, |node| highlight.selected.contains(&node));
+        paint_edge(cr, &edge.points, edge.loop_back, touches_selected || highlight.selected_edge == Some(index));
+    }
+
+    for (index, node) in graph.nodes.iter().enumerate() {
+        let outline = if highlight.selected.contains(&index) {
+            SELECTED
+        } else if highlight.hovered == Some(index) {
+            HOVERED
+        } else {
+            OUTLINE
+        };
+        paint_node(cr, node, outline, depth);
+    }
+}
+
+// The flow's inputs are drawn on the left edge of the graph and its outputs on the right
+fn paint_flow_ports(cr: &Context, graph: &FlowGraph) {
+    for input in &graph.inputs {
+        let (x, y) = graph.anchor(&Endpoint::FlowInput(input.name.clone()));
+        paint_port(cr, x, y);
+        paint_label(cr, input, x + PORT_RADIUS + 2.0, y, false);
+    }
+
+    for output in &graph.outputs {
+        let (x, y) = graph.anchor(&Endpoint::FlowOutput(output.name.clone()));
+        paint_port(cr, x, y);
+        paint_label(cr, output, x - PORT_RADIUS - 2.0, y, true);
+    }
+}
+
+fn paint_edge(cr: &Context, points: &[(f64, f64)], loop_back: bool, highlighted: bool) {
+    if points.len() < 2 {
+        return;
+    }
+
+    set_colour(cr, if highlighted { SELECTED } else { EDGE });
+    cr.set_line_width(if highlighted { 2.0 } else { 1.2 });
+
+    let (start_x, start_y) = points[0];
+    cr.move_to(start_x, start_y);
+    if loop_back {
+        // loops are routed around other nodes with straight segments
+        for (x, y) in &points[1..] {
+            cr.line_to(*x, *y);
+        }
+    } else {
+        for window in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+            // horizontal tangents at each point give smooth curves in and out of the ports
+            let control = curve_control(x0, x1);
+            cr.curve_to(x0 + control, y0, x1 - control, y1, x1, y1);
+        }
+    }
+    cr.stroke();
+
+    // arrow head at the destination port
+    let (end_x, end_y) = points[points.len() - 1];
+    cr.move_to(end_x, end_y);
+    cr.line_to(end_x - 8.0, end_y - 4.0);
+    cr.line_to(end_x - 8.0, end_y + 4.0);
+    cr.close_path();
+    cr.fill();
+}
+
+fn paint_node(cr: &Context, node: &Node, outline: (f64, f64, f64), depth: usize) {
+    let bounds = &node.bounds;
+
+    set_colour(cr, match node.kind {
+        NodeKind::Function => FUNCTION_FILL,
+        NodeKind::Flow => FLOW_FILL,
+    });
+    rounded_rectangle(cr, bounds.x, bounds.y, bounds.width, bounds.height, 6.0);
+    cr.fill_preserve();
+    set_colour(cr, outline);
+    cr.set_line_width(if outline == OUTLINE { 1.0 } else { 2.5 });
+    cr.stroke();
+
+    // Title: alias in bold with the process name under it
+    set_colour(cr, TEXT);
+    cr.select_font_face("Sans", FontSlant::Normal, FontWeight::Bold);
+    centered_text(cr, &node.alias, bounds.x + bounds.width / 2.0, bounds.y + 14.0);
+    cr.select_font_face("Sans", FontSlant::Italic, FontWeight::Normal);
+    set_colour(cr, PORT_TEXT);
+    centered_text(cr, &node.name, bounds.x + bounds.width / 2.0, bounds.y + 27.0);
+    cr.select_font_face("Sans", FontSlant::Normal, FontWeight::Normal);
+
+    for input in &node.inputs {
+        let (x, y) = node.input_anchor(&input.name);
+        paint_port(cr, x, y);
+        paint_label(cr, input, x + PORT_RADIUS + 2.0, y, false);
+    }
+
+    for output in &node.outputs {
+        let (x, y) = node.output_anchor(&output.name);
+        paint_port(cr, x, y);
+        paint_label(cr, output, x - PORT_RADIUS - 2.0, y, true);
+    }
+
+    // A sub-flow's contents are drawn scaled down inside it
+    if depth >= MAX_NESTING {
+        return;
+    }
+
+    if let Some(ref sub_graph) = node.sub_graph {
+        let (x, y) = node.content_origin();
+        cr.save();
+        cr.translate(x, y);
+        cr.scale(NESTED_SCALE, NESTED_SCALE);
+        cr.rectangle(0.0, 0.0, sub_graph.bounds.width, sub_graph.bounds.height);
+        cr.clip();
+        paint_graph(cr, sub_graph, &Highlight::default(), depth + 1);
+        cr.restore();
+    }
+}
+
+fn paint_port(cr: &Context, x: f64, y: f64) {
+    cr.arc(x, y, PORT_RADIUS, 0.0, 2.0 * PI);
+    set_colour(cr, BACKGROUND);
+    cr.fill_preserve();
+    set_colour(cr, OUTLINE);
+    cr.set_line_width(1.0);
+    cr.stroke();
+}
+
+// Draw the label of a port beside it, to the left of 'x' if 'right_aligned'
+fn paint_label(cr: &Context, port: &Port, x: f64, y: f64, right_aligned: bool) {
+    let label = port.label();
+    if label.is_empty() {
+        return;
+    }
+
+    let extents = cr.text_extents(label);
+    let text_x = if right_aligned { x - extents.x_advance } else { x };
+    set_colour(cr, PORT_TEXT);
+    cr.move_to(text_x, y + extents.height / 2.0);
+    cr.show_text(label);
+}
+
+fn centered_text(cr: &Context, text: &str, center_x: f64, baseline: f64) {
+    let extents = cr.text_extents(text);
+    cr.move_to(center_x - extents.x_advance / 2.0, baseline);
+    cr.show_text(text);
+}
+
+fn rounded_rectangle(cr: &Context, x: f64, y: f64, width: f64, height: f64, radius: f64) {
+    cr.new_sub_path();
+    cr.arc(x + width - radius, y + radius, radius, -PI / 2.0, 0.0);
+    cr.arc(x + width - radius, y + height - radius, radius, 0.0, PI / 2.0);
+    cr.arc(x + radius, y + height - radius, radius, PI / 2.0, PI);
+    cr.arc(x + radius, y + radius, radius, PI, 3.0 * PI / 2.0);
+    cr.close_path();
+}
+
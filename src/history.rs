@@ -0,0 +1,191 @@
+// The most changes kept for undoing, older ones are forgotten
+const MAX_CHANGES: usize = 100;
+
+/// A change made to the definitions of the flow, or its sub-flows, from the graph or the source
+/// editor. It holds the whole of each definition before and after the change, so that undoing it
+/// restores the definitions exactly as they were written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub description: String,
+    pub files: Vec<FileChange>,
+    // The saved positions of nodes that move with the processes renamed by the change
+    pub positions: Vec<MovedPositions>,
+}
+
+/// The text of one definition before and after a change. The text is empty when the definition
+/// does not exist, for a change that creates or deletes one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub url: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// The saved positions of a process, and of the nodes inside it if it is a sub-flow, moved from
+/// one alias to another in the (sub)flow at 'location' in the graph
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovedPositions {
+    pub location: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl MovedPositions {
+    /// The move that puts the positions back where they were
+    pub fn reversed(&self) -> Self {
+        MovedPositions { location: self.location.clone(), from: self.to.clone(), to: self.from.clone() }
+    }
+}
+
+impl Change {
+    /// A change to a single definition
+    pub fn single(description: String, url: String, before: String, after: String) -> Self {
+        Change { description, files: vec!(FileChange { url, before, after }), positions: vec!() }
+    }
+}
+
+/// The changes made to the flow being edited, that can be undone and then redone. The history is
+/// kept while the same flow is reloaded or compiled, and is cleared when another flow is loaded.
+#[derive(Debug, Default)]
+pub struct History {
+    root_url: Option<String>,
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    /// Set the url of the root flow loaded, clearing the history if it is a different flow
+    pub fn set_root(&mut self, url: Option<&str>) {
+        if self.root_url.as_deref() != url {
+            self.root_url = url.map(|url| url.to_string());
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Record a new change. Changes that had been undone can no longer be redone after it.
+    pub fn record(&mut self, change: Change) {
+        if change.files.iter().all(|file| file.before == file.after) {
+            return;
+        }
+
+        self.redo.clear();
+        self.push_undo(change);
+    }
+
+    fn push_undo(&mut self, change: Change) {
+        self.undo.push(change);
+        if self.undo.len() > MAX_CHANGES {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Take the last change made, to undo it
+    pub fn take_undo(&mut self) -> Option<Change> {
+        self.undo.pop()
+    }
+
+    /// Take the last change undone, to redo it
+    pub fn take_redo(&mut self) -> Option<Change> {
+        self.redo.pop()
+    }
+
+    /// Put back a change after undoing it, so it can be redone
+    pub fn undone(&mut self, change: Change) {
+        self.redo.push(change);
+    }
+
+    /// Put back a change after redoing it, so it can be undone again
+    pub fn redone(&mut self, change: Change) {
+        self.push_undo(change);
+    }
+
+    /// The description of the change that would be undone next, if any
+    pub fn next_undo(&self) -> Option<&str> {
+        self.undo.last().map(|change| change.description.as_str())
+    }
+
+    /// The description of the change that would be redone next, if any
+    pub fn next_redo(&self) -> Option<&str> {
+        self.redo.last().map(|change| change.description.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, History, MovedPositions, MAX_CHANGES};
+
+    fn change(description: &str) -> Change {
+        Change::single(description.into(), "file:///flow.toml".into(), "before".into(), format!("after {}", description))
+    }
+
+    #[test]
+    fn change_undone_then_redone() {
+        let mut history = History::default();
+        history.record(change("first"));
+        history.record(change("second"));
+        assert_eq!(history.next_undo(), Some("second"));
+
+        let undone = history.take_undo().unwrap();
+        history.undone(undone);
+        assert_eq!((history.next_undo(), history.next_redo()), (Some("first"), Some("second")));
+
+        let redone = history.take_redo().unwrap();
+        history.redone(redone);
+        assert_eq!((history.next_undo(), history.next_redo()), (Some("second"), None));
+    }
+
+    #[test]
+    fn new_change_cannot_redo_those_undone() {
+        let mut history = History::default();
+        history.record(change("first"));
+        let undone = history.take_undo().unwrap();
+        history.undone(undone);
+
+        history.record(change("second"));
+        assert_eq!(history.next_redo(), None);
+    }
+
+    #[test]
+    fn change_that_changes_nothing_not_recorded() {
+        let mut history = History::default();
+        history.record(Change::single("nothing".into(), "file:///flow.toml".into(), "same".into(), "same".into()));
+        assert_eq!(history.next_undo(), None);
+    }
+
+    #[test]
+    fn oldest_changes_forgotten() {
+        let mut history = History::default();
+        for count in 0..MAX_CHANGES + 5 {
+            history.record(change(&count.to_string()));
+        }
+        let mut undone = 0;
+        while history.take_undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_CHANGES);
+    }
+
+    #[test]
+    fn history_cleared_when_another_flow_loaded() {
+        let mut history = History::default();
+        history.set_root(Some("file:///flow.toml"));
+        history.record(change("first"));
+
+        history.set_root(Some("file:///flow.toml"));
+        assert_eq!(history.next_undo(), Some("first"));
+        history.set_root(Some("file:///other.toml"));
+        assert_eq!(history.next_undo(), None);
+    }
+
+    #[test]
+    fn moved_positions_reversed() {
+        let moved = MovedPositions { location: "file:///flow.toml".into(), from: "a".into(), to: "b".into() };
+        assert_eq!(moved.reversed(), MovedPositions { location: "file:///flow.toml".into(), from: "b".into(), to: "a".into() });
+        assert_eq!(moved.reversed().reversed(), moved);
+    }
+}
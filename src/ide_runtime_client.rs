@@ -3,11 +3,11 @@ use flowrlib::coordinator::Submission;
 use flowrlib::runtime::{Event, Response};
 use flowrlib::runtime::Response::ClientSubmission;
 use std::io::Write;
-// use gtk::{WidgetExt, ApplicationWindow, WindowPosition};
-// use gtk::prelude::*;
 use gtk::TextBufferExt;
 
 use crate::build_ui::widgets;
+use crate::image_window;
+use crate::stdin::STDIN;
 use crate::ui_context::UiContext;
 use std::fs::File;
 
@@ -55,6 +55,19 @@ impl IdeRuntimeClient {
         }
     }
 
+    // Read the input sent to STDIN, showing the user the flow is waiting for it if there is none yet
+    fn wait_for_stdin(read: impl Fn() -> Option<String>) -> Option<String> {
+        let waiting = !STDIN.is_available();
+        if waiting {
+            UiContext::waiting_for_stdin(true);
+        }
+        let input = read();
+        if waiting {
+            UiContext::waiting_for_stdin(false);
+        }
+        input
+    }
+
     fn process_event(&mut self, event: Event) -> Response {
         match event {
             Event::FlowStart => Response::Ack,
@@ -73,10 +86,16 @@ impl IdeRuntimeClient {
                 Response::Ack
             }
             Event::GetStdin => {
-                Response::Stdin("bla bla".to_string()) // TODO
+                match Self::wait_for_stdin(|| STDIN.get_buffered()) {
+                    Some(contents) => Response::Stdin(contents),
+                    None => Response::GetStdinEOF
+                }
             }
             Event::GetLine => {
-                Response::Stdin("bla bla".to_string())  // TODO
+                match Self::wait_for_stdin(|| STDIN.get_line()) {
+                    Some(line) => Response::Line(line),
+                    None => Response::GetLineEOF
+                }
             }
             Event::GetArgs => {
                 Response::Args(self.args.clone())
@@ -86,24 +105,8 @@ impl IdeRuntimeClient {
                 file.write_all(bytes.as_slice()).unwrap();
                 Response::Ack
             }
-            Event::PixelWrite((_x, _y), (_r, _g, _b), (_width, _height), _name) => {
-                // widgets::do_in_gtk_eventloop(|refs| {
-                //     let pix_buf = refs.image();
-                //     if pix_buf.get_width() == 0 {
-                //         pix_buf.set_width(width as i32);
-                //         pix_buf.set_height(height as i32);
-                //         let image = gtk::Image::from_pixbuf(Some(&pix_buf));
-                //
-                //         let image_window = ApplicationWindow::new(&refs.app());
-                //         image_window.set_title(&name);
-                //         image_window.set_position(WindowPosition::Center);
-                //         image_window.set_size_request(width as i32, height as i32);
-                //         image_window.add(&image);
-                //         image_window.show_all();
-                //     }
-                //     pix_buf.put_pixel(x, y, r, g, b, 255);
-                // });
-
+            Event::PixelWrite(point, color, size, name) => {
+                image_window::write_pixel(name, point, color, size);
                 Response::Ack
             }
             Event::StderrEOF => Response::Ack,
@@ -0,0 +1,205 @@
+//! Images written by a running flow a pixel at a time, each shown in a window of its own that
+//! updates as the pixels arrive and can be saved as a PNG file
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gdk::prelude::GdkContextExt;
+use gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::{ApplicationWindow, Button, DrawingArea, FileChooserAction, FileChooserDialog, FileFilter, HeaderBar,
+          Inhibit, ResponseType, WindowPosition};
+use gtk::prelude::*;
+use image::{ImageFormat, Rgb, RgbImage};
+use lazy_static::lazy_static;
+
+use crate::build_ui::widgets;
+use crate::ui_context::UiContext;
+
+// The largest a window is made to show an image at first, the user can make it larger
+const MAX_WINDOW_SIZE: u32 = 800;
+
+// The window showing an image, and the pixels written to it so far
+struct ImageWindow {
+    window: gtk::Window,
+    drawing_area: DrawingArea,
+    image: Rc<RefCell<RgbImage>>,
+}
+
+// A pixel written to an image: where it is, its color and the size of the image
+type Pixel = ((u32, u32), (u8, u8, u8), (u32, u32));
+
+thread_local! {
+    // The images written by the flow being run, by name. Only used on the gtk thread.
+    static IMAGES: RefCell<HashMap<String, ImageWindow>> = RefCell::new(HashMap::new());
+}
+
+lazy_static! {
+    // The pixels written to each image, by name, that are waiting to be drawn on the gtk thread
+    static ref WRITTEN: Mutex<HashMap<String, Vec<Pixel>>> = Mutex::new(HashMap::new());
+}
+
+// Set while there are pixels written that the gtk thread has been asked to draw
+static DRAW_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Write a pixel of the image called 'name', of size 'width' by 'height'. Pixels are buffered as
+/// they are written and drawn together on the gtk thread, each image in a window of its own shown
+/// when it is first written to. Pixels outside the image are ignored.
+pub fn write_pixel(name: String, point: (u32, u32), color: (u8, u8, u8), size: (u32, u32)) {
+    match WRITTEN.lock() {
+        Ok(mut written) => written.entry(name).or_default().push((point, color, size)),
+        Err(_) => return
+    }
+    if !DRAW_PENDING.swap(true, Ordering::SeqCst) {
+        widgets::do_in_gtk_eventloop(|refs| draw_written(&refs.app_window()));
+    }
+}
+
+// Draw all the pixels written since they were last drawn
+fn draw_written(parent: &ApplicationWindow) {
+    // Pixels written from now on are drawn the next time
+    DRAW_PENDING.store(false, Ordering::SeqCst);
+    let written = match WRITTEN.lock() {
+        Ok(mut written) => std::mem::take(&mut *written),
+        Err(_) => return
+    };
+
+    IMAGES.with(|images| {
+        let mut images = images.borrow_mut();
+        for (name, pixels) in written {
+            let (width, height) = match pixels.first() {
+                Some(&(_, _, size)) => size,
+                None => continue
+            };
+            let image_window = images.entry(name.clone())
+                .or_insert_with(|| ImageWindow::new(parent, &name, width, height));
+
+            {
+                let mut image = image_window.image.borrow_mut();
+                for ((x, y), (r, g, b), (width, height)) in pixels {
+                    if image.dimensions() != (width, height) {
+                        *image = RgbImage::new(width, height);
+                    }
+                    if x < width && y < height {
+                        image.put_pixel(x, y, Rgb([r, g, b]));
+                    }
+                }
+            }
+            image_window.drawing_area.queue_draw();
+        }
+    });
+}
+
+/// Show the windows of the images written by the last flow run again, after they were closed.
+/// Returns false if no images have been written.
+pub fn show_all() -> bool {
+    IMAGES.with(|images| {
+        let images = images.borrow();
+        for image_window in images.values() {
+            image_window.window.present();
+        }
+        !images.is_empty()
+    })
+}
+
+/// Close the windows of the images written by the last flow run, before running one again
+pub fn close_all() {
+    if let Ok(mut written) = WRITTEN.lock() {
+        written.clear();
+    }
+    IMAGES.with(|images| {
+        for (_, image_window) in images.borrow_mut().drain() {
+            unsafe { image_window.window.destroy(); }
+        }
+    });
+}
+
+impl ImageWindow {
+    fn new(parent: &ApplicationWindow, name: &str, width: u32, height: u32) -> Self {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_transient_for(Some(parent));
+        window.set_destroy_with_parent(true);
+        window.set_position(WindowPosition::CenterOnParent);
+        // Closing the window only hides it, so the pixels still to come are not shown in a new one,
+        // and it can be shown again from the "Flow" menu
+        window.connect_delete_event(|window, _| {
+            window.hide();
+            Inhibit(true)
+        });
+
+        let header = HeaderBar::new();
+        header.set_title(Some(name));
+        header.set_show_close_button(true);
+        let save = Button::with_label("Save as PNG…");
+        header.pack_end(&save);
+        window.set_titlebar(Some(&header));
+
+        let scale = (MAX_WINDOW_SIZE as f64 / width.max(height).max(1) as f64).min(1.0);
+        window.set_default_size((width as f64 * scale).max(1.0) as i32, (height as f64 * scale).max(1.0) as i32);
+
+        let drawing_area = DrawingArea::new();
+        drawing_area.set_hexpand(true);
+        drawing_area.set_vexpand(true);
+        window.add(&drawing_area);
+
+        let image_window = ImageWindow {
+            window,
+            drawing_area,
+            image: Rc::new(RefCell::new(RgbImage::new(width, height))),
+        };
+        image_window.connect_signals(&save, name);
+        image_window.window.show_all();
+        image_window
+    }
+
+    fn connect_signals(&self, save: &Button, name: &str) {
+        // Draw the image as large as fits in the window, keeping its shape
+        let image = self.image.clone();
+        self.drawing_area.connect_draw(move |area, cr| {
+            let image = image.borrow();
+            let (width, height) = image.dimensions();
+            if width == 0 || height == 0 {
+                return Inhibit(false);
+            }
+
+            let pixbuf = Pixbuf::from_bytes(&glib::Bytes::from(image.as_raw().as_slice()), Colorspace::Rgb, false, 8,
+                                            width as i32, height as i32, width as i32 * 3);
+            let (area_width, area_height) = (area.get_allocated_width() as f64, area.get_allocated_height() as f64);
+            let scale = (area_width / width as f64).min(area_height / height as f64);
+            cr.translate((area_width - width as f64 * scale) / 2.0, (area_height - height as f64 * scale) / 2.0);
+            cr.scale(scale, scale);
+            cr.set_source_pixbuf(&pixbuf, 0.0, 0.0);
+            cr.paint();
+            Inhibit(false)
+        });
+
+        let image = self.image.clone();
+        let window = self.window.clone();
+        let file_name = format!("{}.png", name);
+        save.connect_clicked(move |_| unsafe {
+            let dialog = FileChooserDialog::new(Some("Save as PNG"), Some(&window), FileChooserAction::Save);
+            dialog.add_buttons(&[
+                ("Save", ResponseType::Ok),
+                ("Cancel", ResponseType::Cancel)
+            ]);
+            dialog.set_do_overwrite_confirmation(true);
+            dialog.set_current_name(&file_name);
+            let filter = FileFilter::new();
+            filter.set_name(Some("PNG image"));
+            filter.add_pattern("*.png");
+            dialog.add_filter(&filter);
+
+            let response = dialog.run();
+            let filename = dialog.get_filename();
+            dialog.destroy();
+
+            if let (ResponseType::Ok, Some(path)) = (response, filename) {
+                match image.borrow().save_with_format(&path, ImageFormat::Png) {
+                    Ok(()) => UiContext::message(&format!("Image saved to '{}'", path.display())),
+                    Err(e) => UiContext::ui_error(&format!("Could not save the image to '{}': {}", path.display(), e))
+                }
+            }
+        });
+    }
+}
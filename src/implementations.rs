@@ -0,0 +1,315 @@
+//! Building the implementations supplied with the functions of a flow to wasm. flowc's
+//! `compile_supplied_implementations` builds them all in one call that cannot be stopped and
+//! whose output is only seen when it has finished, so they are built here one `cargo` build at a
+//! time, the same way and to the same wasm files as flowc does, so that progress can be reported
+//! as each is built, the output of each shown as it is written, and a build stopped. When they
+//! are not to be built flowc is used, skipping the building. By default an implementation is only
+//! built again if it has changed since its wasm was built.
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use url::Url;
+
+use flowclib::compiler::compile_wasm;
+use flowclib::generator::generate::GenerationTables;
+use flowclib::model::function::Function;
+use flowclib::model::name::HasName;
+
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+// How often a running build is checked to see if it has finished or been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When to build the implementations supplied with the functions of a flow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rebuild {
+    // Only if a source has changed since the wasm was built
+    Changed,
+    Always,
+    // Use the wasm built before, as flowc's skip_building does
+    Never,
+}
+
+pub const REBUILDS: &[Rebuild] = &[Rebuild::Changed, Rebuild::Always, Rebuild::Never];
+
+impl Rebuild {
+    /// An identifier for when to build, used in the UI and compile options file
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rebuild::Changed => "changed",
+            Rebuild::Always => "always",
+            Rebuild::Never => "never",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Rebuild::Changed => "Build if changed",
+            Rebuild::Always => "Always build",
+            Rebuild::Never => "Never build, use the wasm built before",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Rebuild> {
+        REBUILDS.iter().find(|rebuild| rebuild.id() == id).cloned()
+    }
+}
+
+/// Lets a compile running on another thread be cancelled, stopping the `cargo` build it is running
+#[derive(Clone, Default)]
+pub struct Cancel {
+    cancelled: Arc<AtomicBool>,
+    build: Arc<Mutex<Option<Child>>>,
+}
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(mut build) = self.build.lock() {
+            if let Some(mut child) = build.take() {
+                stop(&mut child);
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The functions of the compiled flow with a supplied implementation, which are built to wasm
+pub fn supplied(tables: &GenerationTables) -> Vec<String> {
+    tables.functions.iter()
+        .filter(|function| is_supplied(function))
+        .map(|function| function.name().to_string())
+        .collect()
+}
+
+// Implementations supplied with a function are the rust source of it, library functions have none
+fn is_supplied(function: &Function) -> bool {
+    function.get_implementation().ends_with(".rs")
+}
+
+/// The files the implementation supplied with a function is built from: the manifest and rust
+/// source files of its crate. There are none for a function without a supplied implementation.
+pub fn sources(function: &Function) -> Vec<PathBuf> {
+    if !is_supplied(function) {
+        return vec!();
+    }
+    match implementation_source(function) {
+        Ok(source) => crate_sources(source.parent().unwrap_or_else(|| Path::new("."))),
+        Err(_) => vec!()
+    }
+}
+
+fn crate_sources(directory: &Path) -> Vec<PathBuf> {
+    let mut sources = vec!(directory.join("Cargo.toml"));
+    let mut to_visit = vec!(directory.to_path_buf());
+    while let Some(visiting) = to_visit.pop() {
+        for entry in fs::read_dir(&visiting).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                // Skip what cargo builds
+                if path.file_name().map_or(true, |name| name != "target") {
+                    to_visit.push(path);
+                }
+            } else if path.extension().map_or(false, |extension| extension == "rs") {
+                sources.push(path);
+            }
+        }
+    }
+    sources
+}
+
+// A wasm file is up to date if it was written after all the sources it was built from were
+fn up_to_date(wasm: &Path, sources: &[PathBuf]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match modified(wasm) {
+        Some(built) => sources.iter().all(|source| modified(source).map_or(false, |modified| modified <= built)),
+        None => false
+    }
+}
+
+/// Build the supplied implementations of the functions of the compiled flow, when 'rebuild' says
+/// to, and use the wasm built for each in place of its source. 'progress' is called with the
+/// number of the function, its name and the directory it is built in before each is built, and
+/// 'output' with each line the build writes as it is written.
+pub fn build(tables: &mut GenerationTables, rebuild: Rebuild, cancel: &Cancel, progress: &dyn Fn(usize, &str, &Path),
+             output: &dyn Fn(&str)) -> Result<(), String> {
+    if rebuild == Rebuild::Never {
+        let result = compile_wasm::compile_supplied_implementations(tables, true).map_err(|e| e.to_string())?;
+        output(&result);
+        return Ok(());
+    }
+
+    let mut number = 0;
+    for function in tables.functions.iter_mut().filter(|function| is_supplied(function)) {
+        if cancel.is_cancelled() {
+            return Err("Cancelled".into());
+        }
+
+        number += 1;
+        let source = implementation_source(function)?;
+        progress(number, &function.name().to_string(), source.parent().unwrap_or_else(|| Path::new(".")));
+        let wasm = build_implementation(function, &source, rebuild, cancel, output)?;
+        function.set_implementation(&wasm.to_string_lossy());
+    }
+    Ok(())
+}
+
+fn implementation_source(function: &Function) -> Result<PathBuf, String> {
+    Url::parse(function.get_source_url())
+        .and_then(|url| url.join(function.get_implementation()))
+        .ok().and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("Could not find the implementation of function '{}'", function.name()))
+}
+
+// Build the implementation of 'function' in 'source' with cargo, returning the path of the wasm file
+// built next to it. As flowc does, the crate is built in a temporary directory and the wasm named
+// after the source is copied next to it. Paths in the output of the build are relative to the
+// directory of the source.
+fn build_implementation(function: &Function, source: &Path, rebuild: Rebuild, cancel: &Cancel,
+                        output: &dyn Fn(&str)) -> Result<PathBuf, String> {
+    let directory = source.parent().unwrap_or_else(|| Path::new("."));
+    let wasm = source.with_extension("wasm");
+    if rebuild == Rebuild::Changed && up_to_date(&wasm, &crate_sources(directory)) {
+        output(&format!("'{}' is up to date", wasm.display()));
+        return Ok(wasm);
+    }
+
+    let cargo_toml = directory.join("Cargo.toml");
+    if !cargo_toml.exists() {
+        return Err(format!("No Cargo.toml file could be found at '{}'", cargo_toml.display()));
+    }
+    let wasm_name = wasm.file_name().map(PathBuf::from)
+        .ok_or_else(|| format!("Could not find the implementation of function '{}'", function.name()))?;
+    let target_dir = std::env::temp_dir().join(format!("flowide-{}-{}", std::process::id(), function.name()));
+
+    let mut command = Command::new("cargo");
+    command.arg("build").arg("--release").arg("--color").arg("never")
+        .arg(format!("--target={}", WASM_TARGET))
+        .arg("--manifest-path").arg(&cargo_toml)
+        .arg("--target-dir").arg(&target_dir)
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // cargo is run in a process group of its own, so the compilers and build scripts it runs can
+    // be stopped with it
+    #[cfg(unix)]
+    unsafe {
+        command.pre_exec(|| match libc::setsid() {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(())
+        });
+    }
+    let built = run(command, cancel, output).map_err(|output| {
+        // The errors in the build's output are reported, each on a line of its own
+        let errors: Vec<&str> = output.lines().filter(|line| line.starts_with("error")).collect();
+        let errors = if errors.is_empty() { output.trim().to_string() } else { errors.join("\n") };
+        format!("Could not build the implementation of function '{}'\n{}", function.name(), errors)
+    }).and_then(|written| {
+        let built = target_dir.join(WASM_TARGET).join("release").join(&wasm_name);
+        fs::copy(&built, &wasm).map_err(|e| format!("Could not copy '{}' to '{}': {}. {}",
+                                                       built.display(), wasm.display(), e, written))
+    });
+    let _ = fs::remove_dir_all(&target_dir);
+    built.map(|_| wasm)
+}
+
+// Run a build, waiting for it to finish unless it is cancelled first. Each line it writes is
+// passed to 'output' as it is written, and all of them are returned.
+fn run(mut command: Command, cancel: &Cancel, output: &dyn Fn(&str)) -> Result<String, String> {
+    let mut child = command.spawn().map_err(|e| format!("Could not run cargo: {}", e))?;
+
+    // Read the output as it is written, so the build cannot block on a full pipe
+    let (sender, lines) = mpsc::channel();
+    let mut readers = vec!();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(read_lines(stdout, sender.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(read_lines(stderr, sender));
+    }
+    let mut written = String::new();
+    let mut pass_on = |line: String| {
+        output(&line);
+        written.push_str(&line);
+        written.push('\n');
+    };
+
+    // The build is kept where it can be stopped if the compile is cancelled
+    *cancel.build.lock().map_err(|_| "Could not start the build")? = Some(child);
+    let status = loop {
+        if cancel.is_cancelled() {
+            if let Ok(mut build) = cancel.build.lock() {
+                if let Some(mut child) = build.take() {
+                    stop(&mut child);
+                }
+            }
+            return Err("Cancelled".into());
+        }
+
+        while let Ok(line) = lines.try_recv() {
+            pass_on(line);
+        }
+
+        let finished = match cancel.build.lock() {
+            Ok(mut build) => match build.as_mut().map(Child::try_wait) {
+                Some(Ok(Some(status))) => Some(Ok(status)),
+                Some(Ok(None)) => None,
+                Some(Err(e)) => Some(Err(e.to_string())),
+                None => Some(Err("The build was stopped".to_string()))
+            },
+            Err(_) => Some(Err("Could not check the build".to_string()))
+        };
+        if let Some(status) = finished {
+            if let Ok(mut build) = cancel.build.lock() {
+                build.take();
+            }
+            break status?;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    for reader in readers {
+        let _ = reader.join();
+    }
+    for line in lines.try_iter() {
+        pass_on(line);
+    }
+
+    if status.success() {
+        Ok(written)
+    } else {
+        Err(written)
+    }
+}
+
+// Stop a build, and all the processes in its process group, and wait for it to finish
+fn stop(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn read_lines<R: Read + Send + 'static>(readable: R, sender: Sender<String>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(readable).lines().flatten() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    })
+}
@@ -0,0 +1,452 @@
+//! Layered ("Sugiyama" style) layout of a `FlowGraph`. Processes are placed in columns from left
+//! to right following the direction data flows through the connections between them, the order
+//! of processes within each column is chosen to reduce the number of connections that cross,
+//! and connections that loop back to an earlier column are routed around the bottom of the graph.
+use std::collections::HashSet;
+
+use crate::flow_graph::{CHAR_WIDTH, Endpoint, FlowGraph, GRAPH_MARGIN, PORT_SPACING, Rect};
+
+// Horizontal space between columns of processes, where connections are drawn
+const LAYER_GAP: f64 = 90.0;
+// Vertical space between processes in the same column
+const NODE_GAP: f64 = 30.0;
+// Height reserved for a connection passing through a column it does not connect to
+const DUMMY_HEIGHT: f64 = 12.0;
+// Spacing of the lanes used by connections that loop back around the bottom of the graph
+const LOOP_LANE_GAP: f64 = 14.0;
+// Horizontal distance a loop back connection travels out of its port before turning
+const LOOP_STEP: f64 = 20.0;
+// Number of down and up sweeps made to reduce crossings
+const ORDERING_SWEEPS: usize = 8;
+
+// An item placed in a layer: a process node or a point a long connection passes through
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Node(usize),
+    Dummy,
+}
+
+struct LayeredGraph {
+    items: Vec<Item>,
+    heights: Vec<f64>,
+    widths: Vec<f64>,
+    layers: Vec<Vec<usize>>,
+    // links between items in consecutive layers (upper layer item, lower layer item)
+    segments: Vec<(usize, usize)>,
+    // for each edge of the graph, the dummy items it passes through in order, if it is routed
+    // forward between process nodes
+    chains: Vec<Option<Vec<usize>>>,
+}
+
+/// Lay out the graph: position all its nodes, route all its edges and set its bounds.
+/// Nodes must already have been sized.
+pub fn layered(graph: &mut FlowGraph) {
+    let node_count = graph.nodes.len();
+
+    // Connections between two processes, as (from node, to node, edge index)
+    let links: Vec<(usize, usize, usize)> = graph.edges.iter().enumerate()
+        .filter_map(|(index, edge)| match (&edge.from, &edge.to) {
+            (Endpoint::NodeOutput(from, _), Endpoint::NodeInput(to, _)) => Some((*from, *to, index)),
+            _ => None
+        })
+        .collect();
+
+    let loop_backs = find_loop_backs(node_count, &links);
+    for (index, edge) in graph.edges.iter_mut().enumerate() {
+        edge.loop_back = loop_backs.contains(&index);
+    }
+
+    // The remaining forward links form an acyclic graph that can be layered
+    let forward: Vec<(usize, usize, usize)> = links.iter()
+        .filter(|(_, _, index)| !loop_backs.contains(index))
+        .cloned()
+        .collect();
+    let node_layers = assign_layers(node_count, &forward);
+
+    let mut layered = LayeredGraph::new(graph, &node_layers, &forward);
+    layered.reduce_crossings();
+    let positions = layered.position(graph);
+
+    for (item, rect) in layered.items.iter().zip(positions.iter()) {
+        if let Item::Node(node) = item {
+            graph.nodes[*node].bounds = *rect;
+        }
+    }
+
+    let nodes_bottom = graph.nodes.iter().map(|n| n.bounds.bottom()).fold(0.0, f64::max);
+    let nodes_right = graph.nodes.iter().map(|n| n.bounds.right()).fold(0.0, f64::max);
+    let output_labels = label_width(graph.outputs.iter().map(|p| p.name.len()));
+    let ports_height = 2.0 * GRAPH_MARGIN + graph.inputs.len().max(graph.outputs.len()) as f64 * PORT_SPACING;
+    let loop_lanes = loop_backs.len() as f64 * LOOP_LANE_GAP;
+
+    graph.bounds = Rect::new(0.0, 0.0,
+                             (nodes_right + LAYER_GAP + output_labels).max(2.0 * GRAPH_MARGIN),
+                             (nodes_bottom + loop_lanes + GRAPH_MARGIN).max(ports_height));
+
+    route_edges(graph, &layered, &positions, nodes_bottom);
+}
+
+// Width needed for the longest of a set of labels
+fn label_width(lengths: impl Iterator<Item=usize>) -> f64 {
+    lengths.max().unwrap_or(0) as f64 * CHAR_WIDTH
+}
+
+// Use a depth first search to find the links that close a cycle. These will be drawn as loops
+// back to an earlier layer, and ignored when assigning layers. Processes are visited in the order
+// they are defined in, so the first process of a loop in the definition is drawn first.
+fn find_loop_backs(node_count: usize, links: &[(usize, usize, usize)]) -> HashSet<usize> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark { New, Active, Done }
+
+    let mut marks = vec!(Mark::New; node_count);
+    let mut loop_backs = HashSet::new();
+
+    for root in 0..node_count {
+        if marks[root] != Mark::New {
+            continue;
+        }
+
+        // stack of (node, index of the next outgoing link to look at)
+        let mut stack = vec!((root, 0));
+        marks[root] = Mark::Active;
+        while let Some((node, next)) = stack.pop() {
+            let outgoing: Vec<&(usize, usize, usize)> = links.iter().filter(|(from, _, _)| *from == node).collect();
+            match outgoing.get(next) {
+                Some((_, to, index)) => {
+                    stack.push((node, next + 1));
+                    match marks[*to] {
+                        Mark::Active => { loop_backs.insert(*index); }
+                        Mark::New => {
+                            marks[*to] = Mark::Active;
+                            stack.push((*to, 0));
+                        }
+                        Mark::Done => {}
+                    }
+                }
+                None => marks[node] = Mark::Done
+            }
+        }
+    }
+
+    loop_backs
+}
+
+// Assign each node to the layer one after the furthest of the nodes that send to it
+fn assign_layers(node_count: usize, links: &[(usize, usize, usize)]) -> Vec<usize> {
+    let mut layers = vec!(0; node_count);
+
+    // Iterating once per node is enough for the longest path in an acyclic graph to settle
+    for _ in 0..node_count {
+        let mut changed = false;
+        for (from, to, _) in links {
+            if layers[*to] < layers[*from] + 1 {
+                layers[*to] = layers[*from] + 1;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    layers
+}
+
+impl LayeredGraph {
+    fn new(graph: &FlowGraph, node_layers: &[usize], forward: &[(usize, usize, usize)]) -> Self {
+        let layer_count = node_layers.iter().max().map(|max| max + 1).unwrap_or(0);
+        let mut layered = LayeredGraph {
+            items: vec!(),
+            heights: vec!(),
+            widths: vec!(),
+            layers: vec!(vec!(); layer_count),
+            segments: vec!(),
+            chains: vec!(None; graph.edges.len()),
+        };
+
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let item = layered.add_item(Item::Node(index), node.bounds.width, node.bounds.height);
+            layered.layers[node_layers[index]].push(item);
+        }
+
+        // Long links get a dummy item in each layer they pass through, so that they take
+        // part in crossing reduction and get space reserved for them
+        for (from, to, edge) in forward {
+            let mut chain = vec!();
+            let mut upper = *from;
+            for layer in node_layers[*from] + 1..node_layers[*to] {
+                let dummy = layered.add_item(Item::Dummy, 0.0, DUMMY_HEIGHT);
+                layered.layers[layer].push(dummy);
+                layered.segments.push((upper, dummy));
+                chain.push(dummy);
+                upper = dummy;
+            }
+            layered.segments.push((upper, *to));
+            layered.chains[*edge] = Some(chain);
+        }
+
+        layered
+    }
+
+    fn add_item(&mut self, item: Item, width: f64, height: f64) -> usize {
+        self.items.push(item);
+        self.widths.push(width);
+        self.heights.push(height);
+        self.items.len() - 1
+    }
+
+    // Reorder the items in each layer using the barycenter heuristic, keeping the ordering
+    // with the fewest crossings found
+    fn reduce_crossings(&mut self) {
+        let mut best = self.layers.clone();
+        let mut best_crossings = self.crossings();
+
+        for _ in 0..ORDERING_SWEEPS {
+            for layer in 1..self.layers.len() {
+                self.order_by_neighbours(layer, layer - 1, true);
+            }
+            for layer in (0..self.layers.len().saturating_sub(1)).rev() {
+                self.order_by_neighbours(layer, layer + 1, false);
+            }
+
+            let crossings = self.crossings();
+            if crossings < best_crossings {
+                best_crossings = crossings;
+                best = self.layers.clone();
+            }
+            if best_crossings == 0 {
+                break;
+            }
+        }
+
+        self.layers = best;
+    }
+
+    // Sort a layer by the average position of each item's neighbours in an adjacent layer.
+    // Items with no neighbours there keep their current position.
+    fn order_by_neighbours(&mut self, layer: usize, adjacent: usize, adjacent_is_upper: bool) {
+        let positions = self.positions_in_layers();
+        let mut keyed: Vec<(f64, usize)> = self.layers[layer].iter().enumerate()
+            .map(|(current, item)| {
+                let neighbours: Vec<usize> = self.segments.iter()
+                    .filter_map(|(upper, lower)| if adjacent_is_upper && lower == item {
+                        Some(*upper)
+                    } else if !adjacent_is_upper && upper == item {
+                        Some(*lower)
+                    } else {
+                        None
+                    })
+                    .filter(|neighbour| self.layers[adjacent].contains(neighbour))
+                    .collect();
+                if neighbours.is_empty() {
+                    (current as f64, *item)
+                } else {
+                    let sum: f64 = neighbours.iter().map(|n| positions[*n] as f64).sum();
+                    (sum / neighbours.len() as f64, *item)
+                }
+            })
+            .collect();
+
+        keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        self.layers[layer] = keyed.into_iter().map(|(_, item)| item).collect();
+    }
+
+    // The index of each item within its layer
+    fn positions_in_layers(&self) -> Vec<usize> {
+        let mut positions = vec!(0; self.items.len());
+        for layer in &self.layers {
+            for (position, item) in layer.iter().enumerate() {
+                positions[*item] = position;
+            }
+        }
+        positions
+    }
+
+    // Count the pairs of segments that cross between consecutive layers
+    fn crossings(&self) -> usize {
+        let positions = self.positions_in_layers();
+        let mut count = 0;
+        for (index, (upper_a, lower_a)) in self.segments.iter().enumerate() {
+            for (upper_b, lower_b) in &self.segments[index + 1..] {
+                let same_layers = self.layers.iter().any(|layer| layer.contains(upper_a) && layer.contains(upper_b));
+                if !same_layers {
+                    continue;
+                }
+                let upper_order = positions[*upper_a] as i64 - positions[*upper_b] as i64;
+                let lower_order = positions[*lower_a] as i64 - positions[*lower_b] as i64;
+                if upper_order * lower_order < 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    // Calculate the rectangle each item occupies on the graph
+    fn position(&self, graph: &FlowGraph) -> Vec<Rect> {
+        let mut rects = vec!(Rect::default(); self.items.len());
+
+        let input_labels = label_width(graph.inputs.iter().map(|p| p.name.len()));
+        let mut x = GRAPH_MARGIN + input_labels + LAYER_GAP / 2.0;
+
+        for layer in &self.layers {
+            let layer_width = layer.iter().map(|item| self.widths[*item]).fold(0.0, f64::max);
+
+            // Try to place each item level with the items that connect into it, without
+            // overlapping the item above it in the layer
+            let mut y = GRAPH_MARGIN;
+            for item in layer {
+                let upper_centers: Vec<f64> = self.segments.iter()
+                    .filter(|(_, lower)| lower == item)
+                    .map(|(upper, _)| rects[*upper].y + rects[*upper].height / 2.0)
+                    .collect();
+                let wanted = if upper_centers.is_empty() {
+                    y
+                } else {
+                    upper_centers.iter().sum::<f64>() / upper_centers.len() as f64 - self.heights[*item] / 2.0
+                };
+                let top = wanted.max(y);
+                rects[*item] = Rect::new(x + (layer_width - self.widths[*item]) / 2.0, top,
+                                         self.widths[*item], self.heights[*item]);
+                y = top + self.heights[*item] + NODE_GAP;
+            }
+
+            x += layer_width + LAYER_GAP;
+        }
+
+        rects
+    }
+}
+
+// Set the points each edge is drawn through
+fn route_edges(graph: &mut FlowGraph, layered: &LayeredGraph, positions: &[Rect], nodes_bottom: f64) {
+    let mut lane = 0;
+    for index in 0..graph.edges.len() {
+        let start = graph.anchor(&graph.edges[index].from);
+        let end = graph.anchor(&graph.edges[index].to);
+
+        let points = if graph.edges[index].loop_back {
+            // leave the output to the right, run along a lane below the graph and come back
+            // up into the input from the left
+            lane += 1;
+            let lane_y = nodes_bottom + lane as f64 * LOOP_LANE_GAP;
+            vec!(start,
+                 (start.0 + LOOP_STEP, start.1),
+                 (start.0 + LOOP_STEP, lane_y),
+                 (end.0 - LOOP_STEP, lane_y),
+                 (end.0 - LOOP_STEP, end.1),
+                 end)
+        } else {
+            let mut points = vec!(start);
+            if let Some(ref chain) = layered.chains[index] {
+                for dummy in chain {
+                    let rect = &positions[*dummy];
+                    points.push((rect.x, rect.y + rect.height / 2.0));
+                }
+            }
+            points.push(end);
+            points
+        };
+
+        graph.edges[index].points = points;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::flow_graph::{Edge, Endpoint, FlowGraph, Node, NodeKind, Port, Rect};
+
+    use super::{assign_layers, find_loop_backs, layered};
+
+    fn port() -> Port {
+        Port { name: String::new(), datatype: "Value".into() }
+    }
+
+    fn node(alias: &str) -> Node {
+        Node {
+            alias: alias.into(),
+            name: alias.into(),
+            source: format!("{}.toml", alias),
+            kind: NodeKind::Function,
+            inputs: vec!(port()),
+            outputs: vec!(port()),
+            sub_graph: None,
+            bounds: Rect::new(0.0, 0.0, 140.0, 60.0),
+        }
+    }
+
+    // A graph of processes with 'aliases', connected by 'links' between their indexes
+    fn graph(aliases: &[&str], links: &[(usize, usize)]) -> FlowGraph {
+        FlowGraph {
+            name: "test".into(),
+            source_url: "file:///test.toml".into(),
+            inputs: vec!(),
+            outputs: vec!(),
+            nodes: aliases.iter().map(|alias| node(alias)).collect(),
+            edges: links.iter()
+                .map(|&(from, to)| Edge {
+                    from: Endpoint::NodeOutput(from, String::new()),
+                    to: Endpoint::NodeInput(to, String::new()),
+                    from_route: aliases[from].into(),
+                    to_route: aliases[to].into(),
+                    name: None,
+                    points: vec!(),
+                    loop_back: false,
+                })
+                .collect(),
+            bounds: Rect::default(),
+        }
+    }
+
+    #[test]
+    fn link_closing_a_loop_loops_back() {
+        let links = [(0, 1, 0), (1, 2, 1), (2, 0, 2), (2, 3, 3)];
+        let loop_backs = find_loop_backs(4, &links);
+        assert_eq!(loop_backs.into_iter().collect::<Vec<usize>>(), vec!(2));
+    }
+
+    #[test]
+    fn process_connected_to_itself_loops_back() {
+        assert!(find_loop_backs(1, &[(0, 0, 0)]).contains(&0));
+    }
+
+    #[test]
+    fn layers_follow_the_longest_path() {
+        // 0 -> 1 -> 2 and 0 -> 2 directly
+        assert_eq!(assign_layers(4, &[(0, 1, 0), (1, 2, 1), (0, 2, 2)]), vec!(0, 1, 2, 0));
+    }
+
+    #[test]
+    fn nodes_laid_out_left_to_right_without_crossings() {
+        // 'a' and 'b' send to 'd' and 'c', which would cross if kept in the order defined
+        let mut graph = graph(&["a", "b", "c", "d"], &[(0, 3), (1, 2)]);
+        layered(&mut graph);
+
+        let bounds: Vec<Rect> = graph.nodes.iter().map(|node| node.bounds).collect();
+        assert!(bounds[0].right() < bounds[3].x && bounds[1].right() < bounds[2].x);
+        assert_eq!(bounds[0].y < bounds[1].y, bounds[3].y < bounds[2].y);
+        assert!(graph.edges.iter().all(|edge| !edge.loop_back && edge.points.len() == 2));
+    }
+
+    #[test]
+    fn loop_routed_below_the_nodes() {
+        let mut graph = graph(&["a", "b"], &[(0, 1), (1, 0)]);
+        layered(&mut graph);
+
+        assert!(!graph.edges[0].loop_back && graph.edges[1].loop_back);
+        let nodes_bottom = graph.nodes.iter().map(|node| node.bounds.bottom()).fold(0.0, f64::max);
+        let lowest = graph.edges[1].points.iter().map(|point| point.1).fold(0.0, f64::max);
+        assert!(lowest > nodes_bottom && lowest <= graph.bounds.bottom());
+    }
+
+    #[test]
+    fn long_link_passes_through_the_layers_between() {
+        let mut graph = graph(&["a", "b", "c"], &[(0, 1), (1, 2), (0, 2)]);
+        layered(&mut graph);
+
+        // The link from 'a' to 'c' bends at a point in the layer of 'b'
+        let points = &graph.edges[2].points;
+        assert_eq!(points.len(), 3);
+        assert!(points[1].0 > graph.nodes[0].bounds.right() && points[1].0 < graph.nodes[2].bounds.x);
+    }
+}
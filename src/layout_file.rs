@@ -0,0 +1,179 @@
+//! Positions of the nodes the user has moved in the graph of a flow are saved in a "layout" file
+//! next to the flow's definition, e.g. `myflow.layout.toml` for `myflow.toml`, so they can be
+//! restored when the flow is opened again. The positions in the flow itself are in the `flow`
+//! table by process alias, and those in its sub-flows in a `sub_flows` table by the path of
+//! aliases leading to the sub-flow:
+//!
+//! ```toml
+//! [flow]
+//! add = [120.0, 40.0]
+//!
+//! [sub_flows."sequence/compare"]
+//! tap = [60.0, 80.0]
+//! ```
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use url::Url;
+
+const LAYOUT_EXTENSION: &str = "layout.toml";
+const FLOW_TABLE: &str = "flow";
+const SUB_FLOWS_TABLE: &str = "sub_flows";
+
+/// Node positions by alias, for the root flow and each of its sub-flows by location: the url of
+/// the root flow followed by the aliases of the sub-flows leading to it, separated by '/'
+pub type Positions = HashMap<String, HashMap<String, (f64, f64)>>;
+
+// The path of the layout file for the flow defined at 'flow_url', if it is a file
+fn layout_path(flow_url: &str) -> Option<PathBuf> {
+    let path = Url::parse(flow_url).ok()?.to_file_path().ok()?;
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    Some(path.with_file_name(format!("{}.{}", stem, LAYOUT_EXTENSION)))
+}
+
+/// Is the file called 'name' a layout file rather than a flow definition
+pub fn is_layout_file(name: &str) -> bool {
+    name.ends_with(&format!(".{}", LAYOUT_EXTENSION))
+}
+
+/// Read the positions saved for the flow defined at 'flow_url'. There are none if it has no layout
+/// file, and entries that are not valid positions are ignored.
+pub fn read_positions(flow_url: &str) -> Positions {
+    let mut positions = Positions::new();
+
+    let text = match layout_path(flow_url).and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => text,
+        None => return positions
+    };
+    let layout: toml::Value = match toml::from_str(&text) {
+        Ok(layout) => layout,
+        Err(e) => {
+            crate::log_warn(&format!("Could not read the layout of flow '{}': {}", flow_url, e));
+            return positions;
+        }
+    };
+
+    if let Some(table) = layout.get(FLOW_TABLE) {
+        positions.insert(flow_url.to_string(), table_positions(table));
+    }
+    if let Some(sub_flows) = layout.get(SUB_FLOWS_TABLE).and_then(toml::Value::as_table) {
+        for (path, table) in sub_flows {
+            positions.insert(format!("{}/{}", flow_url, path), table_positions(table));
+        }
+    }
+
+    positions
+}
+
+fn table_positions(table: &toml::Value) -> HashMap<String, (f64, f64)> {
+    table.as_table().into_iter().flatten()
+        .filter_map(|(alias, position)| {
+            let position = position.as_array()?;
+            match (position.get(0).and_then(number), position.get(1).and_then(number)) {
+                (Some(x), Some(y)) if position.len() == 2 => Some((alias.clone(), (x, y))),
+                _ => None
+            }
+        })
+        .collect()
+}
+
+// Positions written by hand may be integers
+fn number(value: &toml::Value) -> Option<f64> {
+    value.as_float().or_else(|| value.as_integer().map(|integer| integer as f64))
+}
+
+/// Move the position of the process 'from' in the (sub)flow at 'location' to the process 'to', and
+/// the positions of the nodes inside it if it is a sub-flow, as when the process is renamed
+pub fn move_positions(positions: &mut Positions, location: &str, from: &str, to: &str) {
+    if let Some(position) = positions.get_mut(location).and_then(|flow_positions| flow_positions.remove(from)) {
+        positions.entry(location.to_string()).or_insert_with(HashMap::new).insert(to.to_string(), position);
+    }
+
+    let old_location = format!("{}/{}", location, from);
+    let new_location = format!("{}/{}", location, to);
+    let sub_locations: Vec<String> = positions.keys()
+        .filter(|sub_location| **sub_location == old_location || sub_location.starts_with(&format!("{}/", old_location)))
+        .cloned()
+        .collect();
+    for sub_location in sub_locations {
+        if let Some(sub_positions) = positions.remove(&sub_location) {
+            positions.insert(format!("{}{}", new_location, &sub_location[old_location.len()..]), sub_positions);
+        }
+    }
+}
+
+/// Write the positions of the flow defined at 'flow_url', and its sub-flows, to its layout file
+pub fn write_positions(flow_url: &str, positions: &Positions) -> Result<(), String> {
+    let path = layout_path(flow_url)
+        .ok_or_else(|| format!("Cannot save the layout of flow '{}' as it is not a file", flow_url))?;
+
+    let mut flow = toml::value::Table::new();
+    let mut sub_flows = toml::value::Table::new();
+    for (location, flow_positions) in positions {
+        let table = position_table(flow_positions);
+        if location == flow_url {
+            flow = table;
+        } else if let Some(sub_flow_path) = location.strip_prefix(&format!("{}/", flow_url)) {
+            sub_flows.insert(sub_flow_path.to_string(), toml::Value::Table(table));
+        }
+    }
+
+    let mut layout = toml::value::Table::new();
+    layout.insert(FLOW_TABLE.into(), toml::Value::Table(flow));
+    if !sub_flows.is_empty() {
+        layout.insert(SUB_FLOWS_TABLE.into(), toml::Value::Table(sub_flows));
+    }
+
+    let text = toml::to_string_pretty(&toml::Value::Table(layout)).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| format!("Could not save the layout to '{}': {}", path.display(), e))
+}
+
+fn position_table(positions: &HashMap<String, (f64, f64)>) -> toml::value::Table {
+    positions.iter()
+        .map(|(alias, (x, y))| (alias.clone(), toml::Value::Array(vec!(toml::Value::Float(x.round()),
+                                                                       toml::Value::Float(y.round())))))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{move_positions, Positions};
+
+    const ROOT: &str = "file:///flows/root.toml";
+
+    fn positions(entries: &[(&str, &str, (f64, f64))]) -> Positions {
+        let mut positions = Positions::new();
+        for (location, alias, position) in entries {
+            positions.entry(location.to_string()).or_insert_with(HashMap::new).insert(alias.to_string(), *position);
+        }
+        positions
+    }
+
+    #[test]
+    fn process_and_the_nodes_inside_it_moved() {
+        let mut moved = positions(&[(ROOT, "seq", (1.0, 2.0)), (ROOT, "add", (3.0, 4.0)),
+            ("file:///flows/root.toml/seq", "tap", (5.0, 6.0)),
+            ("file:///flows/root.toml/seq/compare", "gt", (7.0, 8.0)),
+            ("file:///flows/root.toml/sequence", "tap", (9.0, 10.0))]);
+        move_positions(&mut moved, ROOT, "seq", "range");
+
+        assert_eq!(moved, positions(&[(ROOT, "range", (1.0, 2.0)), (ROOT, "add", (3.0, 4.0)),
+            ("file:///flows/root.toml/range", "tap", (5.0, 6.0)),
+            ("file:///flows/root.toml/range/compare", "gt", (7.0, 8.0)),
+            ("file:///flows/root.toml/sequence", "tap", (9.0, 10.0))]));
+
+        move_positions(&mut moved, ROOT, "range", "seq");
+        assert_eq!(moved["file:///flows/root.toml/seq/compare"]["gt"], (7.0, 8.0));
+        assert_eq!(moved[ROOT]["seq"], (1.0, 2.0));
+    }
+
+    #[test]
+    fn process_without_a_position_not_moved() {
+        let mut moved = positions(&[(ROOT, "add", (3.0, 4.0))]);
+        move_positions(&mut moved, ROOT, "seq", "range");
+        assert_eq!(moved, positions(&[(ROOT, "add", (3.0, 4.0))]));
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde_json::Value;
+
+use flowclib::model::datatype::HasDataType;
+use flowclib::model::flow::Flow;
+use flowclib::model::io::IO;
+use flowclib::model::name::HasName;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
+use flowclib::model::process_reference::ProcessReference;
+
+// The type of a port that accepts any value
+const GENERIC_TYPE: &str = "Value";
+// Prefix of the type of a port that takes an array of values of the type that follows
+const ARRAY_PREFIX: &str = "Array/";
+
+/// How serious a problem found in a flow is: errors stop the flow working, warnings may be intended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "Error"),
+            Severity::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+/// A problem found in a loaded flow, in the (sub)flow reached from the root through the sub-flows
+/// with the aliases in 'location', and the process in it with 'alias' if it is about one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Vec<String>,
+    pub alias: Option<String>,
+}
+
+/// Check the flow, and all its sub-flows, for problems that would stop it working as expected
+/// when run, which otherwise are only found by running it and seeing it hang
+pub fn check(flow: &Flow) -> Vec<Lint> {
+    let mut lints = vec!();
+    check_flow(flow, &[], &mut lints);
+    lints
+}
+
+// The ports of a process of a flow
+struct Ports<'a> {
+    alias: String,
+    inputs: Vec<&'a IO>,
+    outputs: Vec<&'a IO>,
+    // Names of the inputs given an initial value, by the flow or by their own definition
+    initialized: Vec<String>,
+}
+
+fn check_flow(flow: &Flow, location: &[String], lints: &mut Vec<Lint>) {
+    let mut lint = |severity, message, alias: Option<&str>| lints.push(Lint {
+        severity,
+        message,
+        location: location.to_vec(),
+        alias: alias.map(|alias| alias.to_string()),
+    });
+
+    let connections: Vec<(String, String)> = flow.connections.iter().flatten()
+        .map(|connection| (trim(&connection.from.to_string()), trim(&connection.to.to_string())))
+        .collect();
+    let processes = processes(flow);
+
+    for process in &processes {
+        for input in &process.inputs {
+            let name = input.name().to_string();
+            let connected = connections.iter().any(|(_, to)| refers_to(to, &process.alias, &name));
+            if !connected && !process.initialized.contains(&name) {
+                lint(Severity::Error, format!("Input {} of '{}' is not connected and has no initial value, so it will never run",
+                                              port_label(&name), process.alias), Some(&process.alias));
+            }
+        }
+
+        for output in &process.outputs {
+            let name = output.name().to_string();
+            if !connections.iter().any(|(from, _)| refers_to(from, &process.alias, &name)) {
+                lint(Severity::Warning, format!("Output {} of '{}' is not connected to anything",
+                                                port_label(&name), process.alias), Some(&process.alias));
+            }
+        }
+    }
+
+    for (from, to) in &connections {
+        if let (Some(from_type), Some(to_type)) = (route_type(flow, &processes, from, true),
+                                                   route_type(flow, &processes, to, false)) {
+            if !compatible(&from_type, &to_type) {
+                let alias = processes.iter().find(|process| refers_to(to, &process.alias, ""))
+                    .or_else(|| processes.iter().find(|process| refers_to(from, &process.alias, "")))
+                    .map(|process| process.alias.as_str());
+                lint(Severity::Error, format!("'{}' of type {} is connected to '{}' of type {}",
+                                              from, from_type, to, to_type), alias);
+            }
+        }
+    }
+
+    for cycle in uninitialized_cycles(&processes, &connections) {
+        let aliases: Vec<String> = cycle.iter().map(|alias| format!("'{}'", alias)).collect();
+        lint(Severity::Error, format!("{} are connected in a loop with no initial value, so they will wait for each other forever",
+                                      aliases.join(", ")), cycle.iter().next().map(|alias| alias.as_str()));
+    }
+
+    for process_ref in flow.process_refs.iter().flatten() {
+        let alias = process_ref.alias.to_string();
+        if let Some(FlowProcess(sub_flow)) = flow.subprocesses.get(&process_ref.alias) {
+            for input in sub_flow.inputs.iter().flatten() {
+                let name = input.name().to_string();
+                let used = sub_flow.connections.iter().flatten()
+                    .any(|connection| refers_to(&trim(&connection.from.to_string()), "input", &name));
+                if !used {
+                    lint(Severity::Warning, format!("Input {} of sub-flow '{}' is not used by any process in it",
+                                                    port_label(&name), alias), Some(&alias));
+                }
+            }
+        }
+    }
+
+    for process_ref in flow.process_refs.iter().flatten() {
+        if let Some(FlowProcess(sub_flow)) = flow.subprocesses.get(&process_ref.alias) {
+            let mut sub_location = location.to_vec();
+            sub_location.push(process_ref.alias.to_string());
+            check_flow(sub_flow, &sub_location, lints);
+        }
+    }
+}
+
+fn processes(flow: &Flow) -> Vec<Ports> {
+    flow.process_refs.iter().flatten()
+        .filter_map(|process_ref| {
+            let (inputs, outputs): (Vec<&IO>, Vec<&IO>) = match flow.subprocesses.get(&process_ref.alias)? {
+                FlowProcess(sub_flow) => (sub_flow.inputs.iter().flatten().collect(),
+                                          sub_flow.outputs.iter().flatten().collect()),
+                FunctionProcess(function) => (function.get_inputs().iter().flatten().collect(),
+                                              function.get_outputs().iter().flatten().collect()),
+            };
+            let mut initialized = initialized_inputs(process_ref);
+            initialized.extend(inputs.iter()
+                .filter(|input| has_initializer(input))
+                .map(|input| input.name().to_string()));
+            Some(Ports {
+                alias: process_ref.alias.to_string(),
+                inputs,
+                outputs,
+                initialized,
+            })
+        })
+        .collect()
+}
+
+// The names of the inputs a reference to a process gives initial values, as written in its definition
+fn initialized_inputs(process_ref: &ProcessReference) -> Vec<String> {
+    match serde_json::to_value(process_ref).ok().and_then(|value| value.get("input").cloned()) {
+        Some(Value::Object(table)) => table.keys().cloned().collect(),
+        _ => vec!()
+    }
+}
+
+// Does the definition of the input give it an initial value
+fn has_initializer(io: &IO) -> bool {
+    serde_json::to_value(io).ok()
+        .and_then(|io| io.get("initializer").cloned())
+        .map_or(false, |initializer| !initializer.is_null())
+}
+
+fn trim(route: &str) -> String {
+    route.trim_start_matches('/').to_string()
+}
+
+fn first_segment(route: &str) -> &str {
+    route.split('/').next().unwrap_or("")
+}
+
+// Does a route refer to the port 'name' of 'owner' (a process alias, "input" or "output"), or a
+// part of it. The default port has no name and is referred to by the owner alone.
+fn refers_to(route: &str, owner: &str, name: &str) -> bool {
+    let port = if name.is_empty() { owner.to_string() } else { format!("{}/{}", owner, name) };
+    route == port || route.starts_with(&format!("{}/", port))
+}
+
+fn port_label(name: &str) -> String {
+    if name.is_empty() { "(default)".into() } else { format!("'{}'", name) }
+}
+
+// The type of the port a route refers to, if it refers to the whole of a port whose type is known
+fn route_type(flow: &Flow, processes: &[Ports], route: &str, source: bool) -> Option<String> {
+    let owner = first_segment(route);
+    let ios: Vec<&IO> = match (owner, source) {
+        ("input", true) => flow.inputs.iter().flatten().collect(),
+        ("output", false) => flow.outputs.iter().flatten().collect(),
+        (alias, _) => {
+            let process = processes.iter().find(|process| process.alias == alias)?;
+            if source { process.outputs.clone() } else { process.inputs.clone() }
+        }
+    };
+
+    ios.into_iter()
+        .find(|io| {
+            let name = io.name().to_string();
+            route == owner && name.is_empty() || route == format!("{}/{}", owner, name)
+        })
+        .map(|io| io.datatype(0).to_string())
+}
+
+// Values of one type can be sent to a port of another if they are the same, either takes any
+// value, or one is an array of the other, as arrays are split up or values gathered into them
+fn compatible(from: &str, to: &str) -> bool {
+    from == to || from == GENERIC_TYPE || to == GENERIC_TYPE
+        || from == format!("{}{}", ARRAY_PREFIX, to) || to == format!("{}{}", ARRAY_PREFIX, from)
+}
+
+// The loops of processes of a flow in which no input connected from inside the loop is given an
+// initial value, so none of them can run first
+fn uninitialized_cycles(processes: &[Ports], connections: &[(String, String)]) -> Vec<BTreeSet<String>> {
+    cycles(processes, connections).into_iter()
+        .filter(|cycle| !connections.iter().any(|(from, to)| {
+            let (from_alias, to_alias) = (first_segment(from), first_segment(to));
+            cycle.contains(from_alias) && cycle.contains(to_alias) && processes.iter()
+                .filter(|process| process.alias == to_alias)
+                .any(|process| process.initialized.iter().any(|name| refers_to(to, to_alias, name)))
+        }))
+        .collect()
+}
+
+// The groups of processes of a flow connected in a loop, as sets of aliases. A process connected
+// to itself is a loop on its own.
+fn cycles(processes: &[Ports], connections: &[(String, String)]) -> Vec<BTreeSet<String>> {
+    let mut next: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (from, to) in connections {
+        let (from_alias, to_alias) = (first_segment(from), first_segment(to));
+        let known = |alias: &str| processes.iter().any(|process| process.alias == alias);
+        if known(from_alias) && known(to_alias) {
+            next.entry(from_alias).or_default().insert(to_alias);
+        }
+    }
+
+    // The processes reachable from each process, so two processes are in a loop if each
+    // reaches the other
+    let reachable: BTreeMap<&str, BTreeSet<&str>> = processes.iter()
+        .map(|process| {
+            let mut reached = BTreeSet::new();
+            let mut to_visit: Vec<&str> = next.get(process.alias.as_str()).into_iter().flatten().cloned().collect();
+            while let Some(alias) = to_visit.pop() {
+                if reached.insert(alias) {
+                    to_visit.extend(next.get(alias).into_iter().flatten().cloned());
+                }
+            }
+            (process.alias.as_str(), reached)
+        })
+        .collect();
+
+    let mut cycles: Vec<BTreeSet<String>> = vec!();
+    for process in processes {
+        let alias = process.alias.as_str();
+        if !reachable[alias].contains(alias) || cycles.iter().any(|cycle| cycle.contains(alias)) {
+            continue;
+        }
+        cycles.push(reachable[alias].iter()
+            .filter(|other| reachable[**other].contains(alias))
+            .map(|other| other.to_string())
+            .collect());
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use flowclib::deserializers::deserializer_helper;
+    use flowclib::model::flow::Flow;
+    use flowclib::model::process::Process::{self, FlowProcess, FunctionProcess};
+
+    use super::{check, compatible, cycles, refers_to, uninitialized_cycles, Ports, Severity};
+
+    const PASS: &str = "flow = \"pass\"
+
+[[input]]
+name = \"start\"
+type = \"Number\"
+
+[[output]]
+name = \"number\"
+type = \"Number\"
+
+[[connection]]
+from = \"input/start\"
+to = \"output/number\"
+";
+
+    const LOOP: &str = "flow = \"loop\"
+
+[[process]]
+alias = \"a\"
+source = \"pass.toml\"
+
+[[process]]
+alias = \"b\"
+source = \"pass.toml\"
+
+[[connection]]
+from = \"a/number\"
+to = \"b/start\"
+
+[[connection]]
+from = \"b/number\"
+to = \"a/start\"
+";
+
+    // The flow defined by 'text' as it is when loaded from 'url'
+    fn flow(url: &str, text: &str) -> Flow {
+        match deserializer_helper::get_deserializer::<Process>(url).unwrap().deserialize(text, Some(url)).unwrap() {
+            FlowProcess(mut flow) => {
+                flow.source_url = url.into();
+                flow
+            }
+            FunctionProcess(_) => panic!("'{}' is not a flow", url)
+        }
+    }
+
+    // The flow defined by 'text' with the 'pass' sub-flow loaded for each of its processes
+    fn loaded(text: &str) -> Flow {
+        let mut loaded = flow("file:///flows/root.toml", text);
+        let aliases: Vec<_> = loaded.process_refs.iter().flatten().map(|process_ref| process_ref.alias.clone()).collect();
+        for alias in aliases {
+            loaded.subprocesses.insert(alias, FlowProcess(flow("file:///flows/pass.toml", PASS)));
+        }
+        loaded
+    }
+
+    fn ports(alias: &str, initialized: &[&str]) -> Ports<'static> {
+        Ports {
+            alias: alias.into(),
+            inputs: vec!(),
+            outputs: vec!(),
+            initialized: initialized.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn connections(routes: &[(&str, &str)]) -> Vec<(String, String)> {
+        routes.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    fn set(aliases: &[&str]) -> BTreeSet<String> {
+        aliases.iter().map(|alias| alias.to_string()).collect()
+    }
+
+    #[test]
+    fn loop_found() {
+        let processes = vec!(ports("a", &[]), ports("b", &[]), ports("c", &[]));
+        let connections = connections(&[("a/out", "b/in"), ("b", "c"), ("c", "a/in"), ("input/x", "a/in")]);
+        assert_eq!(cycles(&processes, &connections), vec!(set(&["a", "b", "c"])));
+    }
+
+    #[test]
+    fn process_connected_to_itself_is_a_loop() {
+        let processes = vec!(ports("a", &[]), ports("b", &[]));
+        let connections = connections(&[("a", "a/in"), ("a", "b")]);
+        assert_eq!(cycles(&processes, &connections), vec!(set(&["a"])));
+    }
+
+    #[test]
+    fn chain_is_not_a_loop() {
+        let processes = vec!(ports("a", &[]), ports("b", &[]));
+        let connections = connections(&[("a", "b"), ("b", "output/x")]);
+        assert!(cycles(&processes, &connections).is_empty());
+    }
+
+    #[test]
+    fn loop_without_an_initializer_found() {
+        let processes = vec!(ports("a", &[]), ports("b", &["other"]));
+        let connections = connections(&[("a", "b/in"), ("b", "a/in")]);
+        assert_eq!(uninitialized_cycles(&processes, &connections), vec!(set(&["a", "b"])));
+    }
+
+    #[test]
+    fn loop_with_an_initializer_can_start() {
+        let processes = vec!(ports("a", &[]), ports("b", &["in"]));
+        let connections = connections(&[("a", "b/in"), ("b", "a/in")]);
+        assert!(uninitialized_cycles(&processes, &connections).is_empty());
+    }
+
+    #[test]
+    fn loop_into_an_initialized_default_input_can_start() {
+        let processes = vec!(ports("a", &[""]), ports("b", &[]));
+        let connections = connections(&[("a", "b"), ("b", "a")]);
+        assert!(uninitialized_cycles(&processes, &connections).is_empty());
+    }
+
+    #[test]
+    fn loop_of_sub_flows_checked() {
+        let lints = check(&loaded(LOOP));
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].severity, Severity::Error);
+        assert_eq!(lints[0].alias, Some("a".into()));
+        assert!(lints[0].location.is_empty());
+    }
+
+    #[test]
+    fn loop_of_sub_flows_with_an_initial_value_accepted() {
+        let initialized = LOOP.replacen("source = \"pass.toml\"\n", "source = \"pass.toml\"\ninput.start = { once = 1 }\n", 1);
+        assert!(check(&loaded(&initialized)).is_empty());
+    }
+
+    #[test]
+    fn unconnected_ports_found() {
+        let lints = check(&loaded("flow = \"root\"\n\n[[process]]\nalias = \"a\"\nsource = \"pass.toml\"\n"));
+        let found: Vec<(Severity, Option<String>)> = lints.into_iter().map(|lint| (lint.severity, lint.alias)).collect();
+        assert_eq!(found, vec!((Severity::Error, Some("a".into())), (Severity::Warning, Some("a".into()))));
+    }
+
+    #[test]
+    fn types_compatible() {
+        assert!(compatible("Number", "Number"));
+        assert!(compatible("Value", "Number"));
+        assert!(compatible("String", "Value"));
+        assert!(compatible("Array/Number", "Number"));
+        assert!(compatible("Number", "Array/Number"));
+        assert!(compatible("Array/Value", "Array/Value"));
+    }
+
+    #[test]
+    fn types_not_compatible() {
+        assert!(!compatible("String", "Number"));
+        assert!(!compatible("Array/String", "Number"));
+        assert!(!compatible("Array/Array/Number", "Number"));
+    }
+
+    #[test]
+    fn route_refers_to_port_or_part_of_it() {
+        assert!(refers_to("add/i1", "add", "i1"));
+        assert!(refers_to("add/i1/0", "add", "i1"));
+        assert!(refers_to("add", "add", ""));
+        assert!(refers_to("add/0", "add", ""));
+        assert!(!refers_to("add/i10", "add", "i1"));
+        assert!(!refers_to("adder/i1", "add", "i1"));
+    }
+}
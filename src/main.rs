@@ -15,6 +15,32 @@ mod build_ui;
 mod ui_layout;
 mod toolbar;
 mod notebook;
+mod flow_graph;
+mod flow_graph_view;
+mod graph_painter;
+mod layout;
+mod export;
+mod templates;
+mod syntax;
+mod flow_source_editor;
+mod completion;
+mod flow_edit;
+mod definition;
+mod palette;
+mod history;
+mod layout_file;
+mod refactor;
+mod diff;
+mod rename;
+mod lint;
+mod problems;
+mod compile_errors;
+mod implementations;
+mod build_console;
+mod watch;
+mod compile_options;
+mod stdin;
+mod image_window;
 
 // For logging errors related with the UI that suggest displaying them on the UI maybe impossible
 pub fn log_error(message: &str) {
@@ -2,15 +2,20 @@ use std::env;
 
 use gdk_pixbuf::Pixbuf;
 use gio::prelude::*;
-use gtk::{AboutDialog, AccelFlags, AccelGroup, ApplicationWindow, FileChooserAction,
-          FileChooserDialog, FileFilter, Menu, MenuBar, MenuItem, ResponseType,
-          WidgetExt};
+use gtk::{AboutDialog, AccelFlags, AccelGroup, ApplicationWindow, CheckButton, CheckMenuItem, ComboBoxText, Dialog,
+          DialogFlags, Entry, FileChooserAction, FileChooserButton, FileChooserDialog, FileFilter,
+          Grid, Label, Menu, MenuBar, MenuItem, ResponseType, WidgetExt};
 use gtk::prelude::*;
 
 use flowclib::deserializers::deserializer_helper;
 use std::path::{Path, PathBuf};
 
-use crate::actions;
+use crate::{actions, compile_options, image_window};
+use crate::build_ui::UICONTEXT;
+use crate::compile_options::CompileOptions;
+use crate::implementations::{Rebuild, REBUILDS};
+use crate::templates::{Template, TEMPLATES};
+use crate::ui_context::UiContext;
 
 /// upgrade weak reference or return
 #[macro_export]
@@ -56,12 +61,203 @@ fn open_action<F: 'static>(window: &ApplicationWindow, open: &MenuItem, action_f
     });
 }
 
+// Ask the user for a file to save to, with a filter for each of the 'formats' (a name and the
+// file extension for it), and call 'action_function' with the path to the file chosen
+pub fn save_action<F: 'static>(window: &ApplicationWindow, save: &MenuItem, title: &'static str,
+                               formats: Vec<(String, String)>, action_function: F)
+    where F: Fn(String) {
+    let window_weak = window.downgrade();
+    save.connect_activate(move |_| unsafe {
+        let window = upgrade_weak!(window_weak);
+        let dialog = FileChooserDialog::new(Some(title), Some(&window),
+                                            FileChooserAction::Save);
+        dialog.add_buttons(&[
+            ("Save", ResponseType::Ok),
+            ("Cancel", ResponseType::Cancel)
+        ]);
+
+        dialog.set_do_overwrite_confirmation(true);
+        for (name, extension) in &formats {
+            let filter = FileFilter::new();
+            filter.set_name(Some(name));
+            filter.add_pattern(&format!("*.{}", extension));
+            dialog.add_filter(&filter);
+        }
+        let response = dialog.run();
+        let filename = dialog.get_filename();
+        dialog.destroy();
+
+        if response == ResponseType::Ok {
+            if let Some(path) = filename {
+                action_function(path.to_string_lossy().to_string());
+            }
+        }
+    });
+}
+
+// Ask the user for the name, location and template of a new flow and then create it
+fn new_flow_action(window: &ApplicationWindow, new_flow: &MenuItem) {
+    let window_weak = window.downgrade();
+    new_flow.connect_activate(move |_| unsafe {
+        let window = upgrade_weak!(window_weak);
+        let dialog = Dialog::with_buttons(Some("New Flow"), Some(&window), DialogFlags::MODAL,
+                                          &[("Create", ResponseType::Ok),
+                                              ("Cancel", ResponseType::Cancel)]);
+
+        let grid = Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(10);
+        grid.set_border_width(10);
+
+        let name = Entry::new();
+        name.set_activates_default(true);
+        grid.attach(&Label::new(Some("Name")), 0, 0, 1, 1);
+        grid.attach(&name, 1, 0, 1, 1);
+
+        let location = FileChooserButton::new("Choose a location", FileChooserAction::SelectFolder);
+        if let Ok(cwd) = env::current_dir() {
+            location.set_current_folder(cwd);
+        }
+        grid.attach(&Label::new(Some("Location")), 0, 1, 1, 1);
+        grid.attach(&location, 1, 1, 1, 1);
+
+        let template = ComboBoxText::new();
+        for flow_template in TEMPLATES {
+            template.append(Some(flow_template.id()), flow_template.description());
+        }
+        template.set_active_id(Some(Template::Empty.id()));
+        grid.attach(&Label::new(Some("Template")), 0, 2, 1, 1);
+        grid.attach(&template, 1, 2, 1, 1);
+
+        dialog.get_content_area().pack_start(&grid, true, true, 0);
+        dialog.set_default_response(ResponseType::Ok);
+        dialog.show_all();
+
+        let response = dialog.run();
+        let flow_name = name.get_text().trim().to_string();
+        let directory = location.get_filename();
+        let flow_template = template.get_active_id()
+            .and_then(|id| Template::from_id(&id))
+            .unwrap_or(Template::Empty);
+        dialog.destroy();
+
+        if response == ResponseType::Ok {
+            match directory {
+                Some(dir) => actions::new_flow(flow_name, dir, flow_template),
+                None => UiContext::ui_error("No location chosen for the new flow")
+            }
+        }
+    });
+}
+
 fn compile_action(compile: &MenuItem) {
     compile.connect_activate(move |_| {
         actions::compile_flow();
     });
 }
 
+// A dialog to set the options for compiling the flow loaded, which are saved with it
+fn compile_options_action(window: &ApplicationWindow, compile_options_item: &MenuItem) {
+    let window_weak = window.downgrade();
+    compile_options_item.connect_activate(move |_| unsafe {
+        let window = upgrade_weak!(window_weak);
+        let flow_url = match UICONTEXT.try_lock() {
+            Ok(context) => context.flow.as_ref().map(|flow| flow.source_url.clone()),
+            _ => None
+        };
+        let (flow_url, flow_dir) = match flow_url.and_then(|url| compile_options::flow_dir(&url).map(|dir| (url, dir))) {
+            Some(flow) => flow,
+            None => return UiContext::ui_error("No flow loaded from a file to set the compile options of")
+        };
+        let options = compile_options::read_options(&flow_url);
+
+        let dialog = Dialog::with_buttons(Some("Compile Options"), Some(&window), DialogFlags::MODAL,
+                                          &[("Save", ResponseType::Ok),
+                                              ("Cancel", ResponseType::Cancel)]);
+
+        let grid = Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(10);
+        grid.set_border_width(10);
+
+        let output_dir = FileChooserButton::new("Choose where to write the manifest", FileChooserAction::SelectFolder);
+        output_dir.set_current_folder(flow_dir.join(&options.output_dir));
+        grid.attach(&Label::new(Some("Output directory")), 0, 0, 1, 1);
+        grid.attach(&output_dir, 1, 0, 1, 1);
+
+        let manifest_name = Entry::new();
+        manifest_name.set_text(&options.manifest_name);
+        grid.attach(&Label::new(Some("Manifest name")), 0, 1, 1, 1);
+        grid.attach(&manifest_name, 1, 1, 1, 1);
+
+        let debug_symbols = CheckButton::with_label("Include debug symbols in the manifest");
+        debug_symbols.set_active(options.debug_symbols);
+        grid.attach(&debug_symbols, 1, 2, 1, 1);
+
+        let implementations = ComboBoxText::new();
+        for rebuild in REBUILDS {
+            implementations.append(Some(rebuild.id()), rebuild.description());
+        }
+        implementations.set_active_id(Some(options.implementations.id()));
+        grid.attach(&Label::new(Some("Implementations")), 0, 3, 1, 1);
+        grid.attach(&implementations, 1, 3, 1, 1);
+
+        let lib_dirs = Entry::new();
+        lib_dirs.set_text(&options.lib_dirs.join(","));
+        lib_dirs.set_tooltip_text(Some("Directories to look for libraries in as well as those in FLOW_LIB_PATH, \
+                                        separated by ','. Relative directories are relative to the flow."));
+        grid.attach(&Label::new(Some("Library search path")), 0, 4, 1, 1);
+        grid.attach(&lib_dirs, 1, 4, 1, 1);
+
+        dialog.get_content_area().pack_start(&grid, true, true, 0);
+        dialog.set_default_response(ResponseType::Ok);
+        dialog.show_all();
+
+        let response = dialog.run();
+        // An output directory in the flow's directory is saved relative to it, so it moves with the flow
+        let output_dir = match output_dir.get_filename() {
+            Some(dir) => match dir.strip_prefix(&flow_dir) {
+                Ok(relative) => relative.to_string_lossy().to_string(),
+                Err(_) => dir.to_string_lossy().to_string()
+            },
+            None => options.output_dir.clone()
+        };
+        let name = manifest_name.get_text().trim().to_string();
+        let new_options = CompileOptions {
+            output_dir,
+            manifest_name: if name.is_empty() { options.manifest_name.clone() } else { name },
+            debug_symbols: debug_symbols.get_active(),
+            implementations: implementations.get_active_id()
+                .and_then(|id| Rebuild::from_id(&id))
+                .unwrap_or(options.implementations),
+            lib_dirs: lib_dirs.get_text().split(',')
+                .map(|dir| dir.trim().to_string())
+                .filter(|dir| !dir.is_empty())
+                .collect(),
+        };
+        dialog.destroy();
+
+        if response == ResponseType::Ok && new_options != options {
+            actions::set_compile_options(flow_url, new_options);
+        }
+    });
+}
+
+// Show the windows of the images written by the last run again, once they have been closed
+fn show_images_action(show_images: &MenuItem) {
+    show_images.connect_activate(move |_| {
+        if !image_window::show_all() {
+            UiContext::message("No images have been written by the flow run");
+        }
+    });
+}
+
+fn compile_on_change_action(compile_on_change: &CheckMenuItem) {
+    compile_on_change.connect_toggled(move |item| {
+        actions::watch_flow(item.get_active());
+    });
+}
+
 // Return a Path(PathBuf) to a resource file that is part of the application
 fn resource(path: &str) -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).join("resources").join(path)
@@ -93,10 +289,12 @@ fn about_dialog() -> AboutDialog {
 }
 
 // Flow Menu
-fn flow_menu(app_window: &ApplicationWindow, accelerator_group: &AccelGroup) -> (MenuItem, MenuItem, MenuItem) {
+fn flow_menu(app_window: &ApplicationWindow, accelerator_group: &AccelGroup)
+    -> (MenuItem, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem) {
     let flow_menu = Menu::new();
     let flow = MenuItem::with_label("Flow");
     let new_flow_menu_item = MenuItem::with_label("New Flow");
+    new_flow_action(app_window, &new_flow_menu_item);
     flow_menu.append(&new_flow_menu_item);
 
     let open_flow_menu_item = MenuItem::with_label("Open a Flow");
@@ -107,17 +305,51 @@ fn flow_menu(app_window: &ApplicationWindow, accelerator_group: &AccelGroup) ->
     open_action(app_window, &open_manifest_menu_item, actions::open_manifest);
     flow_menu.append(&open_manifest_menu_item);
 
+    // The save actions are wired up to the flow source editor when the UI is built
+    let save_flow_menu_item = MenuItem::with_label("Save");
+    save_flow_menu_item.set_sensitive(false);
+    flow_menu.append(&save_flow_menu_item);
+    let (key, modifier) = gtk::accelerator_parse("<Primary>S");
+    save_flow_menu_item.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
+
+    let save_flow_as_menu_item = MenuItem::with_label("Save As…");
+    save_flow_as_menu_item.set_sensitive(false);
+    flow_menu.append(&save_flow_as_menu_item);
+    let (key, modifier) = gtk::accelerator_parse("<Primary><Shift>S");
+    save_flow_as_menu_item.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
+
+    let export_graph_menu_item = MenuItem::with_label("Export Graph…");
+    let graph_formats = vec!(("SVG image".into(), "svg".into()), ("PNG image".into(), "png".into()),
+                             ("Graphviz".into(), "dot".into()));
+    save_action(app_window, &export_graph_menu_item, "Export Graph", graph_formats,
+                actions::export_graph);
+    export_graph_menu_item.set_sensitive(false);
+    flow_menu.append(&export_graph_menu_item);
+
     let compile_flow_menu_item = MenuItem::with_label("Compile");
     compile_action(&compile_flow_menu_item);
     compile_flow_menu_item.set_sensitive(false);
     flow_menu.append(&compile_flow_menu_item);
 
+    let compile_options_menu_item = MenuItem::with_label("Compile Options…");
+    compile_options_action(app_window, &compile_options_menu_item);
+    compile_options_menu_item.set_sensitive(false);
+    flow_menu.append(&compile_options_menu_item);
+
+    let compile_on_change_menu_item = CheckMenuItem::with_label("Compile on Change");
+    compile_on_change_action(&compile_on_change_menu_item);
+    flow_menu.append(&compile_on_change_menu_item);
+
     let run_manifest_menu = MenuItem::with_label("Run");
     run_manifest_menu.set_sensitive(false);
     flow_menu.append(&run_manifest_menu);
     let (key, modifier) = gtk::accelerator_parse("<Primary>R");
     run_manifest_menu.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
 
+    let show_images_menu_item = MenuItem::with_label("Show Images");
+    show_images_action(&show_images_menu_item);
+    flow_menu.append(&show_images_menu_item);
+
     let quit = MenuItem::with_label("Quit");
     flow_menu.append(&quit);
     let window_weak = app_window.downgrade();
@@ -130,7 +362,29 @@ fn flow_menu(app_window: &ApplicationWindow, accelerator_group: &AccelGroup) ->
     quit.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
 
     flow.set_submenu(Some(&flow_menu));
-    (flow, compile_flow_menu_item, run_manifest_menu)
+    (flow, save_flow_menu_item, save_flow_as_menu_item, export_graph_menu_item, compile_flow_menu_item,
+     compile_options_menu_item, run_manifest_menu)
+}
+
+// Edit Menu, with the undo and redo actions that are wired up to the editors when the UI is built
+fn edit_menu(accelerator_group: &AccelGroup) -> (MenuItem, MenuItem, MenuItem) {
+    let edit_menu = Menu::new();
+    let edit = MenuItem::with_label("Edit");
+
+    let undo_menu_item = MenuItem::with_label("Undo");
+    undo_menu_item.set_sensitive(false);
+    edit_menu.append(&undo_menu_item);
+    let (key, modifier) = gtk::accelerator_parse("<Primary>Z");
+    undo_menu_item.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
+
+    let redo_menu_item = MenuItem::with_label("Redo");
+    redo_menu_item.set_sensitive(false);
+    edit_menu.append(&redo_menu_item);
+    let (key, modifier) = gtk::accelerator_parse("<Primary><Shift>Z");
+    redo_menu_item.add_accelerator("activate", accelerator_group, key, modifier, AccelFlags::VISIBLE);
+
+    edit.set_submenu(Some(&edit_menu));
+    (edit, undo_menu_item, redo_menu_item)
 }
 
 // Help Menu
@@ -154,17 +408,24 @@ fn help_menu(app_window: &ApplicationWindow, _accelerator_group: &AccelGroup) ->
 }
 
 // Create a Menu bar with the submenus on it
-pub fn menu_bar(app_window: &ApplicationWindow) -> (MenuBar, AccelGroup, MenuItem, MenuItem) {
+pub fn menu_bar(app_window: &ApplicationWindow)
+    -> (MenuBar, AccelGroup, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem, MenuItem) {
     let accelerator_group = AccelGroup::new();
     let menu_bar = MenuBar::new();
 
     // Create and append a "Flow" menu
-    let (flow_menu, compile_flow_menu_item, run_manifest_menu) = flow_menu(&app_window, &accelerator_group);
+    let (flow_menu, save_flow_menu_item, save_flow_as_menu_item, export_graph_menu_item,
+        compile_flow_menu_item, compile_options_menu_item, run_manifest_menu) = flow_menu(&app_window, &accelerator_group);
     menu_bar.append(&flow_menu);
 
+    // Create and append an "Edit" menu
+    let (edit_menu, undo_menu_item, redo_menu_item) = edit_menu(&accelerator_group);
+    menu_bar.append(&edit_menu);
+
     // Create and add an "Help" menu
     let help_menu = help_menu(&app_window, &accelerator_group);
     menu_bar.append(&help_menu);
 
-    (menu_bar, accelerator_group, compile_flow_menu_item, run_manifest_menu)
+    (menu_bar, accelerator_group, save_flow_menu_item, save_flow_as_menu_item, undo_menu_item, redo_menu_item,
+     export_graph_menu_item, compile_flow_menu_item, compile_options_menu_item, run_manifest_menu)
 }
\ No newline at end of file
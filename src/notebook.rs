@@ -1,24 +1,30 @@
-use gtk::{ScrolledWindow, TextBuffer};
+use gtk::TextBuffer;
 use gtk::prelude::*;
 
-fn flow_graph_viewer(title: &str, notebook: &mut gtk::Notebook) -> ScrolledWindow {
-    let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
-    // let view = gtk::TextView::new();
-    // view.set_editable(false);
-    // scroll.add(&view);
+use crate::flow_graph_view::FlowGraphView;
+use crate::flow_source_editor::FlowSourceEditor;
+
+fn flow_graph_viewer(title: &str, notebook: &mut gtk::Notebook) -> FlowGraphView {
+    let view = FlowGraphView::new();
     let label = gtk::Label::new(Some(title));
-    notebook.append_page(&scroll, Some(&label));
-    scroll
+    notebook.append_page(view.widget(), Some(&label));
+    view
 }
 
-fn flow_json_viewer(title: &str, notebook: &mut gtk::Notebook) -> TextBuffer {
-    let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
-    let view = gtk::TextView::new();
-    view.set_editable(false);
-    scroll.add(&view);
+// An editor for the flow's definition file. The tab's title is marked with a '*' while there
+// are unsaved changes
+fn flow_source_editor(title: &'static str, notebook: &mut gtk::Notebook) -> FlowSourceEditor {
+    let editor = FlowSourceEditor::new();
     let label = gtk::Label::new(Some(title));
-    notebook.append_page(&scroll, Some(&label));
-    view.get_buffer().unwrap()
+    notebook.append_page(editor.widget(), Some(&label));
+    editor.buffer().connect_modified_changed(move |buffer| {
+        if buffer.get_modified() {
+            label.set_text(&format!("{} *", title));
+        } else {
+            label.set_text(title);
+        }
+    });
+    editor
 }
 
 fn manifest_viewer(title: &str, notebook: &mut gtk::Notebook) -> TextBuffer {
@@ -31,9 +37,9 @@ fn manifest_viewer(title: &str, notebook: &mut gtk::Notebook) -> TextBuffer {
     view.get_buffer().unwrap()
 }
 
-pub fn create_tabs(notebook: &mut gtk::Notebook) -> (TextBuffer, TextBuffer ) {
-    let _flow_graph_view = flow_graph_viewer("Flow", notebook);
-    let flow_buffer = flow_json_viewer("Flow (json)", notebook);
+pub fn create_tabs(notebook: &mut gtk::Notebook) -> (FlowGraphView, FlowSourceEditor, TextBuffer) {
+    let flow_graph_view = flow_graph_viewer("Flow", notebook);
+    let flow_source_editor = flow_source_editor("Flow Source", notebook);
     let manifest_buffer = manifest_viewer("Manifest", notebook);
-    (flow_buffer, manifest_buffer)
+    (flow_graph_view, flow_source_editor, manifest_buffer)
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::Path;
+
+use gdk::{DragAction, ModifierType};
+use gtk::{ScrolledWindow, TargetEntry, TargetFlags, TreeIter, TreeStore, TreeView, TreeViewColumn};
+use gtk::prelude::*;
+use url::Url;
+
+use flowclib::deserializers::deserializer_helper;
+
+use crate::{actions, compile_options, layout_file};
+
+/// The drag and drop target used to drop the source of a process on the flow graph view
+pub const PROCESS_SOURCE_TARGET: &str = "text/plain";
+
+const NAME_COLUMN: u32 = 0;
+const SOURCE_COLUMN: u32 = 1;
+// Sub-directories of a flow's directory are only listed to this depth
+const MAX_DEPTH: usize = 4;
+
+// A tree view of the definitions in 'store', from which they can be dragged onto the flow graph
+fn palette_view(store: &TreeStore, title: &str) -> ScrolledWindow {
+    let view = TreeView::new_with_model(store);
+    let column = TreeViewColumn::new();
+    column.set_title(title);
+    let cell = gtk::CellRendererText::new();
+    column.pack_start(&cell, true);
+    column.add_attribute(&cell, "text", NAME_COLUMN as i32);
+    view.append_column(&column);
+
+    view.drag_source_set(ModifierType::BUTTON1_MASK,
+                         &[TargetEntry::new(PROCESS_SOURCE_TARGET, TargetFlags::SAME_APP, 0)],
+                         DragAction::COPY);
+    view.connect_drag_data_get(|view, _, data, _, _| {
+        if let Some((model, iter)) = view.get_selection().get_selected() {
+            let source = model.get_value(&iter, SOURCE_COLUMN as i32).get::<String>().ok().flatten();
+            // Directories have no source and cannot be dropped
+            if let Some(source) = source.filter(|source| !source.is_empty()) {
+                data.set_text(&source);
+            }
+        }
+    });
+
+    let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scroll.add(&view);
+    scroll
+}
+
+fn new_store() -> TreeStore {
+    TreeStore::new(&[glib::Type::String, glib::Type::String])
+}
+
+fn add_row(store: &TreeStore, parent: Option<&TreeIter>, name: &str, source: &str) -> TreeIter {
+    store.insert_with_values(parent, None, &[NAME_COLUMN, SOURCE_COLUMN], &[&name, &source])
+}
+
+/// The list of definitions in the directory of the flow being edited, empty until a flow is loaded
+pub fn flow_files() -> (ScrolledWindow, TreeStore) {
+    let store = new_store();
+    (palette_view(&store, "Flow Files"), store)
+}
+
+/// The list of definitions in the libraries found on the library search path
+pub fn flow_libs() -> ScrolledWindow {
+    let store = new_store();
+    for lib_root in actions::get_lib_search_path().directories() {
+        for (name, path) in sorted_entries(&lib_root) {
+            if path.is_dir() {
+                let parent = add_row(&store, None, &name, "");
+                add_definitions(&store, &parent, &path, &format!("lib://{}", name), true, 0);
+            }
+        }
+    }
+
+    palette_view(&store, "Flow Libs")
+}
+
+/// Show the definitions in the directory of the flow loaded from 'url', or clear the list if None
+pub fn show_flow_files(store: &TreeStore, url: Option<&str>) {
+    store.clear();
+
+    let flow_path = match url.and_then(|url| Url::parse(url).ok()).and_then(|url| url.to_file_path().ok()) {
+        Some(path) => path,
+        None => return
+    };
+
+    if let Some(directory) = flow_path.parent() {
+        for (name, path) in sorted_entries(directory) {
+            if path.is_dir() {
+                let parent = add_row(store, None, &name, "");
+                add_definitions(store, &parent, &path, "", false, 1);
+            } else if let Some(source) = file_source(&path) {
+                add_row(store, None, &name, &source);
+            }
+        }
+    }
+}
+
+// Add the sub-directories and definitions in 'directory' under 'parent'. Library definitions are
+// referenced as "{prefix}/{path}" without their extension, other files by their url.
+fn add_definitions(store: &TreeStore, parent: &TreeIter, directory: &Path, prefix: &str, library: bool, depth: usize) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+
+    for (name, path) in sorted_entries(directory) {
+        if path.is_dir() {
+            let sub_parent = add_row(store, Some(parent), &name, "");
+            add_definitions(store, &sub_parent, &path, &format!("{}/{}", prefix, name), library, depth + 1);
+        } else if library {
+            if is_definition(&path) {
+                let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+                add_row(store, Some(parent), &name, &format!("{}/{}", prefix, stem));
+            }
+        } else if let Some(source) = file_source(&path) {
+            add_row(store, Some(parent), &name, &source);
+        }
+    }
+}
+
+fn file_source(path: &Path) -> Option<String> {
+    if is_definition(path) {
+        Url::from_file_path(path).ok().map(|url| url.to_string())
+    } else {
+        None
+    }
+}
+
+// Definitions are files with an extension a deserializer accepts, except the cargo and flow
+// manifests and the layout and compile options files that use the same formats
+fn is_definition(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    if name == "Cargo.toml" || name.starts_with("manifest.") || layout_file::is_layout_file(&name)
+        || compile_options::is_options_file(&name) {
+        return false;
+    }
+
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+    deserializer_helper::get_accepted_extensions().contains(&extension.as_str())
+}
+
+// The entries of 'directory' by name, without hidden files or build output
+fn sorted_entries(directory: &Path) -> Vec<(String, std::path::PathBuf)> {
+    let mut entries: Vec<(String, std::path::PathBuf)> = fs::read_dir(directory).into_iter().flatten().flatten()
+        .map(|entry| (entry.file_name().to_string_lossy().to_string(), entry.path()))
+        .filter(|(name, _)| !name.starts_with('.') && name != "target")
+        .collect();
+    entries.sort();
+    entries
+}
@@ -0,0 +1,118 @@
+use gtk::{ListStore, ScrolledWindow, TreeView, TreeViewColumn};
+use gtk::prelude::*;
+
+use crate::compile_errors::CompileError;
+use crate::lint::{Lint, Severity};
+
+const SEVERITY_COLUMN: u32 = 0;
+const MESSAGE_COLUMN: u32 = 1;
+const PLACE_COLUMN: u32 = 2;
+// Not shown: where the problem is, to go to it in the flow graph view or source editor
+const LOCATION_COLUMN: u32 = 3;
+const ALIAS_COLUMN: u32 = 4;
+const URL_COLUMN: u32 = 5;
+const TEXT_COLUMN: u32 = 6;
+
+/// A problem shown in a list, and where it is: in the (sub)flow reached from the root through the
+/// sub-flows with the aliases in 'location', and the process in it with 'alias', or the 'text'
+/// in the definition at 'url'
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub severity: Severity,
+    pub message: String,
+    // Where the problem is, as shown to the user
+    pub place: String,
+    pub location: Vec<String>,
+    pub alias: Option<String>,
+    pub url: Option<String>,
+    pub text: Option<String>,
+}
+
+impl Entry {
+    /// A problem found checking the flow called 'flow_name'
+    pub fn from_lint(flow_name: &str, lint: &Lint) -> Self {
+        let mut place = flow_name.to_string();
+        for alias in &lint.location {
+            place = format!("{}/{}", place, alias);
+        }
+        Entry {
+            severity: lint.severity,
+            message: lint.message.clone(),
+            place,
+            location: lint.location.clone(),
+            alias: lint.alias.clone(),
+            url: None,
+            text: None,
+        }
+    }
+
+    pub fn from_compile_error(error: &CompileError) -> Self {
+        let file = error.url.as_ref().map(|url| url.rsplit('/').next().unwrap_or(url).to_string());
+        let place = match (file, &error.alias) {
+            (Some(file), Some(alias)) => format!("{} ({})", file, alias),
+            (Some(file), None) => file,
+            (None, Some(alias)) => alias.clone(),
+            (None, None) => String::new()
+        };
+        Entry {
+            severity: error.severity,
+            message: error.message.clone(),
+            place,
+            location: error.location.clone(),
+            alias: error.alias.clone(),
+            url: error.url.clone(),
+            text: error.text.clone(),
+        }
+    }
+}
+
+/// A list of problems with the title 'place' for the column saying where they are, and the store
+/// to fill it from
+pub fn problems_list(place: &str) -> (ScrolledWindow, TreeView, ListStore) {
+    let store = ListStore::new(&[glib::Type::String, glib::Type::String, glib::Type::String,
+        glib::Type::String, glib::Type::String, glib::Type::String, glib::Type::String]);
+    let view = TreeView::new_with_model(&store);
+    for (title, column_index) in &[("", SEVERITY_COLUMN), ("Problem", MESSAGE_COLUMN), (place, PLACE_COLUMN)] {
+        let column = TreeViewColumn::new();
+        column.set_title(title);
+        column.set_resizable(true);
+        let cell = gtk::CellRendererText::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", *column_index as i32);
+        view.append_column(&column);
+    }
+
+    let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scroll.add(&view);
+    (scroll, view, store)
+}
+
+/// Show the problems in a list, replacing those shown before
+pub fn show_problems(store: &ListStore, entries: &[Entry]) {
+    store.clear();
+    for entry in entries {
+        store.insert_with_values(None,
+                                 &[SEVERITY_COLUMN, MESSAGE_COLUMN, PLACE_COLUMN, LOCATION_COLUMN, ALIAS_COLUMN,
+                                     URL_COLUMN, TEXT_COLUMN],
+                                 &[&entry.severity.to_string(), &entry.message, &entry.place, &entry.location.join("/"),
+                                     &entry.alias.clone().unwrap_or_default(), &entry.url.clone().unwrap_or_default(),
+                                     &entry.text.clone().unwrap_or_default()]);
+    }
+}
+
+/// The problem selected in a list
+pub fn selected_problem(view: &TreeView) -> Option<Entry> {
+    let (model, iter) = view.get_selection().get_selected()?;
+    let text = |column: u32| model.get_value(&iter, column as i32).get::<String>().ok().flatten().unwrap_or_default();
+    let optional = |column: u32| Some(text(column)).filter(|value| !value.is_empty());
+
+    Some(Entry {
+        severity: if text(SEVERITY_COLUMN) == Severity::Warning.to_string() { Severity::Warning } else { Severity::Error },
+        message: text(MESSAGE_COLUMN),
+        place: text(PLACE_COLUMN),
+        location: text(LOCATION_COLUMN).split('/').filter(|alias| !alias.is_empty()).map(|alias| alias.to_string()).collect(),
+        alias: optional(ALIAS_COLUMN),
+        url: optional(URL_COLUMN),
+        text: optional(TEXT_COLUMN),
+    })
+}
@@ -0,0 +1,375 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+use url::Url;
+
+use flowclib::model::datatype::HasDataType;
+use flowclib::model::flow::Flow;
+use flowclib::model::io::IO;
+use flowclib::model::name::HasName;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
+
+use crate::definition;
+use crate::flow_edit::{self, route_alias, FlowEdit, Fragment};
+use crate::syntax::SourceFormat;
+
+// The type used for a sub-flow's input or output when the type of the port connected to it is unknown
+const DEFAULT_TYPE: &str = "Value";
+
+/// A new sub-flow extracted from a flow: the file name and definition of the new flow, and the
+/// edit of the parent flow that replaces the extracted processes with a reference to it
+pub struct Extraction {
+    pub file_name: String,
+    pub definition: String,
+    pub edit: FlowEdit,
+}
+
+/// Extract the processes of 'flow' with 'aliases' into a new flow called 'name', defined in a file
+/// next to the flow's. Connections between the extracted processes move with them, and those
+/// crossing to the rest of the flow go through inputs and outputs of the new flow.
+pub fn extract(flow: &Flow, aliases: &[String], name: &str) -> Result<Extraction, String> {
+    if aliases.is_empty() {
+        return Err("Select the processes to extract into a new flow".into());
+    }
+    if name.is_empty() || name.contains(|c: char| c == '/' || c.is_whitespace()) {
+        return Err(format!("'{}' is not a valid name for a flow", name));
+    }
+
+    let inside = |route: &str| aliases.iter().any(|alias| alias == route_alias(route));
+    let remaining: Vec<String> = flow.process_refs.iter().flatten()
+        .map(|process_ref| process_ref.alias.to_string())
+        .filter(|alias| !aliases.contains(alias))
+        .collect();
+    let sub_flow_alias = flow_edit::unique_alias(&remaining, name);
+
+    let mut sub_connections = vec!();
+    let mut parent_connections = vec!();
+    // The ports of the extracted processes that are connected from, or to, the rest of the flow,
+    // each becoming an input, or output, of the new flow named after the port
+    let mut inputs: BTreeMap<String, String> = BTreeMap::new();
+    let mut outputs: BTreeMap<String, String> = BTreeMap::new();
+
+    for connection in flow.connections.iter().flatten() {
+        let (from, to) = (connection.from.to_string(), connection.to.to_string());
+        match (inside(&from), inside(&to)) {
+            (true, true) => sub_connections.push(json!({"from": from, "to": to})),
+            (false, true) => {
+                let input = io_name(&to);
+                if !inputs.contains_key(&input) {
+                    inputs.insert(input.clone(), port_type(flow, &to, true));
+                    sub_connections.push(json!({"from": format!("input/{}", input), "to": to}));
+                }
+                parent_connections.push((from, format!("{}/{}", sub_flow_alias, input)));
+            }
+            (true, false) => {
+                let output = io_name(&from);
+                if !outputs.contains_key(&output) {
+                    outputs.insert(output.clone(), port_type(flow, &from, false));
+                    sub_connections.push(json!({"from": from, "to": format!("output/{}", output)}));
+                }
+                parent_connections.push((format!("{}/{}", sub_flow_alias, output), to));
+            }
+            (false, false) => {}
+        }
+    }
+
+    let processes = flow.process_refs.iter().flatten()
+        .filter(|process_ref| aliases.contains(&process_ref.alias.to_string()))
+        .map(|process_ref| serde_json::to_value(process_ref).map(flow_edit::without_nulls).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<Value>, String>>()?;
+
+    let mut sub_flow = Map::new();
+    sub_flow.insert("flow".into(), Value::String(name.into()));
+    if !inputs.is_empty() {
+        sub_flow.insert("input".into(), io_entries(&inputs));
+    }
+    if !outputs.is_empty() {
+        sub_flow.insert("output".into(), io_entries(&outputs));
+    }
+    sub_flow.insert("process".into(), Value::Array(processes));
+    if !sub_connections.is_empty() {
+        sub_flow.insert("connection".into(), Value::Array(sub_connections));
+    }
+
+    // The new flow is written in the same format, and directory, as the flow it is extracted from
+    // so the sources of the processes moved into it are still correct
+    let format = SourceFormat::from_url(&flow.source_url);
+    let extension = Url::parse(&flow.source_url).ok()
+        .and_then(|url| Path::new(url.path()).extension().map(|extension| extension.to_string_lossy().to_string()))
+        .unwrap_or_else(|| format.extension().into());
+    let file_name = format!("{}.{}", name, extension);
+    let definition = definition::serialize(format, &Value::Object(sub_flow))?;
+
+    let mut edits: Vec<FlowEdit> = aliases.iter()
+        .map(|alias| FlowEdit::RemoveProcess { alias: alias.clone() })
+        .collect();
+    edits.push(FlowEdit::AddProcess { alias: sub_flow_alias, source: file_name.clone() });
+    edits.extend(parent_connections.into_iter().map(|(from, to)| FlowEdit::AddConnection { from, to }));
+
+    Ok(Extraction {
+        file_name,
+        definition,
+        edit: FlowEdit::Batch { description: format!("Extract sub-flow '{}'", name), edits },
+    })
+}
+
+// The name of the sub-flow input or output for a route to a process port: the alias and the port
+fn io_name(route: &str) -> String {
+    route.trim_start_matches('/').replace('/', "_")
+}
+
+fn io_entries(ios: &BTreeMap<String, String>) -> Value {
+    Value::Array(ios.iter().map(|(name, datatype)| json!({"name": name, "type": datatype})).collect())
+}
+
+// The type of the input (or output) of the process in 'flow' a route refers to
+fn port_type(flow: &Flow, route: &str, input: bool) -> String {
+    let alias = route_alias(route);
+    let port = route.trim_start_matches('/')[alias.len()..].trim_start_matches('/');
+    let port_name = port.split('/').next().unwrap_or("");
+
+    let process = match flow.subprocesses.iter().find(|(name, _)| name.to_string() == alias) {
+        Some((_, process)) => process,
+        None => return DEFAULT_TYPE.into()
+    };
+    let ios: Vec<IO> = match (process, input) {
+        (FlowProcess(sub_flow), true) => sub_flow.inputs.iter().flatten().cloned().collect(),
+        (FlowProcess(sub_flow), false) => sub_flow.outputs.iter().flatten().cloned().collect(),
+        (FunctionProcess(function), true) => function.get_inputs().iter().flatten().cloned().collect(),
+        (FunctionProcess(function), false) => function.get_outputs().iter().flatten().cloned().collect(),
+    };
+
+    ios.iter().find(|io| io.name().to_string() == port || io.name().to_string() == port_name)
+        .map(|io| io.datatype(0).to_string())
+        .unwrap_or_else(|| DEFAULT_TYPE.into())
+}
+
+/// Replace the sub-flow process 'alias' of 'flow' with the processes of the sub-flow. They are
+/// given aliases prefixed with the sub-flow's alias, and connections to and from the sub-flow's
+/// inputs and outputs are made directly to the processes they were connected to inside it.
+pub fn inline(flow: &Flow, alias: &str) -> Result<FlowEdit, String> {
+    let sub_flow = match flow.subprocesses.iter().find(|(name, _)| name.to_string() == alias) {
+        Some((_, FlowProcess(sub_flow))) => sub_flow,
+        Some(_) => return Err(format!("'{}' is a function, only sub-flows can be inlined", alias)),
+        None => return Err(format!("Flow '{}' has no process called '{}'", flow.name, alias))
+    };
+
+    // Values the parent gives the sub-flow's inputs would have to be moved to the processes
+    // inside it, which may already be initialized
+    let initialized = flow.process_refs.iter().flatten()
+        .find(|process_ref| process_ref.alias.to_string() == alias)
+        .and_then(|process_ref| serde_json::to_value(process_ref).ok())
+        .map(flow_edit::without_nulls)
+        .map_or(false, |process_ref| process_ref.get("input").map_or(false, |inputs| !inputs.is_null()));
+    if initialized {
+        return Err(format!("Cannot inline '{}' as its inputs are initialized", alias));
+    }
+
+    let mut existing: Vec<String> = flow.process_refs.iter().flatten()
+        .map(|process_ref| process_ref.alias.to_string())
+        .filter(|existing_alias| existing_alias != alias)
+        .collect();
+    let mut renames = HashMap::new();
+    let mut processes = vec!();
+    for process_ref in sub_flow.process_refs.iter().flatten() {
+        let inner_alias = process_ref.alias.to_string();
+        let new_alias = flow_edit::numbered_alias(&existing, &format!("{}_{}", alias, inner_alias));
+        existing.push(new_alias.clone());
+
+        let mut process = serde_json::to_value(process_ref).map(flow_edit::without_nulls).map_err(|e| e.to_string())?;
+        process["alias"] = Value::String(new_alias.clone());
+        processes.push(process);
+        renames.insert(inner_alias, new_alias);
+    }
+    let rename = |route: &str| {
+        let inner_alias = route_alias(route);
+        match renames.get(inner_alias) {
+            Some(new_alias) => format!("{}{}", new_alias, &route.trim_start_matches('/')[inner_alias.len()..]),
+            None => route.to_string()
+        }
+    };
+
+    // Routes in the parent to and from the sub-flow's ports, by port name
+    let sub_flow_port = |route: &str| {
+        let route = route.trim_start_matches('/');
+        if route_alias(route) == alias {
+            Some(route[alias.len()..].trim_start_matches('/').to_string())
+        } else {
+            None
+        }
+    };
+    let parent_connections: Vec<(String, String)> = flow.connections.iter().flatten()
+        .map(|connection| (connection.from.to_string(), connection.to.to_string()))
+        .collect();
+    let sources_of = |input: &str| parent_connections.iter()
+        .filter(|(_, to)| sub_flow_port(to).as_deref() == Some(input))
+        .map(|(from, _)| from.clone())
+        .collect::<Vec<String>>();
+    let destinations_of = |output: &str| parent_connections.iter()
+        .filter(|(from, _)| sub_flow_port(from).as_deref() == Some(output))
+        .map(|(_, to)| to.clone())
+        .collect::<Vec<String>>();
+
+    let mut internal = vec!();
+    let mut rewired = vec!();
+    for connection in sub_flow.connections.iter().flatten() {
+        let (from, to) = (connection.from.to_string(), connection.to.to_string());
+        let from_input = from.trim_start_matches('/').strip_prefix("input/").map(|input| input.to_string());
+        let to_output = to.trim_start_matches('/').strip_prefix("output/").map(|output| output.to_string());
+
+        let froms = match from_input {
+            Some(ref input) => sources_of(input),
+            None => vec!(rename(&from))
+        };
+        let tos = match to_output {
+            Some(ref output) => destinations_of(output),
+            None => vec!(rename(&to))
+        };
+
+        for from in &froms {
+            for to in &tos {
+                if from_input.is_none() && to_output.is_none() {
+                    internal.push((from.clone(), to.clone()));
+                } else {
+                    rewired.push(FlowEdit::AddConnection { from: from.clone(), to: to.clone() });
+                }
+            }
+        }
+    }
+
+    let mut edits = vec!(
+        FlowEdit::RemoveProcess { alias: alias.to_string() },
+        FlowEdit::Paste {
+            fragment: Fragment { flow_url: sub_flow.source_url.clone(), processes, connections: internal }
+        },
+    );
+    edits.extend(rewired);
+
+    Ok(FlowEdit::Batch { description: format!("Inline sub-flow '{}'", alias), edits })
+}
+
+#[cfg(test)]
+mod test {
+    use flowclib::deserializers::deserializer_helper;
+    use flowclib::model::flow::Flow;
+    use flowclib::model::process::Process::{self, FlowProcess, FunctionProcess};
+
+    use crate::flow_edit::FlowEdit;
+
+    use super::{inline, io_name};
+
+    const PARENT: &str = "flow = \"parent\"
+
+[[process]]
+alias = \"seq\"
+source = \"seq.toml\"
+
+[[process]]
+alias = \"seq_tap\"
+source = \"tap.toml\"
+
+[[process]]
+alias = \"print\"
+source = \"print.toml\"
+
+[[connection]]
+from = \"seq_tap\"
+to = \"seq/start\"
+
+[[connection]]
+from = \"seq/number\"
+to = \"print\"
+";
+
+    const SEQ: &str = "flow = \"seq\"
+
+[[input]]
+name = \"start\"
+type = \"Number\"
+
+[[output]]
+name = \"number\"
+type = \"Number\"
+
+[[process]]
+alias = \"tap\"
+source = \"tap.toml\"
+
+[[process]]
+alias = \"add\"
+source = \"lib://flowstdlib/math/add\"
+
+[[connection]]
+from = \"input/start\"
+to = \"tap\"
+
+[[connection]]
+from = \"tap\"
+to = \"add/i1\"
+
+[[connection]]
+from = \"add\"
+to = \"output/number\"
+";
+
+    // The flow defined by 'text' as it is when loaded from 'url'
+    fn flow(url: &str, text: &str) -> Flow {
+        match deserializer_helper::get_deserializer::<Process>(url).unwrap().deserialize(text, Some(url)).unwrap() {
+            FlowProcess(mut flow) => {
+                flow.source_url = url.into();
+                flow
+            }
+            FunctionProcess(_) => panic!("'{}' is not a flow", url)
+        }
+    }
+
+    // The parent flow with the 'seq' sub-flow loaded into it
+    fn parent(text: &str) -> Flow {
+        let mut parent = flow("file:///flows/parent.toml", text);
+        let seq = parent.process_refs.iter().flatten().find(|process_ref| process_ref.alias.to_string() == "seq")
+            .map(|process_ref| process_ref.alias.clone()).unwrap();
+        parent.subprocesses.insert(seq, FlowProcess(flow("file:///flows/seq.toml", SEQ)));
+        parent
+    }
+
+    #[test]
+    fn inlined_processes_renamed_and_rewired() {
+        let edits = match inline(&parent(PARENT), "seq").unwrap() {
+            FlowEdit::Batch { edits, .. } => edits,
+            edit => panic!("{:?} is not a batch of edits", edit)
+        };
+
+        assert_eq!(edits[0], FlowEdit::RemoveProcess { alias: "seq".into() });
+        match &edits[1] {
+            FlowEdit::Paste { fragment } => {
+                // 'seq_tap' is already used in the parent
+                assert_eq!(fragment.aliases(), vec!("seq_tap_2".to_string(), "seq_add".to_string()));
+                assert_eq!(fragment.flow_url, "file:///flows/seq.toml");
+                assert_eq!(fragment.connections, vec!(("seq_tap_2".to_string(), "seq_add/i1".to_string())));
+            }
+            edit => panic!("{:?} is not a paste", edit)
+        }
+        assert_eq!(edits[2..].to_vec(), vec!(
+            FlowEdit::AddConnection { from: "seq_tap".into(), to: "seq_tap_2".into() },
+            FlowEdit::AddConnection { from: "seq_add".into(), to: "print".into() }));
+    }
+
+    #[test]
+    fn sub_flow_with_initialized_inputs_not_inlined() {
+        let initialized = PARENT.replace("source = \"seq.toml\"\n", "source = \"seq.toml\"\ninput.start = { once = 1 }\n");
+        assert!(inline(&parent(&initialized), "seq").is_err());
+    }
+
+    #[test]
+    fn only_sub_flows_inlined() {
+        assert!(inline(&parent(PARENT), "print").is_err());
+        assert!(inline(&parent(PARENT), "missing").is_err());
+    }
+
+    #[test]
+    fn io_named_after_the_route() {
+        assert_eq!(io_name("/add/i1"), "add_i1");
+        assert_eq!(io_name("tap"), "tap");
+    }
+}
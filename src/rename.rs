@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use flowclib::model::flow::Flow;
+use flowclib::model::name::HasName;
+use flowclib::model::process::Process::FlowProcess;
+
+use crate::flow_edit;
+use crate::history::MovedPositions;
+
+/// Something in a flow that can be renamed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Renamed {
+    // The process with this alias in the flow
+    Process { alias: String },
+    // An input (or output) of the flow defined at 'flow_url', which is the flow itself or one of
+    // its sub-flows
+    Port { flow_url: String, name: String, input: bool },
+}
+
+/// The renames to make in one definition
+#[derive(Debug, Default)]
+pub struct Renames {
+    // Aliases of processes of the flow: old and new
+    aliases: Vec<(String, String)>,
+    // Inputs and outputs of the flow itself: whether it is an input, old and new name
+    ios: Vec<(bool, String, String)>,
+    // Inputs and outputs of sub-flows of the flow: the sub-flow's alias, whether it is an input,
+    // old and new name
+    ports: Vec<(String, bool, String, String)>,
+}
+
+/// A rename to be made: what it is, and what to change in the definition at each url affected
+pub struct Plan {
+    pub description: String,
+    pub definitions: Vec<(String, Renames)>,
+    // The saved positions of a renamed process, in each place the flow defining it is shown
+    pub positions: Vec<MovedPositions>,
+}
+
+/// Work out the changes needed to rename something in the (sub)flow loaded from 'flow_url' of
+/// the 'root' flow. A process alias is only used in the flow that defines it, but the input or
+/// output of a flow is also used by every flow in the root flow that references it as a sub-flow.
+pub fn plan(root: &Flow, flow_url: &str, renamed: &Renamed, new_name: &str) -> Result<Plan, String> {
+    if new_name.is_empty() || new_name.contains(|c: char| c == '/' || c.is_whitespace()) {
+        return Err(format!("'{}' is not a valid name", new_name));
+    }
+
+    let mut definitions: BTreeMap<String, Renames> = BTreeMap::new();
+    let mut positions = vec!();
+    let description = match renamed {
+        Renamed::Process { alias } => {
+            let flow = flow_edit::find_flow(root, flow_url)
+                .ok_or_else(|| format!("Could not find the flow loaded from '{}'", flow_url))?;
+            let aliases: Vec<String> = flow.process_refs.iter().flatten()
+                .map(|process_ref| process_ref.alias.to_string())
+                .collect();
+            if !aliases.contains(alias) {
+                return Err(format!("Flow '{}' has no process called '{}'", flow.name, alias));
+            }
+            // The first segment of a route to the flow's own inputs and outputs is their direction
+            if aliases.iter().any(|existing| existing == new_name) || new_name == "input" || new_name == "output" {
+                return Err(format!("Flow '{}' already has a process called '{}'", flow.name, new_name));
+            }
+
+            definitions.entry(flow_url.to_string()).or_default()
+                .aliases.push((alias.clone(), new_name.to_string()));
+            positions = flow_locations(root, &root.source_url, flow_url).into_iter()
+                .map(|location| MovedPositions { location, from: alias.clone(), to: new_name.to_string() })
+                .collect();
+            format!("Rename process '{}' to '{}'", alias, new_name)
+        }
+        Renamed::Port { flow_url: port_flow_url, name, input } => {
+            let flow = flow_edit::find_flow(root, port_flow_url)
+                .ok_or_else(|| format!("Could not find the flow loaded from '{}'", port_flow_url))?;
+            let direction = if *input { "input" } else { "output" };
+            let ios = if *input { &flow.inputs } else { &flow.outputs };
+            let names: Vec<String> = ios.iter().flatten().map(|io| io.name().to_string()).collect();
+            if name.is_empty() || !names.contains(name) {
+                return Err(format!("Flow '{}' has no {} called '{}' that can be renamed", flow.name, direction, name));
+            }
+            if names.iter().any(|existing| existing == new_name) {
+                return Err(format!("Flow '{}' already has an {} called '{}'", flow.name, direction, new_name));
+            }
+
+            definitions.entry(port_flow_url.clone()).or_default()
+                .ios.push((*input, name.clone(), new_name.to_string()));
+            for parent in all_flows(root) {
+                for (alias, process) in &parent.subprocesses {
+                    match process {
+                        FlowProcess(sub_flow) if sub_flow.source_url == *port_flow_url => {
+                            let renames = definitions.entry(parent.source_url.clone()).or_default();
+                            let port = (alias.to_string(), *input, name.clone(), new_name.to_string());
+                            // A flow used in several places is loaded again for each of them
+                            if !renames.ports.contains(&port) {
+                                renames.ports.push(port);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            format!("Rename {} '{}' of '{}' to '{}'", direction, name, flow.name, new_name)
+        }
+    };
+
+    if let Some(url) = definitions.keys().find(|url| !url.starts_with("file:")) {
+        return Err(format!("Cannot rename as the definition '{}' that uses it cannot be edited", url));
+    }
+
+    Ok(Plan { description, definitions: definitions.into_iter().collect(), positions })
+}
+
+// The locations in the graph of 'flow', which is at 'location', of the flows loaded from 'flow_url'
+fn flow_locations(flow: &Flow, location: &str, flow_url: &str) -> Vec<String> {
+    let mut locations = vec!();
+    if flow.source_url == flow_url {
+        locations.push(location.to_string());
+    }
+    for (alias, process) in &flow.subprocesses {
+        if let FlowProcess(sub_flow) = process {
+            locations.extend(flow_locations(sub_flow, &format!("{}/{}", location, alias), flow_url));
+        }
+    }
+    locations
+}
+
+// The flow and all of its sub-flows
+fn all_flows(flow: &Flow) -> Vec<&Flow> {
+    let mut flows = vec!(flow);
+    for process in flow.subprocesses.values() {
+        if let FlowProcess(sub_flow) = process {
+            flows.extend(all_flows(sub_flow));
+        }
+    }
+    flows
+}
+
+impl Renames {
+    /// Make the renames in a definition, held as a json value
+    pub fn apply(&self, definition: &mut Value) {
+        for (old, new) in &self.aliases {
+            let mut found = false;
+            for process in entries(definition, "process") {
+                if process.get("alias").and_then(Value::as_str) == Some(old.as_str()) {
+                    process["alias"] = Value::String(new.clone());
+                    found = true;
+                }
+            }
+            // A process without an alias is known by the name of its definition
+            if !found {
+                let unnamed = entries(definition, "process").into_iter()
+                    .find(|process| process.get("alias").is_none() && process.get("source").and_then(Value::as_str)
+                        .map_or(false, |source| flow_edit::unique_alias(&[], source) == *old));
+                if let Some(process) = unnamed {
+                    process["alias"] = Value::String(new.clone());
+                }
+            }
+            rename_routes(definition, "from", &[old.as_str()], new);
+            rename_routes(definition, "to", &[old.as_str()], new);
+        }
+
+        for (input, old, new) in &self.ios {
+            let (direction, end) = if *input { ("input", "from") } else { ("output", "to") };
+            for io in entries(definition, direction) {
+                if io.get("name").and_then(Value::as_str) == Some(old.as_str()) {
+                    io["name"] = Value::String(new.clone());
+                }
+            }
+            rename_routes(definition, end, &[direction, old.as_str()], new);
+        }
+
+        for (alias, input, old, new) in &self.ports {
+            let end = if *input { "to" } else { "from" };
+            rename_routes(definition, end, &[alias.as_str(), old.as_str()], new);
+
+            // Values the flow gives the sub-flow's inputs are by input name
+            if *input {
+                for process in entries(definition, "process") {
+                    if process.get("alias").and_then(Value::as_str) == Some(alias.as_str()) {
+                        if let Some(Value::Object(initializers)) = process.get_mut("input") {
+                            if let Some(initializer) = initializers.remove(old) {
+                                initializers.insert(new.clone(), initializer);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn entries<'a>(definition: &'a mut Value, key: &str) -> Vec<&'a mut Value> {
+    match definition.get_mut(key) {
+        Some(Value::Array(entries)) => entries.iter_mut().collect(),
+        _ => vec!()
+    }
+}
+
+// Rename the last of the 'prefix' segments in the 'end' ("from" or "to") routes of the
+// connections that start with them
+fn rename_routes(definition: &mut Value, end: &str, prefix: &[&str], new: &str) {
+    for connection in entries(definition, "connection") {
+        let renamed = connection.get(end).and_then(Value::as_str)
+            .and_then(|route| rename_route(route, prefix, new));
+        if let Some(route) = renamed {
+            connection[end] = Value::String(route);
+        }
+    }
+}
+
+fn rename_route(route: &str, prefix: &[&str], new: &str) -> Option<String> {
+    let leading = if route.starts_with('/') { "/" } else { "" };
+    let mut segments: Vec<&str> = route.trim_start_matches('/').split('/').collect();
+    if segments.len() < prefix.len() || segments[..prefix.len()] != *prefix {
+        return None;
+    }
+
+    segments[prefix.len() - 1] = new;
+    Some(format!("{}{}", leading, segments.join("/")))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use flowclib::deserializers::deserializer_helper;
+    use flowclib::model::flow::Flow;
+    use flowclib::model::process::Process::{self, FlowProcess, FunctionProcess};
+
+    use super::{plan, rename_route, Renamed, Renames};
+
+    const ROOT: &str = "flow = \"root\"
+
+[[process]]
+alias = \"seq\"
+source = \"seq.toml\"
+
+[[process]]
+alias = \"print\"
+source = \"print.toml\"
+
+[[connection]]
+from = \"seq/number\"
+to = \"print\"
+";
+
+    const SEQ: &str = "flow = \"seq\"
+
+[[input]]
+name = \"start\"
+type = \"Number\"
+
+[[output]]
+name = \"number\"
+type = \"Number\"
+
+[[process]]
+alias = \"tap\"
+source = \"tap.toml\"
+
+[[connection]]
+from = \"input/start\"
+to = \"tap\"
+
+[[connection]]
+from = \"tap\"
+to = \"output/number\"
+";
+
+    // The flow defined by 'text' as it is when loaded from 'url'
+    fn flow(url: &str, text: &str) -> Flow {
+        match deserializer_helper::get_deserializer::<Process>(url).unwrap().deserialize(text, Some(url)).unwrap() {
+            FlowProcess(mut flow) => {
+                flow.source_url = url.into();
+                flow
+            }
+            FunctionProcess(_) => panic!("'{}' is not a flow", url)
+        }
+    }
+
+    // The root flow with the 'seq' sub-flow loaded into it
+    fn root() -> Flow {
+        let mut root = flow("file:///flows/root.toml", ROOT);
+        let seq = root.process_refs.iter().flatten().find(|process_ref| process_ref.alias.to_string() == "seq")
+            .map(|process_ref| process_ref.alias.clone()).unwrap();
+        root.subprocesses.insert(seq, FlowProcess(flow("file:///flows/seq.toml", SEQ)));
+        root
+    }
+
+    fn process(alias: &str) -> Renamed {
+        Renamed::Process { alias: alias.into() }
+    }
+
+    #[test]
+    fn route_renamed_by_its_leading_segments() {
+        assert_eq!(rename_route("input/x", &["input", "x"], "y"), Some("input/y".into()));
+        assert_eq!(rename_route("/input/x", &["input", "x"], "y"), Some("/input/y".into()));
+        assert_eq!(rename_route("input/x/1", &["input", "x"], "y"), Some("input/y/1".into()));
+        assert_eq!(rename_route("x/input", &["input", "x"], "y"), None);
+        assert_eq!(rename_route("input/xx", &["input", "x"], "y"), None);
+        assert_eq!(rename_route("input", &["input", "x"], "y"), None);
+    }
+
+    #[test]
+    fn route_of_process_renamed_with_its_alias() {
+        assert_eq!(rename_route("x/input", &["x"], "y"), Some("y/input".into()));
+        assert_eq!(rename_route("x", &["x"], "y"), Some("y".into()));
+        assert_eq!(rename_route("input/x", &["x"], "y"), None);
+    }
+
+    #[test]
+    fn process_renamed_with_its_connections() {
+        let renames = Renames { aliases: vec!(("add".into(), "sum".into())), ..Renames::default() };
+        let mut definition = json!({
+            "process": [{"alias": "add", "source": "lib://flowstdlib/math/add"}],
+            "connection": [{"from": "add", "to": "print"}, {"from": "input/add", "to": "add/i1"}]
+        });
+        renames.apply(&mut definition);
+        assert_eq!(definition, json!({
+            "process": [{"alias": "sum", "source": "lib://flowstdlib/math/add"}],
+            "connection": [{"from": "sum", "to": "print"}, {"from": "input/add", "to": "sum/i1"}]
+        }));
+    }
+
+    #[test]
+    fn process_without_an_alias_given_one() {
+        let renames = Renames { aliases: vec!(("add".into(), "sum".into())), ..Renames::default() };
+        let mut definition = json!({
+            "process": [{"source": "lib://flowstdlib/math/add"}],
+            "connection": [{"from": "add", "to": "print"}]
+        });
+        renames.apply(&mut definition);
+        assert_eq!(definition, json!({
+            "process": [{"alias": "sum", "source": "lib://flowstdlib/math/add"}],
+            "connection": [{"from": "sum", "to": "print"}]
+        }));
+    }
+
+    #[test]
+    fn io_renamed_with_the_routes_to_it() {
+        let renames = Renames { ios: vec!((true, "start".into(), "begin".into())), ..Renames::default() };
+        let mut definition = json!({
+            "input": [{"name": "start"}],
+            "output": [{"name": "start"}],
+            "connection": [{"from": "input/start", "to": "tap"}, {"from": "tap", "to": "output/start"}]
+        });
+        renames.apply(&mut definition);
+        assert_eq!(definition, json!({
+            "input": [{"name": "begin"}],
+            "output": [{"name": "start"}],
+            "connection": [{"from": "input/begin", "to": "tap"}, {"from": "tap", "to": "output/start"}]
+        }));
+    }
+
+    #[test]
+    fn sub_flow_input_renamed_with_its_initializer() {
+        let renames = Renames { ports: vec!(("seq".into(), true, "start".into(), "begin".into())), ..Renames::default() };
+        let mut definition = json!({
+            "process": [{"alias": "seq", "source": "seq.toml", "input": {"start": {"once": 1}}}],
+            "connection": [{"from": "seq/start", "to": "print"}, {"from": "tap", "to": "seq/start"}]
+        });
+        renames.apply(&mut definition);
+        assert_eq!(definition, json!({
+            "process": [{"alias": "seq", "source": "seq.toml", "input": {"begin": {"once": 1}}}],
+            "connection": [{"from": "seq/start", "to": "print"}, {"from": "tap", "to": "seq/begin"}]
+        }));
+    }
+
+    #[test]
+    fn invalid_names_not_planned() {
+        let root = root();
+        assert!(plan(&root, "file:///flows/root.toml", &process("print"), "").is_err());
+        assert!(plan(&root, "file:///flows/root.toml", &process("print"), "a/b").is_err());
+        assert!(plan(&root, "file:///flows/root.toml", &process("print"), "a b").is_err());
+    }
+
+    #[test]
+    fn names_already_used_not_planned() {
+        let root = root();
+        assert!(plan(&root, "file:///flows/root.toml", &process("print"), "seq").is_err());
+        assert!(plan(&root, "file:///flows/root.toml", &process("print"), "input").is_err());
+        assert!(plan(&root, "file:///flows/root.toml", &process("missing"), "other").is_err());
+        let port = Renamed::Port { flow_url: "file:///flows/seq.toml".into(), name: "start".into(), input: true };
+        assert!(plan(&root, "file:///flows/root.toml", &port, "start").is_err());
+    }
+
+    #[test]
+    fn process_renamed_where_its_flow_is_shown() {
+        let plan = plan(&root(), "file:///flows/seq.toml", &process("tap"), "pass").unwrap();
+        assert_eq!(plan.definitions.len(), 1);
+        assert_eq!(plan.definitions[0].0, "file:///flows/seq.toml");
+        assert_eq!(plan.definitions[0].1.aliases, vec!(("tap".to_string(), "pass".to_string())));
+        assert_eq!(plan.positions.len(), 1);
+        assert_eq!(plan.positions[0].location, "file:///flows/root.toml/seq");
+        assert_eq!((plan.positions[0].from.as_str(), plan.positions[0].to.as_str()), ("tap", "pass"));
+    }
+
+    #[test]
+    fn sub_flow_input_renamed_in_the_flows_using_it() {
+        let port = Renamed::Port { flow_url: "file:///flows/seq.toml".into(), name: "start".into(), input: true };
+        let plan = plan(&root(), "file:///flows/root.toml", &port, "begin").unwrap();
+        let urls: Vec<&str> = plan.definitions.iter().map(|(url, _)| url.as_str()).collect();
+        assert_eq!(urls, vec!("file:///flows/root.toml", "file:///flows/seq.toml"));
+        assert_eq!(plan.definitions[0].1.ports, vec!(("seq".to_string(), true, "start".to_string(), "begin".to_string())));
+        assert_eq!(plan.definitions[1].1.ios, vec!((true, "start".to_string(), "begin".to_string())));
+        assert!(plan.positions.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! The input for STDIN of a running flow, entered by the user a line at a time, or read from a
+//! file, and buffered until the flow reads it. The flow's runtime client waits for input when
+//! there is none yet.
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// The input for STDIN of the flow being run
+    pub static ref STDIN: Input = Input::default();
+}
+
+#[derive(Default)]
+struct Buffered {
+    lines: VecDeque<String>,
+    // The end of the input has been sent, so there are no more lines to come after those buffered
+    eof: bool,
+    // Counts the runs the input has been reset for, so readers waiting for the input of an
+    // earlier run stop waiting
+    run: usize,
+}
+
+/// Lines of input buffered for reading by a flow, that readers wait for
+#[derive(Default)]
+pub struct Input {
+    buffered: Mutex<Buffered>,
+    available: Condvar,
+}
+
+impl Input {
+    /// Discard any input left from a previous run, ready for a new one. Readers still waiting for
+    /// input from the previous run are woken and get the end of the input.
+    pub fn reset(&self) {
+        if let Ok(mut buffered) = self.buffered.lock() {
+            let run = buffered.run.wrapping_add(1);
+            *buffered = Buffered { run, ..Buffered::default() };
+            self.available.notify_all();
+        }
+    }
+
+    /// Use 'contents' as all of the input, read a line at a time and then ended
+    pub fn set_contents(&self, contents: &str) {
+        if let Ok(mut buffered) = self.buffered.lock() {
+            buffered.lines = contents.lines().map(String::from).collect();
+            buffered.eof = true;
+            self.available.notify_all();
+        }
+    }
+
+    /// Add a line to the input, unless its end has been sent
+    pub fn send_line(&self, line: &str) -> Result<(), String> {
+        let mut buffered = self.buffered.lock().map_err(|_| "Could not access STDIN")?;
+        if buffered.eof {
+            return Err("The end of STDIN has already been sent".into());
+        }
+        buffered.lines.push_back(line.to_string());
+        self.available.notify_all();
+        Ok(())
+    }
+
+    /// Send the end of the input, after the lines already sent
+    pub fn send_eof(&self) {
+        if let Ok(mut buffered) = self.buffered.lock() {
+            buffered.eof = true;
+            self.available.notify_all();
+        }
+    }
+
+    /// Is there input, or its end, for a reader without waiting
+    pub fn is_available(&self) -> bool {
+        self.buffered.lock().map_or(true, |buffered| buffered.eof || !buffered.lines.is_empty())
+    }
+
+    /// The next line of input, waiting for one to be sent if there is none yet. None at the end
+    /// of the input.
+    pub fn get_line(&self) -> Option<String> {
+        let mut buffered = self.wait()?;
+        buffered.lines.pop_front()
+    }
+
+    /// All the input buffered, with a newline after each line, waiting for some to be sent if
+    /// there is none yet. None at the end of the input.
+    pub fn get_buffered(&self) -> Option<String> {
+        let mut buffered = self.wait()?;
+        if buffered.lines.is_empty() {
+            return None;
+        }
+        Some(buffered.lines.drain(..).map(|line| format!("{}\n", line)).collect())
+    }
+
+    // Wait until there is a line of input or its end has been sent, or the input is reset for
+    // another run, when there is none for this one
+    fn wait(&self) -> Option<MutexGuard<Buffered>> {
+        let mut buffered = self.buffered.lock().ok()?;
+        let run = buffered.run;
+        while buffered.lines.is_empty() && !buffered.eof {
+            buffered = self.available.wait(buffered).ok()?;
+            if buffered.run != run {
+                return None;
+            }
+        }
+        Some(buffered)
+    }
+}
@@ -0,0 +1,409 @@
+/// The formats a flow definition can be written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl SourceFormat {
+    /// Determine the format of a definition from the extension of its url, defaulting to toml
+    pub fn from_url(url: &str) -> Self {
+        let extension = url.rsplit('.').next().unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "json" => SourceFormat::Json,
+            "yaml" | "yml" => SourceFormat::Yaml,
+            _ => SourceFormat::Toml
+        }
+    }
+
+    /// The extension of the file name of a definition in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SourceFormat::Toml => "toml",
+            SourceFormat::Json => "json",
+            SourceFormat::Yaml => "yaml",
+        }
+    }
+}
+
+/// The kinds of text that are highlighted differently in a definition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Comment,
+    Key,
+    String,
+    Number,
+    Keyword,
+    Table,
+}
+
+pub const TOKEN_KINDS: &[TokenKind] = &[TokenKind::Comment, TokenKind::Key, TokenKind::String,
+    TokenKind::Number, TokenKind::Keyword, TokenKind::Table];
+
+impl TokenKind {
+    /// The name of the text tag used to highlight this kind of token
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TokenKind::Comment => "comment",
+            TokenKind::Key => "key",
+            TokenKind::String => "string",
+            TokenKind::Number => "number",
+            TokenKind::Keyword => "keyword",
+            TokenKind::Table => "table",
+        }
+    }
+}
+
+/// A highlighted range of the text, in characters (not bytes) as used by gtk text buffers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A problem found in a definition, covering the characters from 'start' to 'end'
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+impl Problem {
+    /// A problem at a line and column (both starting at 1) as reported by the deserializers,
+    /// covering the rest of the word or line it starts in
+    pub fn at_line_column(text: &str, line: usize, column: usize, message: String) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut start = 0;
+        for _ in 1..line {
+            match chars[start..].iter().position(|c| *c == '\n') {
+                Some(newline) => start += newline + 1,
+                None => break
+            }
+        }
+        start = (start + column.saturating_sub(1)).min(chars.len());
+
+        // A problem at the end of a line is shown on the last character before it
+        if start > 0 && (start == chars.len() || chars[start] == '\n') {
+            start -= 1;
+        }
+        let end = start + chars[start..].iter()
+            .position(|c| c.is_whitespace()).unwrap_or(chars.len() - start).max(1);
+
+        Problem { start, end: end.min(chars.len()), message }
+    }
+
+    /// A problem with the first occurrence of 'found' in the text, preferring one that is quoted.
+    /// If it cannot be found the problem is shown on the first line.
+    pub fn at_text(text: &str, found: &str, message: String) -> Self {
+        let quoted = format!("\"{}\"", found);
+        let byte_position = text.find(&quoted).map(|position| position + 1)
+            .or_else(|| if found.is_empty() { None } else { text.find(found) });
+
+        match byte_position {
+            Some(byte_start) => {
+                let start = text[..byte_start].chars().count();
+                Problem { start, end: start + found.chars().count(), message }
+            }
+            None => Problem::at_line_column(text, 1, 1, message)
+        }
+    }
+
+    /// A problem with the text from byte 'start' to byte 'end', shown on at least one character
+    pub fn at_range(text: &str, start: usize, end: usize, message: String) -> Self {
+        let start_char = text[..start].chars().count();
+        let length = text[start..end].chars().count().max(1);
+        Problem { start: start_char, end: (start_char + length).min(text.chars().count()), message }
+    }
+
+    /// A problem described by a deserializer error message. Messages from the toml, json and yaml
+    /// parsers include the position as "line X column Y"
+    pub fn from_error(text: &str, message: String) -> Self {
+        let (line, column) = error_position(&message).unwrap_or((1, 1));
+        Problem::at_line_column(text, line, column, message)
+    }
+}
+
+// Find the "line X column Y" (or "line X, column Y") position in an error message
+fn error_position(message: &str) -> Option<(usize, usize)> {
+    let words: Vec<&str> = message.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let number = |word: &str| word.trim_end_matches(|c: char| !c.is_ascii_digit()).parse::<usize>().ok();
+
+    words.windows(4).find_map(|window| {
+        if window[0] == "line" && window[2] == "column" {
+            Some((number(window[1])?, number(window[3])?))
+        } else {
+            None
+        }
+    })
+}
+
+/// Find the tokens to highlight in the text of a definition in 'format'
+pub fn tokens(format: SourceFormat, text: &str) -> Vec<Token> {
+    let mut scanner = Scanner { chars: text.chars().collect(), position: 0, tokens: vec!() };
+    match format {
+        SourceFormat::Toml => scanner.toml(),
+        SourceFormat::Json => scanner.json(),
+        SourceFormat::Yaml => scanner.yaml(),
+    }
+    scanner.tokens
+}
+
+struct Scanner {
+    chars: Vec<char>,
+    position: usize,
+    tokens: Vec<Token>,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        if self.position > start {
+            self.tokens.push(Token { kind, start, end: self.position });
+        }
+    }
+
+    fn skip_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while self.peek().map_or(false, |c| predicate(c)) {
+            self.position += 1;
+        }
+    }
+
+    // Skip over spaces and tabs but not the end of the line
+    fn skip_blanks(&mut self) {
+        self.skip_while(|c| c == ' ' || c == '\t');
+    }
+
+    fn comment(&mut self) {
+        let start = self.position;
+        self.skip_while(|c| c != '\n');
+        self.push(TokenKind::Comment, start);
+    }
+
+    // A string delimited by 'quote', with backslash escapes unless it is a literal string
+    fn string(&mut self, kind: TokenKind, quote: char, escapes: bool) {
+        let start = self.position;
+        self.position += 1;
+        while let Some(c) = self.peek() {
+            self.position += 1;
+            if c == quote {
+                break;
+            } else if c == '\\' && escapes {
+                self.position += 1;
+            } else if c == '\n' {
+                break;
+            }
+        }
+        self.position = self.position.min(self.chars.len());
+        self.push(kind, start);
+    }
+
+    // A bare word that ends at a delimiter, highlighted if it is a number or a keyword
+    fn value(&mut self, keywords: &[&str]) {
+        let start = self.position;
+        self.skip_while(|c| !c.is_whitespace() && !",[]{}#".contains(c));
+        let word: String = self.chars[start..self.position].iter().collect();
+        if keywords.contains(&word.as_str()) {
+            self.push(TokenKind::Keyword, start);
+        } else if word.parse::<f64>().is_ok() || word.replace('_', "").parse::<i64>().is_ok() {
+            self.push(TokenKind::Number, start);
+        } else if self.position == start {
+            self.position += 1;
+        }
+    }
+
+    fn toml(&mut self) {
+        let mut line_start = true;
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' => {
+                    line_start = true;
+                    self.position += 1;
+                    continue;
+                }
+                ' ' | '\t' => {
+                    self.position += 1;
+                    continue;
+                }
+                '#' => self.comment(),
+                '[' if line_start => {
+                    let start = self.position;
+                    self.skip_while(|c| c != '\n' && c != '#');
+                    self.push(TokenKind::Table, start);
+                }
+                '"' => self.string(TokenKind::String, '"', true),
+                '\'' => self.string(TokenKind::String, '\'', false),
+                _ if line_start => {
+                    let start = self.position;
+                    self.skip_while(|c| c != '=' && c != '\n' && c != '#');
+                    // don't include the space between the key and the '='
+                    let end = self.position;
+                    self.position = start + self.chars[start..end].iter()
+                        .rposition(|c| !c.is_whitespace()).map_or(0, |last| last + 1);
+                    self.push(TokenKind::Key, start);
+                    self.position = end;
+                    if self.peek() == Some('=') {
+                        self.position += 1;
+                    }
+                }
+                _ => self.value(&["true", "false"]),
+            }
+            line_start = false;
+        }
+    }
+
+    fn json(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.string(TokenKind::String, '"', true);
+                    // a string followed by a ':' is the key of a member of an object
+                    let after = self.position;
+                    self.skip_while(char::is_whitespace);
+                    if self.peek() == Some(':') {
+                        if let Some(token) = self.tokens.last_mut() {
+                            token.kind = TokenKind::Key;
+                        }
+                    }
+                    self.position = after;
+                }
+                '-' | '0'..='9' | 't' | 'f' | 'n' => self.value(&["true", "false", "null"]),
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    fn yaml(&mut self) {
+        while self.peek().is_some() {
+            // Each line may start with indentation and list item markers, followed by a key
+            self.skip_blanks();
+            while self.peek() == Some('-') && self.chars.get(self.position + 1).map_or(true, |c| c.is_whitespace()) {
+                self.position += 1;
+                self.skip_blanks();
+            }
+
+            if let Some(colon) = self.key_end() {
+                let start = self.position;
+                self.position = colon;
+                self.push(TokenKind::Key, start);
+                self.position += 1;
+            }
+
+            while let Some(c) = self.peek() {
+                match c {
+                    '\n' => {
+                        self.position += 1;
+                        break;
+                    }
+                    ' ' | '\t' => self.position += 1,
+                    '#' => self.comment(),
+                    '"' => self.string(TokenKind::String, '"', true),
+                    '\'' => self.string(TokenKind::String, '\'', false),
+                    _ => self.value(&["true", "false", "null", "~"]),
+                }
+            }
+        }
+    }
+
+    // If the line continues with "key:" find the position of the ':'
+    fn key_end(&self) -> Option<usize> {
+        let mut position = self.position;
+        while let Some(c) = self.chars.get(position) {
+            match c {
+                ':' if position > self.position => {
+                    let next = self.chars.get(position + 1);
+                    return if next.map_or(true, |c| c.is_whitespace()) { Some(position) } else { None };
+                }
+                '\n' | '#' | '"' | '\'' | '{' | '[' => return None,
+                _ => position += 1
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{error_position, tokens, Problem, SourceFormat, Token, TokenKind};
+
+    // Multi-byte characters, so positions in characters and bytes differ
+    const TEXT: &str = "naïve = \"é\"\nkey = 1\n";
+
+    fn problem(start: usize, end: usize) -> Problem {
+        Problem { start, end, message: "problem".into() }
+    }
+
+    #[test]
+    fn line_and_column_counted_in_characters() {
+        assert_eq!(Problem::at_line_column(TEXT, 1, 1, "problem".into()), problem(0, 5));
+        assert_eq!(Problem::at_line_column(TEXT, 1, 10, "problem".into()), problem(9, 11));
+        assert_eq!(Problem::at_line_column(TEXT, 2, 7, "problem".into()), problem(18, 19));
+    }
+
+    #[test]
+    fn problem_at_the_end_of_a_line_shown_before_it() {
+        assert_eq!(Problem::at_line_column(TEXT, 1, 12, "problem".into()), problem(10, 11));
+    }
+
+    #[test]
+    fn problem_past_the_end_shown_on_the_last_character() {
+        assert_eq!(Problem::at_line_column(TEXT, 5, 1, "problem".into()), problem(19, 20));
+        assert_eq!(Problem::at_line_column(TEXT, 2, 50, "problem".into()), problem(19, 20));
+        assert_eq!(Problem::at_line_column("", 1, 1, "problem".into()), problem(0, 0));
+    }
+
+    #[test]
+    fn position_found_in_error_messages() {
+        assert_eq!(error_position("expected a value at line 3 column 5"), Some((3, 5)));
+        assert_eq!(error_position("invalid type at line 2, column 7"), Some((2, 7)));
+        assert_eq!(error_position("did not find expected key at line 4 column 10, while parsing a block mapping"), Some((4, 10)));
+    }
+
+    #[test]
+    fn no_position_in_other_messages() {
+        assert_eq!(error_position("missing field `flow`"), None);
+        assert_eq!(error_position("at line x column 2"), None);
+    }
+
+    #[test]
+    fn problem_from_error_at_its_position() {
+        assert_eq!(Problem::from_error(TEXT, "expected newline at line 2 column 5".into()),
+                   Problem { start: 16, end: 17, message: "expected newline at line 2 column 5".into() });
+        assert_eq!(Problem::from_error(TEXT, "missing field".into()).start, 0);
+    }
+
+    #[test]
+    fn quoted_text_found_in_characters() {
+        assert_eq!(Problem::at_text(TEXT, "é", "problem".into()), problem(9, 10));
+        assert_eq!(Problem::at_text(TEXT, "key", "problem".into()), problem(12, 15));
+        assert_eq!(Problem::at_text(TEXT, "missing", "problem".into()), problem(0, 5));
+    }
+
+    #[test]
+    fn byte_range_converted_to_characters() {
+        assert_eq!(Problem::at_range(TEXT, 14, 17, "problem".into()), problem(12, 15));
+        assert_eq!(Problem::at_range(TEXT, 14, 14, "problem".into()), problem(12, 13));
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(SourceFormat::from_url("file:///flows/root.json"), SourceFormat::Json);
+        assert_eq!(SourceFormat::from_url("file:///flows/root.YML"), SourceFormat::Yaml);
+        assert_eq!(SourceFormat::from_url("file:///flows/root"), SourceFormat::Toml);
+    }
+
+    #[test]
+    fn tokens_in_characters() {
+        assert_eq!(tokens(SourceFormat::Toml, "naïve = \"é\" # ü"), vec!(
+            Token { kind: TokenKind::Key, start: 0, end: 5 },
+            Token { kind: TokenKind::String, start: 8, end: 11 },
+            Token { kind: TokenKind::Comment, start: 12, end: 15 }));
+    }
+}
@@ -0,0 +1,135 @@
+/// The templates a new flow can be created from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Template {
+    Empty,
+    StdinToStdout,
+    ArgsDriven,
+    LibrarySkeleton,
+}
+
+pub const TEMPLATES: &[Template] = &[Template::Empty, Template::StdinToStdout,
+    Template::ArgsDriven, Template::LibrarySkeleton];
+
+impl Template {
+    /// An identifier for the template, used in the UI
+    pub fn id(&self) -> &'static str {
+        match self {
+            Template::Empty => "empty",
+            Template::StdinToStdout => "stdin-to-stdout",
+            Template::ArgsDriven => "args",
+            Template::LibrarySkeleton => "library",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Template::Empty => "Empty flow",
+            Template::StdinToStdout => "Pipeline from STDIN to STDOUT",
+            Template::ArgsDriven => "Flow driven by its command line arguments",
+            Template::LibrarySkeleton => "Library flow with an input and an output, for use as a sub-flow",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Template> {
+        TEMPLATES.iter().find(|template| template.id() == id).cloned()
+    }
+
+    /// The contents of the flow definition (in toml) for a new flow called 'name'
+    pub fn definition(&self, name: &str) -> String {
+        let header = format!("flow = \"{}\"\n", name.replace('\\', "\\\\").replace('"', "\\\""));
+        let body = match self {
+            Template::Empty => "",
+            Template::StdinToStdout => STDIN_TO_STDOUT,
+            Template::ArgsDriven => ARGS_DRIVEN,
+            Template::LibrarySkeleton => LIBRARY_SKELETON,
+        };
+        format!("{}{}", header, body)
+    }
+}
+
+const STDIN_TO_STDOUT: &str = "
+[[process]]
+alias = \"stdin\"
+source = \"lib://flowruntime/stdio/stdin.toml\"
+
+[[process]]
+alias = \"stdout\"
+source = \"lib://flowruntime/stdio/stdout.toml\"
+
+[[connection]]
+from = \"stdin/text\"
+to = \"stdout\"
+";
+
+const ARGS_DRIVEN: &str = "
+[[process]]
+alias = \"args\"
+source = \"lib://flowruntime/args/get.toml\"
+
+[[process]]
+alias = \"stdout\"
+source = \"lib://flowruntime/stdio/stdout.toml\"
+
+# The first argument after the flow name
+[[connection]]
+from = \"args/string/1\"
+to = \"stdout\"
+";
+
+const LIBRARY_SKELETON: &str = "
+[[input]]
+name = \"input\"
+type = \"String\"
+
+[[output]]
+name = \"output\"
+type = \"String\"
+
+# Replace with the processes that transform the input into the output
+[[process]]
+alias = \"transform\"
+source = \"lib://flowstdlib/data/buffer\"
+
+[[connection]]
+from = \"input/input\"
+to = \"transform\"
+
+[[connection]]
+from = \"transform\"
+to = \"output/output\"
+";
+
+#[cfg(test)]
+mod test {
+    use super::TEMPLATES;
+
+    // The names of the entries of the array of tables 'key' in a definition, found by 'field'
+    fn names(definition: &toml::Value, key: &str, field: &str) -> Vec<String> {
+        definition.get(key).and_then(toml::Value::as_array).into_iter().flatten()
+            .filter_map(|entry| entry.get(field).and_then(toml::Value::as_str).map(String::from))
+            .collect()
+    }
+
+    #[test]
+    fn templates_connect_what_they_define() {
+        for template in TEMPLATES {
+            let definition: toml::Value = toml::from_str(&template.definition("new \"flow\"")).unwrap();
+            assert_eq!(definition.get("flow").and_then(toml::Value::as_str), Some("new \"flow\""));
+
+            let aliases = names(&definition, "process", "alias");
+            let inputs = names(&definition, "input", "name");
+            let outputs = names(&definition, "output", "name");
+            for (from, to) in names(&definition, "connection", "from").iter().zip(names(&definition, "connection", "to")) {
+                for &(route, ios, direction) in &[(from.as_str(), &inputs, "input"), (to.as_str(), &outputs, "output")] {
+                    let segments: Vec<&str> = route.split('/').collect();
+                    let defined = if segments[0] == direction {
+                        segments.len() == 2 && ios.iter().any(|name| name == segments[1])
+                    } else {
+                        aliases.iter().any(|alias| alias == segments[0])
+                    };
+                    assert!(defined, "'{}' in template '{}' is not defined", route, template.id());
+                }
+            }
+        }
+    }
+}
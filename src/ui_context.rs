@@ -1,19 +1,38 @@
-use gtk::{ButtonsType, DialogFlags, MessageDialog, MessageType};
+use gtk::{ButtonsType, Dialog, DialogFlags, MessageDialog, MessageType, ResponseType, ScrolledWindow, TextTag, TextView};
 use gtk::prelude::*;
 
 use flowclib::model::flow::Flow;
 use flowrlib::loader::Loader;
 use flowrstructs::manifest::Manifest;
+use provider::content::provider::{MetaProvider, Provider};
+use url::Url;
 
-use crate::build_ui::{widgets, MANIFEST_PAGE, FLOW_GRAPH_PAGE};
+use crate::{actions, image_window, lint, palette, problems};
+use crate::build_ui::{widgets, BUILD_PAGE, COMPILE_ERRORS_PAGE, MANIFEST_PAGE, FLOW_GRAPH_PAGE, STDIN_PAGE};
 use crate::build_ui::widgets::WidgetRefs;
+use crate::flow_graph::FlowGraph;
+use crate::completion::Completion;
+use crate::diff::{self, LineChange};
+use crate::history::{Change, FileChange, History, MovedPositions};
+use crate::layout_file::Positions;
+use crate::compile_errors::CompileError;
+use crate::problems::Entry;
+use crate::syntax::Problem;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+// Size of the dialog showing the changes that will be made to definitions
+const PREVIEW_WIDTH: i32 = 640;
+const PREVIEW_HEIGHT: i32 = 420;
+// The pango weight of bold text
+const BOLD: i32 = 700;
+
 pub struct UiContext {
     pub loader: Option<Loader>,
     pub flow: Option<Flow>,
     pub manifest: Option<Manifest>,
     pub manifest_url: Option<String>,
+    pub history: History,
 }
 
 impl UiContext {
@@ -23,70 +42,242 @@ impl UiContext {
             flow: None,
             manifest: None,
             manifest_url: None,
+            history: History::default(),
         }
     }
 
     // Set the flow url and flow object into the `UIContext` for later use
     pub fn set_flow(&mut self, flow: Option<Flow>) {
         self.flow = flow;
+        // Reloading the same flow keeps the changes made to it so they can still be undone
+        self.history.set_root(self.flow.as_ref().map(|flow| flow.source_url.as_str()));
+        self.update_history_menus();
 
         if let Some(flow_loaded) = &self.flow {
             UiContext::message(&format!("Flow loaded from '{:?}'", flow_loaded.source_url));
         }
 
-        // Serialize the flow into toml for ui display - or clear if None
+        // Show the flow's definition in the source editor - or clear if None
         match &self.flow {
             Some(flow_found) => {
+                // Only flows loaded from files can be saved back to where they came from
+                let editable = flow_found.source_url.starts_with("file:");
+                let flow_url = flow_found.source_url.clone();
+
                 // enable menu item that can be used to compile the loaded flow
                 // clear contents of manifest and other widgets
-                widgets::do_in_gtk_eventloop(|refs| {
+                widgets::do_in_gtk_eventloop(move |refs| {
+                    refs.save_flow_menu().set_sensitive(editable);
+                    refs.save_flow_as_menu().set_sensitive(true);
+                    refs.export_graph_menu().set_sensitive(true);
                     refs.compile_flow_menu().set_sensitive(true);
+                    refs.compile_options_menu().set_sensitive(editable);
+                    palette::show_flow_files(refs.flow_files(), Some(&flow_url));
                     Self::clear_manifest_contents(&refs);
                     Self::clear_stdout(&refs);
                     Self::clear_stderr(&refs);
                 });
 
                 Self::set_flow_graph_contents(flow_found);
+                let problems = lint::check(flow_found).iter()
+                    .map(|lint| Entry::from_lint(&flow_found.name.to_string(), lint))
+                    .collect();
+                Self::set_problems(problems);
 
-                // TODO also serialize to toml
-                // but it looks like their is an ambiguity as it reports an error
-                // see https://stackoverflow.com/questions/57560593/why-do-i-get-an-unsupportedtype-error-when-serializing-to-toml-with-a-manually-i
-                match serde_json::to_string_pretty(&flow_found) {
-                    Ok(flow_content) => self.set_flow_json_contents(Some(flow_content)),
+                // Show the definition as written by the user, not a re-serialization of it
+                let url = flow_found.source_url.clone();
+                let provider = MetaProvider::new(actions::get_lib_search_path());
+                match provider.get_contents(&url) {
+                    Ok(contents) => Self::set_flow_source_contents(
+                        Some((url, String::from_utf8_lossy(&contents).to_string())), editable),
                     Err(e) => {
-                        UiContext::ui_error(&format!("Error serializing flow to toml: `{}`", &e.to_string()));
-                        self.set_flow_json_contents(None);
+                        UiContext::ui_error(&format!("Could not read flow definition from '{}': {}", url, e));
+                        Self::set_flow_source_contents(None, false);
                     }
                 }
             }
             None => {
                 // disable menu item that can be used to compile the loaded flow
                 widgets::do_in_gtk_eventloop(|refs| {
+                    refs.save_flow_menu().set_sensitive(false);
+                    refs.save_flow_as_menu().set_sensitive(false);
+                    refs.export_graph_menu().set_sensitive(false);
                     refs.compile_flow_menu().set_sensitive(false);
+                    refs.compile_options_menu().set_sensitive(false);
+                    palette::show_flow_files(refs.flow_files(), None);
                     Self::clear_flow_graph_contents(&refs);
+                    Self::show_problems(&refs, &[]);
                 });
 
-                self.set_flow_json_contents(None);
+                Self::set_flow_source_contents(None, false);
             }
         };
     }
 
-    fn set_flow_graph_contents(_graph: &Flow) {
-        widgets::do_in_gtk_eventloop(|_refs| {
-                    // TODO
+    // Create the graph of the flow and show it in the flow graph view
+    fn set_flow_graph_contents(flow: &Flow) {
+        let graph = FlowGraph::from_flow(flow);
+        widgets::do_in_gtk_eventloop(|refs| {
+            refs.flow_graph_view().set_graph(Some(graph));
         });
     }
 
-    // Show the text representing the flow in toml, or clear the text widget
-    fn set_flow_json_contents(&mut self, content: Option<String>) {
-        widgets::do_in_gtk_eventloop(|refs| {
+    // Show the problems found in the flow, which is checked each time it is loaded or changed
+    fn set_problems(problems: Vec<Entry>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            Self::show_problems(&refs, &problems);
+        });
+    }
+
+    fn show_problems(refs: &Rc<WidgetRefs>, entries: &[Entry]) {
+        problems::show_problems(refs.problems(), entries);
+        refs.problems_label().set_text(&Self::count_label("Problems", entries.len()));
+    }
+
+    // The label of a tab listing 'count' things, which says how many there are
+    fn count_label(title: &str, count: usize) -> String {
+        if count == 0 {
+            title.to_string()
+        } else {
+            format!("{} ({})", title, count)
+        }
+    }
+
+    /// Show the errors from compiling the flow in the compile errors list, where they stay until
+    /// it is next compiled. The list is shown if there are any.
+    pub fn show_compile_errors(errors: Vec<CompileError>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            let entries: Vec<Entry> = errors.iter().map(Entry::from_compile_error).collect();
+            problems::show_problems(refs.compile_errors(), &entries);
+            refs.compile_errors_label().set_text(&Self::count_label("Compile Errors", entries.len()));
+            if !entries.is_empty() {
+                refs.output_notebook().set_property_page(COMPILE_ERRORS_PAGE);
+            }
+        });
+    }
+
+    /// Clear the output of building the supplied implementations, before they are built again
+    pub fn clear_build_output() {
+        widgets::do_in_gtk_eventloop(|refs| refs.build_console().clear());
+    }
+
+    /// Show that the implementation of 'name' is being built in 'directory', in the build output
+    /// that is shown while it is built
+    pub fn build_started(name: String, directory: PathBuf) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            let heading = format!("Building implementation of '{}' in '{}'", name, directory.display());
+            refs.build_console().start_build(&heading, directory);
+            refs.output_notebook().set_property_page(BUILD_PAGE);
+        });
+    }
+
+    /// Add a line written by the build of an implementation to the build output
+    pub fn build_output(line: String) {
+        widgets::do_in_gtk_eventloop(move |refs| refs.build_console().append(&line));
+    }
+
+    // Show the text of the flow's definition and the url it was loaded from, or clear the editor.
+    // Either way there are no unsaved changes in the editor afterwards
+    fn set_flow_source_contents(content: Option<(String, String)>, editable: bool) {
+        widgets::do_in_gtk_eventloop(move |refs| {
             match content {
-                Some(text) => {
-                    refs.flow_buffer().set_text(&text);
+                Some((url, text)) => {
+                    refs.flow_source_editor().set_source(Some(url), Some(&text), editable);
                     // Select flow graph view tab when new flow loaded
                     refs.flow_notebook().set_property_page(FLOW_GRAPH_PAGE);
                 },
-                None => Self::clear_flow_json_contents(&refs)
+                None => refs.flow_source_editor().set_source(None, None, false)
+            }
+        });
+    }
+
+    // Record a change made to the flow's definitions so that it can be undone
+    pub fn record_change(&mut self, change: Change) {
+        self.history.record(change);
+        self.update_history_menus();
+    }
+
+    // Put back a change that has just been undone (or redone) so it can be redone (or undone)
+    pub fn history_stepped(&mut self, undone: bool, change: Change) {
+        if undone {
+            self.history.undone(change);
+        } else {
+            self.history.redone(change);
+        }
+        self.update_history_menus();
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.update_history_menus();
+    }
+
+    // Enable the undo and redo menu items if there is a change to undo or redo, and say what it is
+    fn update_history_menus(&self) {
+        let undo = self.history.next_undo().map(|description| format!("Undo {}", description));
+        let redo = self.history.next_redo().map(|description| format!("Redo {}", description));
+        widgets::do_in_gtk_eventloop(move |refs| {
+            refs.undo_menu().set_sensitive(undo.is_some());
+            refs.undo_menu().set_label(undo.as_deref().unwrap_or("Undo"));
+            refs.redo_menu().set_sensitive(redo.is_some());
+            refs.redo_menu().set_label(redo.as_deref().unwrap_or("Redo"));
+        });
+    }
+
+    // Set the positions of the nodes moved by the user in the flow at 'root_url', before it is shown
+    pub fn set_node_positions(root_url: String, positions: Positions) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            refs.flow_graph_view().set_positions(&root_url, positions);
+        });
+    }
+
+    // Move the positions of the nodes of processes renamed by a change made, or undone
+    pub fn move_node_positions(moved: Vec<MovedPositions>) {
+        if moved.is_empty() {
+            return;
+        }
+        widgets::do_in_gtk_eventloop(move |refs| {
+            refs.flow_graph_view().move_positions(&moved);
+        });
+    }
+
+    // Underline the problems found in the flow definition being edited, if it has not changed since
+    // it was checked, and summarize them in the status bar
+    pub fn show_source_problems(generation: u64, problems: Vec<Problem>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            match refs.flow_source_editor().show_problems(generation, problems) {
+                Some(0) | None => {}
+                Some(1) => refs.status_message().set_label("1 problem found in the flow definition"),
+                Some(count) => refs.status_message()
+                    .set_label(&format!("{} problems found in the flow definition", count)),
+            }
+        });
+    }
+
+    // Offer the completions of the 'typed' start of a value in the flow definition being edited
+    pub fn show_completions(generation: u64, typed: String, completions: Vec<Completion>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            refs.flow_source_editor().show_completions(generation, &typed, completions);
+        });
+    }
+
+    /// Show the progress of compiling the flow as the fraction done and what is being done, with
+    /// the button to cancel it, or hide them when None
+    pub fn compile_progress(progress: Option<(f64, String)>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            match progress {
+                Some((fraction, stage)) => {
+                    refs.compile_progress().set_fraction(fraction);
+                    refs.compile_progress().set_text(Some(&stage));
+                    refs.compile_progress().show();
+                    refs.cancel_compile().show();
+                    refs.compile_flow_menu().set_sensitive(false);
+                }
+                None => {
+                    refs.compile_progress().hide();
+                    refs.cancel_compile().hide();
+                    refs.compile_flow_menu().set_sensitive(true);
+                }
             }
         });
     }
@@ -147,14 +338,8 @@ impl UiContext {
         });
     }
 
-    fn clear_flow_graph_contents(_refs: &Rc<WidgetRefs>) {
-        // let (mut start, mut end) = refs.flow_buffer().get_bounds();
-        // refs.flow_buffer().delete(&mut start, &mut end);
-    }
-
-    fn clear_flow_json_contents(refs: &Rc<WidgetRefs>) {
-        let (mut start, mut end) = refs.flow_buffer().get_bounds();
-        refs.flow_buffer().delete(&mut start, &mut end);
+    fn clear_flow_graph_contents(refs: &Rc<WidgetRefs>) {
+        refs.flow_graph_view().set_graph(None);
     }
 
     fn clear_manifest_contents(refs: &Rc<WidgetRefs>) {
@@ -176,6 +361,117 @@ impl UiContext {
         widgets::do_in_gtk_eventloop(|refs| {
             Self::clear_stdout(&refs);
             Self::clear_stderr(&refs);
+            Self::clear_stdin(&refs);
+            image_window::close_all();
+        });
+    }
+
+    // Clear the input sent to STDIN by a previous run, and allow input to be sent again
+    fn clear_stdin(refs: &Rc<WidgetRefs>) {
+        refs.stdin().set_text("");
+        refs.stdin_status().set_text("");
+        refs.stdin_entry().set_sensitive(true);
+        refs.send_stdin().set_sensitive(true);
+        refs.send_eof().set_sensitive(true);
+    }
+
+    /// Show a line sent to STDIN of the running flow, or that the end of its input was sent when
+    /// None, after which no more can be sent
+    pub fn stdin_sent(line: Option<String>) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            let mut end = refs.stdin().get_end_iter();
+            match line {
+                Some(line) => refs.stdin().insert(&mut end, &format!("{}\n", line)),
+                None => {
+                    refs.stdin().insert(&mut end, "(end of input)\n");
+                    refs.stdin_entry().set_sensitive(false);
+                    refs.send_stdin().set_sensitive(false);
+                    refs.send_eof().set_sensitive(false);
+                }
+            }
+        });
+    }
+
+    /// Show that the input to STDIN of the running flow is read from the file at 'path', so no
+    /// more can be sent
+    pub fn stdin_from_file(path: PathBuf) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            refs.stdin().set_text(&format!("(input read from '{}')\n", path.display()));
+            refs.stdin_entry().set_sensitive(false);
+            refs.send_stdin().set_sensitive(false);
+            refs.send_eof().set_sensitive(false);
+        });
+    }
+
+    /// Show whether the running flow is waiting for input to be sent to STDIN, switching to where
+    /// it is entered when it is
+    pub fn waiting_for_stdin(waiting: bool) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            if waiting {
+                refs.stdin_status().set_text("The flow is waiting for input");
+                refs.output_notebook().set_property_page(STDIN_PAGE);
+                refs.stdin_entry().grab_focus();
+            } else {
+                refs.stdin_status().set_text("");
+            }
+        });
+    }
+
+    // Show the changes that will be made to the flow's definitions in a dialog, and make them if the
+    // user applies them
+    pub fn preview_change(change: Change) {
+        widgets::do_in_gtk_eventloop(move |refs| {
+            let dialog = Dialog::with_buttons(Some(&change.description), Some(&refs.app_window()), DialogFlags::MODAL,
+                                              &[("Apply", ResponseType::Ok), ("Cancel", ResponseType::Cancel)]);
+
+            let view = TextView::new();
+            view.set_editable(false);
+            view.set_monospace(true);
+            let buffer = view.get_buffer().unwrap();
+            let file = TextTag::new(Some("file"));
+            file.set_property_weight(BOLD);
+            let added = TextTag::new(Some("added"));
+            added.set_property_background(Some("#d9f2d9"));
+            let removed = TextTag::new(Some("removed"));
+            removed.set_property_background(Some("#f7d4d4"));
+            let tag_table = buffer.get_tag_table().unwrap();
+            tag_table.add(&file);
+            tag_table.add(&added);
+            tag_table.add(&removed);
+
+            // Each definition changed is headed by its path when more than one is changed
+            let changed: Vec<&FileChange> = change.files.iter().filter(|file| file.before != file.after).collect();
+            for file_change in &changed {
+                if changed.len() > 1 {
+                    let path = Url::parse(&file_change.url).ok().and_then(|url| url.to_file_path().ok())
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|| file_change.url.clone());
+                    buffer.insert_with_tags_by_name(&mut buffer.get_end_iter(), &format!("{}\n", path), &["file"]);
+                }
+
+                for (line_change, line) in diff::line_diff(&file_change.before, &file_change.after) {
+                    let (prefix, tags): (&str, &[&str]) = match line_change {
+                        LineChange::Same => ("  ", &[]),
+                        LineChange::Added => ("+ ", &["added"]),
+                        LineChange::Removed => ("- ", &["removed"]),
+                    };
+                    buffer.insert_with_tags_by_name(&mut buffer.get_end_iter(), &format!("{}{}\n", prefix, line), tags);
+                }
+                buffer.insert(&mut buffer.get_end_iter(), "\n");
+            }
+
+            let scroll = ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+            scroll.set_min_content_width(PREVIEW_WIDTH);
+            scroll.set_min_content_height(PREVIEW_HEIGHT);
+            scroll.add(&view);
+            dialog.get_content_area().pack_start(&scroll, true, true, 0);
+            dialog.show_all();
+
+            let response = dialog.run();
+            unsafe { dialog.destroy(); }
+            if response == ResponseType::Ok {
+                actions::apply_change(change.clone());
+            }
         });
     }
 
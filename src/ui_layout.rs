@@ -1,9 +1,12 @@
-use gtk::{Application, ApplicationWindow, Justification, ScrolledWindow, TextBuffer, WidgetExt, WindowPosition, Label, Notebook};
+use gtk::{Application, ApplicationWindow, Button, Entry, FileChooserAction, FileChooserButton, Justification, ListStore, ProgressBar, ScrolledWindow, TextBuffer, TreeView, WidgetExt, WindowPosition, Label, Notebook};
 use gtk::prelude::*;
 
-use crate::{menu, toolbar};
+use crate::{menu, palette, problems, toolbar};
 use crate::build_ui::widgets;
+use crate::build_console::BuildConsole;
 use crate::notebook;
+use crate::flow_graph_view::FlowGraphView;
+use crate::flow_source_editor::FlowSourceEditor;
 
 fn stdio() -> (ScrolledWindow, TextBuffer) {
     let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
@@ -14,7 +17,32 @@ fn stdio() -> (ScrolledWindow, TextBuffer) {
     (scroll, view.get_buffer().unwrap())
 }
 
-fn status_bar() -> (Label, gtk::Box) {
+// The input to send to STDIN of a running flow a line at a time, above the lines sent so far
+fn stdin_pane() -> (gtk::Box, TextBuffer, Entry, Button, Button, Label) {
+    let pane = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    let (sent_view, sent) = stdio();
+    pane.pack_start(&sent_view, true, true, 0);
+
+    let input_bar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let entry = Entry::new();
+    entry.set_placeholder_text(Some("A line to send to STDIN"));
+    input_bar.pack_start(&entry, true, true, 0);
+    let send = Button::with_label("Send");
+    send.set_tooltip_text(Some("Send the line to STDIN of the flow"));
+    input_bar.pack_start(&send, false, false, 0);
+    let send_eof = Button::with_label("Send EOF");
+    send_eof.set_tooltip_text(Some("End the input to STDIN of the flow"));
+    input_bar.pack_start(&send_eof, false, false, 0);
+    pane.pack_start(&input_bar, false, true, 0);
+
+    let status = Label::new(None);
+    status.set_xalign(0.0);
+    pane.pack_start(&status, false, true, 0);
+
+    (pane, sent, entry, send, send_eof, status)
+}
+
+fn status_bar() -> (Label, ProgressBar, Button, gtk::Box) {
     let status_bar = gtk::Box::new(gtk::Orientation::Horizontal, 0);
     status_bar.set_border_width(1);
     status_bar.set_margin_bottom(0);
@@ -28,10 +56,36 @@ fn status_bar() -> (Label, gtk::Box) {
     status_message.set_xalign(1.0);
     status_bar.pack_start(&status_message, true, true, 4);
 
-    (status_message, status_bar)
+    // The progress of a compile, with a button to cancel it, shown only while compiling
+    let compile_progress = ProgressBar::new();
+    compile_progress.set_show_text(true);
+    compile_progress.set_valign(gtk::Align::Center);
+    compile_progress.set_no_show_all(true);
+    status_bar.pack_start(&compile_progress, false, false, 4);
+    let cancel_compile = Button::with_label("Cancel");
+    cancel_compile.set_tooltip_text(Some("Cancel the compile"));
+    cancel_compile.set_no_show_all(true);
+    status_bar.pack_start(&cancel_compile, false, false, 4);
+
+    (status_message, compile_progress, cancel_compile, status_bar)
+}
+
+// The lists of problems found in the flow and of errors compiling it, each with the label of its tab
+fn problem_lists(notebook: &Notebook) -> ((TreeView, ListStore, Label), (TreeView, ListStore, Label)) {
+    let (problems_scroll, problems_view, problems) = problems::problems_list("Flow");
+    let problems_label = gtk::Label::new(Some("Problems"));
+    notebook.append_page(&problems_scroll, Some(&problems_label));
+
+    let (errors_scroll, errors_view, errors) = problems::problems_list("File");
+    let errors_label = gtk::Label::new(Some("Compile Errors"));
+    notebook.append_page(&errors_scroll, Some(&errors_label));
+
+    ((problems_view, problems, problems_label), (errors_view, errors, errors_label))
 }
 
-fn main_window() -> (gtk::Box, TextBuffer, TextBuffer, TextBuffer, Notebook, TextBuffer, TextBuffer) {
+fn main_window() -> (gtk::Box, TextBuffer, FileChooserButton, FlowGraphView, FlowSourceEditor, TextBuffer, Notebook, Notebook, TextBuffer,
+                     TextBuffer, (TextBuffer, Entry, Button, Button, Label), (TreeView, ListStore, Label),
+                     (TreeView, ListStore, Label), BuildConsole) {
     // Create the main window
     let main_window = gtk::Box::new(gtk::Orientation::Vertical, 4);
     main_window.set_border_width(3);
@@ -49,13 +103,26 @@ fn main_window() -> (gtk::Box, TextBuffer, TextBuffer, TextBuffer, Notebook, Tex
     // and a text view in it
     let args_view = gtk::TextView::new();
     args_bar.pack_start(&args_view, true, true, 0);
+    // and a file to read STDIN from, instead of entering it
+    let label = gtk::Label::new(Some("STDIN:"));
+    label.set_margin_start(10);
+    label.set_margin_end(10);
+    args_bar.pack_start(&label, false, true, 0);
+    let stdin_file = FileChooserButton::new("Choose a file to read STDIN from", FileChooserAction::Open);
+    stdin_file.set_tooltip_text(Some("A file to read STDIN of the flow from when it is run"));
+    args_bar.pack_start(&stdin_file, false, true, 0);
+    let clear_stdin_file = Button::with_label("Clear");
+    clear_stdin_file.set_tooltip_text(Some("Enter STDIN in the \"STDIN\" tab instead of reading it from a file"));
+    let chooser = stdin_file.clone();
+    clear_stdin_file.connect_clicked(move |_| chooser.unselect_all());
+    args_bar.pack_start(&clear_stdin_file, false, true, 0);
     main_window.pack_start(&args_bar, false, true, 0);
     let args_buffer = args_view.get_buffer().unwrap();
 
     let notebook_paned = gtk::Paned::new(gtk::Orientation::Vertical);
     // Notebook for flow and manifest content
     let mut flow_notebook = gtk::Notebook::new();
-    let (flow_buffer, manifest_buffer) = notebook::create_tabs(&mut flow_notebook);
+    let (flow_graph_view, flow_source_editor, manifest_buffer) = notebook::create_tabs(&mut flow_notebook);
     notebook_paned.add1(&flow_notebook);
 
     let notebook = gtk::Notebook::new();
@@ -65,11 +132,20 @@ fn main_window() -> (gtk::Box, TextBuffer, TextBuffer, TextBuffer, Notebook, Tex
     let (stderr_view, stderr_buffer) = stdio();
     let label = gtk::Label::new(Some("STDERR"));
     notebook.append_page(&stderr_view, Some(&label));
+    let (stdin_view, stdin_buffer, stdin_entry, send_stdin, send_eof, stdin_status) = stdin_pane();
+    let label = gtk::Label::new(Some("STDIN"));
+    notebook.append_page(&stdin_view, Some(&label));
+    let (problem_list, compile_error_list) = problem_lists(&notebook);
+    let build_console = BuildConsole::new();
+    let label = gtk::Label::new(Some("Build"));
+    notebook.append_page(build_console.widget(), Some(&label));
     notebook_paned.add2(&notebook);
 
     main_window.pack_start(&notebook_paned, true, true, 4);
 
-    (main_window, args_buffer, flow_buffer, manifest_buffer, flow_notebook, stdout_buffer, stderr_buffer)
+    (main_window, args_buffer, stdin_file, flow_graph_view, flow_source_editor, manifest_buffer, flow_notebook, notebook,
+     stdout_buffer, stderr_buffer, (stdin_buffer, stdin_entry, send_stdin, send_eof, stdin_status), problem_list, compile_error_list,
+     build_console)
 }
 
 pub fn create(application: &Application) -> widgets::WidgetRefs {
@@ -78,7 +154,8 @@ pub fn create(application: &Application) -> widgets::WidgetRefs {
     let v_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
 
     // Create menu bar
-    let (menu_bar, accelerator_group, compile_flow_menu, run_manifest_menu) = menu::menu_bar(&app_window);
+    let (menu_bar, accelerator_group, save_flow_menu, save_flow_as_menu, undo_menu, redo_menu,
+        export_graph_menu, compile_flow_menu, compile_options_menu, run_manifest_menu) = menu::menu_bar(&app_window);
     app_window.add_accel_group(&accelerator_group);
     v_box.pack_start(&menu_bar, false, false, 4);
 
@@ -90,21 +167,25 @@ pub fn create(application: &Application) -> widgets::WidgetRefs {
     let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
     // project view
     let project_view = gtk::Paned::new(gtk::Orientation::Vertical);
-    let files_view = gtk::Label::new(Some("Flow Files"));
+    let (files_view, flow_files) = palette::flow_files();
     project_view.add1(&files_view);
-    let libs_view = gtk::Label::new(Some("Flow Libs"));
+    let libs_view = palette::flow_libs();
     project_view.add2(&libs_view);
+    project_view.set_position(250);
     paned.add1(&project_view);
-    paned.set_position(100);
+    paned.set_position(200);
     // main window
-    let (main_window, args_buffer, flow_buffer, manifest_buffer, flow_notebook, stdout, stderr) = main_window();
+    let (main_window, args_buffer, stdin_file, flow_graph_view, flow_source_editor, manifest_buffer, flow_notebook,
+        output_notebook, stdout, stderr, (stdin, stdin_entry, send_stdin, send_eof, stdin_status),
+        (problems_view, problems, problems_label),
+        (compile_errors_view, compile_errors, compile_errors_label), build_console) = main_window();
     paned.add2(&main_window);
 
     // Stack the h_box with many of the main elements
     v_box.pack_start(&paned, true, true, 0);
 
     // Status bar at the bottom
-    let (status_message, status_bar) = status_bar();
+    let (status_message, compile_progress, cancel_compile, status_bar) = status_bar();
     v_box.pack_start(&status_bar, false, true, 4);
 
     app_window.add(&v_box);
@@ -113,14 +194,38 @@ pub fn create(application: &Application) -> widgets::WidgetRefs {
 
     widgets::WidgetRefs {
         app_window,
-        flow_buffer,
+        flow_graph_view,
+        flow_source_editor,
+        flow_files,
         manifest_buffer,
         flow_notebook,
+        output_notebook,
         args_buffer,
+        stdin_file,
         stdout,
         stderr,
+        stdin,
+        stdin_entry,
+        send_stdin,
+        send_eof,
+        stdin_status,
+        problems_view,
+        problems,
+        problems_label,
+        compile_errors_view,
+        compile_errors,
+        compile_errors_label,
+        build_console,
+        save_flow_menu,
+        save_flow_as_menu,
+        undo_menu,
+        redo_menu,
+        export_graph_menu,
         compile_flow_menu,
+        compile_options_menu,
         run_manifest_menu,
         status_message,
+        compile_progress,
+        cancel_compile,
     }
 }
\ No newline at end of file
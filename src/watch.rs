@@ -0,0 +1,88 @@
+//! Finding the files a loaded flow is made from, and which of them have changed since they were
+//! last checked, so the flow can be recompiled when they are.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use url::Url;
+
+use flowclib::model::flow::Flow;
+use flowclib::model::process::Process::{FlowProcess, FunctionProcess};
+
+use crate::implementations;
+
+/// What has changed in the files of a flow. Changes to definitions need the flow to be loaded
+/// again before it is compiled, changes to implementations only need it compiled.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Changed {
+    Implementations,
+    Definitions,
+}
+
+/// The files of a flow: the definitions of it, its sub-flows and functions, and the sources of
+/// the implementations supplied with its functions
+#[derive(Debug, Default)]
+pub struct Files {
+    definitions: BTreeSet<PathBuf>,
+    implementations: BTreeSet<PathBuf>,
+}
+
+impl Files {
+    pub fn of(flow: &Flow) -> Self {
+        let mut files = Files::default();
+        files.add(flow);
+        files
+    }
+
+    fn add(&mut self, flow: &Flow) {
+        self.definitions.extend(file_path(&flow.source_url));
+        for process in flow.subprocesses.values() {
+            match process {
+                FlowProcess(sub_flow) => self.add(sub_flow),
+                FunctionProcess(function) => {
+                    self.definitions.extend(file_path(function.get_source_url()));
+                    self.implementations.extend(implementations::sources(function));
+                }
+            }
+        }
+    }
+}
+
+// Only definitions loaded from files can be watched
+fn file_path(url: &str) -> Option<PathBuf> {
+    Url::parse(url).ok().and_then(|url| url.to_file_path().ok())
+}
+
+/// When the files of the flow loaded from 'root_url' were last modified, when last checked
+#[derive(Debug, Default)]
+pub struct Watched {
+    root_url: Option<String>,
+    modified: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watched {
+    /// Check the files of the flow loaded from 'root_url' for changes since they were last
+    /// checked. Files not checked before, including all those of a different flow, are not
+    /// changed: they are only remembered to check next time.
+    pub fn changed(&mut self, root_url: &str, files: &Files) -> Option<Changed> {
+        if self.root_url.as_deref() != Some(root_url) {
+            self.root_url = Some(root_url.to_string());
+            self.modified.clear();
+        }
+
+        let mut changed = None;
+        let files = files.definitions.iter().map(|path| (path, Changed::Definitions))
+            .chain(files.implementations.iter().map(|path| (path, Changed::Implementations)));
+        let mut modified = BTreeMap::new();
+        for (path, change) in files {
+            let time = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+            if matches!(self.modified.get(path), Some(last) if *last != time) && Some(change) > changed {
+                changed = Some(change);
+            }
+            modified.insert(path.clone(), time);
+        }
+        self.modified = modified;
+        changed
+    }
+}